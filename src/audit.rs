@@ -0,0 +1,528 @@
+use std::path::PathBuf;
+use std::sync::Mutex as StdMutex;
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use time::{format_description::well_known::Rfc3339, OffsetDateTime};
+use tokio::{fs::OpenOptions, io::AsyncWriteExt, sync::Mutex};
+
+use crate::auth::Operator;
+use crate::errors::{AppError, AppResult};
+use crate::tenant::Tenant;
+
+const GENESIS_HASH: &str = "0000000000000000000000000000000000000000000000000000000000000000";
+
+/// Declares `Action` from one list of variants and their serialized names,
+/// so `Action::ALL` and `as_str` always cover every variant.
+macro_rules! actions {
+    ($($variant:ident => $name:literal,)*) => {
+        #[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+        pub enum Action {
+            $(
+                #[serde(rename = $name)]
+                $variant,
+            )*
+        }
+
+        impl Action {
+            pub const ALL: &'static [Action] = &[$(Action::$variant,)*];
+
+            pub fn as_str(&self) -> &'static str {
+                match self {
+                    $(Action::$variant => $name,)*
+                }
+            }
+        }
+    };
+}
+
+actions! {
+    CreateUser => "user.create",
+    UpdateUser => "user.update",
+    DeleteUser => "user.delete",
+    BlockUser => "user.block",
+    UnblockUser => "user.unblock",
+    AssignUserRoles => "user.assign_roles",
+    RemoveUserRoles => "user.remove_roles",
+    UpdateUserMetadata => "user.update_metadata",
+    DeleteUserMfaMethod => "user.delete_mfa_method",
+    ResetUserMfa => "user.reset_mfa",
+    RegenerateUserRecoveryCode => "user.regenerate_recovery_code",
+    RevokeUserCredential => "user.revoke_credential",
+    RevokeUserCredentials => "user.revoke_all_credentials",
+    CreatePasswordChangeTicket => "user.create_password_ticket",
+    CreateEmailVerificationTicket => "user.create_verification_ticket",
+    SendVerificationEmail => "user.send_verification_email",
+    CreateConnection => "connection.create",
+    UpdateConnection => "connection.update",
+    CreateRole => "role.create",
+    UpdateRole => "role.update",
+    DeleteRole => "role.delete",
+    AddRolePermissions => "role.add_permissions",
+    RemoveRolePermissions => "role.remove_permissions",
+    CreateOrganization => "organization.create",
+    UpdateOrganization => "organization.update",
+    AddOrganizationConnection => "organization.add_connection",
+    RemoveOrganizationConnection => "organization.remove_connection",
+    AddOrganizationMembers => "organization.add_members",
+    RemoveOrganizationMembers => "organization.remove_members",
+    AssignOrganizationMemberRoles => "organization.assign_member_roles",
+    RemoveOrganizationMemberRoles => "organization.remove_member_roles",
+    CreateOrganizationInvitation => "organization.create_invitation",
+    DeleteOrganizationInvitation => "organization.delete_invitation",
+    CreateApplication => "application.create",
+    UpdateApplication => "application.update",
+    DeleteApplication => "application.delete",
+    RotateApplicationSecret => "application.rotate_secret",
+    CreateClientGrant => "application.create_grant",
+    UpdateClientGrant => "application.update_grant",
+    DeleteClientGrant => "application.delete_grant",
+    CreateApi => "api.create",
+    UpdateApi => "api.update",
+    DeleteApi => "api.delete",
+    AddApiScope => "api.add_scope",
+    RemoveApiScope => "api.remove_scope",
+}
+
+impl std::fmt::Display for Action {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct FieldChange {
+    pub field: String,
+    pub before: Option<String>,
+    pub after: Option<String>,
+}
+
+impl FieldChange {
+    pub fn new(field: &str, before: Option<&str>, after: Option<&str>) -> Self {
+        Self {
+            field: field.to_string(),
+            before: before.map(str::to_string),
+            after: after.map(str::to_string),
+        }
+    }
+
+    /// A change whose values must never be written anywhere, e.g. passwords.
+    pub fn redacted(field: &str) -> Self {
+        Self::new(field, None, Some("[redacted]"))
+    }
+}
+
+/// One line of the audit log. `hash` covers every other field plus
+/// `prev_hash`, so editing or removing a line breaks the chain after it.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct AuditEntry {
+    pub seq: u64,
+    pub timestamp: String,
+    pub operator: String,
+    pub operator_name: String,
+    pub tenant: String,
+    pub action: Action,
+    pub target: String,
+    pub changes: Vec<FieldChange>,
+    pub error: Option<String>,
+    pub prev_hash: String,
+    pub hash: String,
+}
+
+impl AuditEntry {
+    fn compute_hash(&self) -> String {
+        let mut unsigned = self.clone();
+        unsigned.hash = String::new();
+        let json = serde_json::to_vec(&unsigned).unwrap_or_default();
+        format!("{:x}", Sha256::digest(&json))
+    }
+}
+
+/// Result of walking the hash chain.
+#[derive(Debug, PartialEq, Eq)]
+pub enum ChainStatus {
+    Intact,
+    BrokenAt(u64),
+}
+
+struct ChainTail {
+    seq: u64,
+    hash: String,
+}
+
+/// Entries that could not be written since start-up, for the status page.
+#[derive(Clone, Default)]
+pub struct WriteFailures {
+    pub count: u64,
+    pub last_at: Option<String>,
+    pub last_error: Option<String>,
+}
+
+pub struct AuditLog {
+    path: PathBuf,
+    tail: Mutex<ChainTail>,
+    failures: StdMutex<WriteFailures>,
+}
+
+impl AuditLog {
+    pub async fn open(path: PathBuf) -> std::io::Result<Self> {
+        let log = read_entries(&path).await?;
+        let tail = match log.entries.last() {
+            Some(last) => ChainTail {
+                seq: last.seq,
+                hash: last.hash.clone(),
+            },
+            None => ChainTail {
+                seq: 0,
+                hash: GENESIS_HASH.to_string(),
+            },
+        };
+
+        Ok(Self {
+            path,
+            tail: Mutex::new(tail),
+            failures: StdMutex::new(WriteFailures::default()),
+        })
+    }
+
+    /// Appends an entry. The upstream change has already happened by the
+    /// time we get here and can't be undone, so a failed write is returned
+    /// for the handler to report instead of its usual response, and counted
+    /// on the status page.
+    pub async fn record(
+        &self,
+        operator: &Operator,
+        tenant: &Tenant,
+        action: Action,
+        target: &str,
+        changes: Vec<FieldChange>,
+        error: Option<String>,
+    ) -> AppResult<()> {
+        self.record_for(operator, &tenant.name, action, target, changes, error)
+            .await
+            .map_err(|e| {
+                AppError::Audit(format!(
+                    "{} on {} reached Auth0 but could not be written to the audit log: {}",
+                    action, target, e
+                ))
+            })
+    }
+
+    pub fn write_failures(&self) -> WriteFailures {
+        self.failures
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+    }
+
+    async fn record_for(
+        &self,
+        operator: &Operator,
+        tenant: &str,
+        action: Action,
+        target: &str,
+        changes: Vec<FieldChange>,
+        error: Option<String>,
+    ) -> std::io::Result<()> {
+        let mut tail = self.tail.lock().await;
+
+        let mut entry = AuditEntry {
+            seq: tail.seq + 1,
+            timestamp: OffsetDateTime::now_utc()
+                .format(&Rfc3339)
+                .unwrap_or_default(),
+            operator: operator.sub.clone(),
+            operator_name: operator.display_name().to_string(),
+            tenant: tenant.to_string(),
+            action,
+            target: target.to_string(),
+            changes,
+            error,
+            prev_hash: tail.hash.clone(),
+            hash: String::new(),
+        };
+        entry.hash = entry.compute_hash();
+
+        match self.append(&entry).await {
+            Ok(()) => {
+                tail.seq = entry.seq;
+                tail.hash = entry.hash;
+                Ok(())
+            }
+            Err(e) => {
+                tracing::error!(
+                    error = ?e,
+                    action = %action,
+                    %tenant,
+                    %target,
+                    "failed to write audit entry"
+                );
+                let mut failures = self.failures.lock().unwrap_or_else(|e| e.into_inner());
+                failures.count += 1;
+                failures.last_at = Some(entry.timestamp);
+                failures.last_error = Some(e.to_string());
+                Err(e)
+            }
+        }
+    }
+
+    async fn append(&self, entry: &AuditEntry) -> std::io::Result<()> {
+        let mut line = serde_json::to_vec(entry)?;
+        line.push(b'\n');
+
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .await?;
+        file.write_all(&line).await?;
+        file.sync_data().await
+    }
+
+    pub async fn entries(&self) -> std::io::Result<AuditEntries> {
+        let _tail = self.tail.lock().await;
+        read_entries(&self.path).await
+    }
+}
+
+/// The readable entries of the log, plus where the first unreadable line
+/// was, if any.
+pub struct AuditEntries {
+    pub entries: Vec<AuditEntry>,
+    /// The sequence number the first unparseable line would have had. A
+    /// line truncated by a crash or edited by hand lands here instead of
+    /// making the whole log unreadable.
+    pub unreadable_at: Option<u64>,
+}
+
+async fn read_entries(path: &PathBuf) -> std::io::Result<AuditEntries> {
+    let contents = match tokio::fs::read_to_string(path).await {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+        Err(e) => return Err(e),
+    };
+    Ok(parse_entries(&contents))
+}
+
+/// Parses every line that can be parsed. Entries after a bad line are kept
+/// so they stay visible, and new entries chain on from the last good one;
+/// `verify_chain` still reports the log as broken from the bad line on.
+fn parse_entries(contents: &str) -> AuditEntries {
+    let mut entries = Vec::new();
+    let mut unreadable_at = None;
+
+    for (index, line) in contents.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        match serde_json::from_str(line) {
+            Ok(entry) => entries.push(entry),
+            Err(e) => {
+                tracing::warn!(error = %e, line = index + 1, "unreadable audit log line");
+                unreadable_at.get_or_insert(entries.len() as u64 + 1);
+            }
+        }
+    }
+
+    AuditEntries {
+        entries,
+        unreadable_at,
+    }
+}
+
+pub fn verify_chain(log: &AuditEntries) -> ChainStatus {
+    let mut prev_hash = GENESIS_HASH;
+    for (index, entry) in log.entries.iter().enumerate() {
+        let expected_seq = index as u64 + 1;
+        if log.unreadable_at == Some(expected_seq)
+            || entry.seq != expected_seq
+            || entry.prev_hash != prev_hash
+            || entry.hash != entry.compute_hash()
+        {
+            return ChainStatus::BrokenAt(expected_seq);
+        }
+        prev_hash = &entry.hash;
+    }
+    match log.unreadable_at {
+        Some(seq) => ChainStatus::BrokenAt(seq),
+        None => ChainStatus::Intact,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use super::*;
+    use crate::auth::Role;
+    use crate::test_support::{operator, temp_path};
+
+    async fn write_entries(log: &AuditLog, targets: &[&str]) {
+        for target in targets {
+            log.record_for(
+                &operator(Role::Admin),
+                "prod",
+                Action::UpdateUser,
+                target,
+                vec![FieldChange::new("name", Some("Old"), Some("New"))],
+                None,
+            )
+            .await
+            .unwrap();
+        }
+    }
+
+    async fn chain_of(targets: &[&str]) -> Vec<AuditEntry> {
+        let log = AuditLog::open(temp_path("chain.jsonl")).await.unwrap();
+        write_entries(&log, targets).await;
+        log.entries().await.unwrap().entries
+    }
+
+    fn intact(entries: Vec<AuditEntry>) -> AuditEntries {
+        AuditEntries {
+            entries,
+            unreadable_at: None,
+        }
+    }
+
+    #[test]
+    fn action_names_match_serde_and_are_unique() {
+        let mut names = HashSet::new();
+        for action in Action::ALL {
+            assert_eq!(serde_json::to_value(action).unwrap(), action.as_str());
+            assert!(names.insert(action.as_str()), "{} listed twice", action);
+        }
+    }
+
+    #[tokio::test]
+    async fn hash_covers_every_field_but_itself() {
+        let entry = chain_of(&["hash"]).await.remove(0);
+        assert_eq!(entry.hash, entry.compute_hash());
+        assert_eq!(entry.hash.len(), 64);
+
+        let mut edited = entry.clone();
+        edited.hash = "ignored".to_string();
+        assert_eq!(edited.compute_hash(), entry.hash);
+
+        edited.changes[0].after = Some("Forged".to_string());
+        assert_ne!(edited.compute_hash(), entry.hash);
+    }
+
+    #[tokio::test]
+    async fn records_chain_from_genesis() {
+        let entries = chain_of(&["a", "b", "c"]).await;
+
+        assert_eq!(entries.len(), 3);
+        assert_eq!(entries[0].prev_hash, GENESIS_HASH);
+        for pair in entries.windows(2) {
+            assert_eq!(pair[1].seq, pair[0].seq + 1);
+            assert_eq!(pair[1].prev_hash, pair[0].hash);
+        }
+        assert_eq!(entries[2].tenant, "prod");
+        assert_eq!(verify_chain(&intact(entries)), ChainStatus::Intact);
+    }
+
+    #[tokio::test]
+    async fn reopening_continues_the_chain() {
+        let path = temp_path("reopen.jsonl");
+        write_entries(&AuditLog::open(path.clone()).await.unwrap(), &["a"]).await;
+
+        let log = AuditLog::open(path).await.unwrap();
+        write_entries(&log, &["b"]).await;
+
+        let log = log.entries().await.unwrap();
+        assert_eq!(log.entries[1].seq, 2);
+        assert_eq!(verify_chain(&log), ChainStatus::Intact);
+    }
+
+    #[tokio::test]
+    async fn detects_edited_removed_and_reordered_entries() {
+        let entries = chain_of(&["d", "e", "f"]).await;
+
+        let mut edited = entries.clone();
+        edited[1].target = "someone-else".to_string();
+        assert_eq!(verify_chain(&intact(edited)), ChainStatus::BrokenAt(2));
+
+        let mut rehashed = entries.clone();
+        rehashed[1].target = "someone-else".to_string();
+        rehashed[1].hash = rehashed[1].compute_hash();
+        assert_eq!(verify_chain(&intact(rehashed)), ChainStatus::BrokenAt(3));
+
+        let mut removed = entries.clone();
+        removed.remove(1);
+        assert_eq!(verify_chain(&intact(removed)), ChainStatus::BrokenAt(2));
+
+        let mut reordered = entries.clone();
+        reordered.swap(0, 1);
+        assert_eq!(verify_chain(&intact(reordered)), ChainStatus::BrokenAt(1));
+
+        let mut truncated = entries;
+        truncated.remove(0);
+        assert_eq!(verify_chain(&intact(truncated)), ChainStatus::BrokenAt(1));
+    }
+
+    #[tokio::test]
+    async fn bad_line_breaks_the_chain_without_failing_to_open() {
+        let path = temp_path("bad-line.jsonl");
+        let log = AuditLog::open(path.clone()).await.unwrap();
+        write_entries(&log, &["g", "h"]).await;
+
+        // A write cut short by a crash.
+        let mut contents = std::fs::read_to_string(&path).unwrap();
+        contents.push_str("{\"seq\":3,\"timest\n");
+        std::fs::write(&path, contents).unwrap();
+
+        let log = AuditLog::open(path).await.unwrap();
+        let read = log.entries().await.unwrap();
+        assert_eq!(read.entries.len(), 2);
+        assert_eq!(read.unreadable_at, Some(3));
+        assert_eq!(verify_chain(&read), ChainStatus::BrokenAt(3));
+
+        // New entries still land in the log and stay visible.
+        write_entries(&log, &["i"]).await;
+        let read = log.entries().await.unwrap();
+        assert_eq!(read.entries.len(), 3);
+        assert_eq!(read.entries[2].target, "i");
+        assert_eq!(verify_chain(&read), ChainStatus::BrokenAt(3));
+    }
+
+    #[test]
+    fn bad_line_in_the_middle_is_reported_where_it_was() {
+        let log = parse_entries("not json\n\n");
+        assert!(log.entries.is_empty());
+        assert_eq!(verify_chain(&log), ChainStatus::BrokenAt(1));
+
+        assert_eq!(verify_chain(&parse_entries("")), ChainStatus::Intact);
+    }
+
+    #[tokio::test]
+    async fn failed_writes_are_returned_and_counted() {
+        let path = temp_path("unwritable");
+        let log = AuditLog::open(path.clone()).await.unwrap();
+        // A directory where the file should be makes every append fail.
+        std::fs::create_dir(&path).unwrap();
+
+        let result = log
+            .record_for(
+                &operator(Role::Admin),
+                "prod",
+                Action::DeleteUser,
+                "auth0|1",
+                vec![],
+                None,
+            )
+            .await;
+        assert!(result.is_err());
+
+        let failures = log.write_failures();
+        assert_eq!(failures.count, 1);
+        assert!(failures.last_at.is_some() && failures.last_error.is_some());
+
+        // The chain did not advance past the lost entry.
+        std::fs::remove_dir(&path).unwrap();
+        write_entries(&log, &["j"]).await;
+        let read = log.entries().await.unwrap();
+        assert_eq!(read.entries[0].seq, 1);
+        assert_eq!(verify_chain(&read), ChainStatus::Intact);
+    }
+}
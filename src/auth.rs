@@ -0,0 +1,944 @@
+use std::collections::HashMap;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use axum::{
+    extract::{FromRequestParts, Request, State},
+    http::{header, request::Parts, HeaderValue, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Redirect, Response},
+};
+use axum_extra::extract::cookie::{Cookie, Key, SameSite, SignedCookieJar};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use openidconnect::{
+    core::{CoreAuthenticationFlow, CoreClient, CoreProviderMetadata},
+    reqwest,
+    url::Url,
+    AuthorizationCode, ClientId, ClientSecret, CsrfToken, EndpointMaybeSet, EndpointNotSet,
+    EndpointSet, IssuerUrl, Nonce, PkceCodeChallenge, PkceCodeVerifier, RedirectUrl, Scope,
+    TokenResponse,
+};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::config::Config;
+use crate::errors::AppError;
+use crate::helpers::is_htmx_request;
+use crate::state::AppState;
+
+pub const SESSION_COOKIE: &str = "session";
+pub const LOGIN_FLOW_COOKIE: &str = "login_flow";
+
+pub type OidcClient = CoreClient<
+    EndpointSet,
+    EndpointNotSet,
+    EndpointNotSet,
+    EndpointNotSet,
+    EndpointMaybeSet,
+    EndpointMaybeSet,
+>;
+
+#[derive(Debug, Error)]
+pub enum AuthError {
+    #[error("invalid OIDC configuration: {0}")]
+    Config(String),
+    #[error("OIDC discovery failed: {0}")]
+    Discovery(String),
+    #[error("login flow missing or expired")]
+    MissingFlow,
+    #[error("state parameter mismatch")]
+    StateMismatch,
+    #[error("issuer returned an error: {0}")]
+    Issuer(String),
+    #[error("token exchange failed: {0}")]
+    Exchange(String),
+    #[error("ID token rejected: {0}")]
+    IdToken(String),
+    #[error("operator {0} has no role")]
+    NoRole(String),
+}
+
+impl IntoResponse for AuthError {
+    fn into_response(self) -> Response {
+        tracing::warn!(error = %self, "operator login failed");
+        (StatusCode::UNAUTHORIZED, "Login failed").into_response()
+    }
+}
+
+pub struct Auth {
+    pub oidc: OidcClient,
+    pub http: reqwest::Client,
+    pub key: Key,
+    pub session_ttl: Duration,
+    pub secure_cookies: bool,
+    pub role_claim: Option<String>,
+    pub roles: OperatorRoles,
+}
+
+impl Auth {
+    pub async fn discover(config: &Config) -> Result<Self, AuthError> {
+        let http = reqwest::ClientBuilder::new()
+            .redirect(reqwest::redirect::Policy::none())
+            .build()
+            .map_err(|e| AuthError::Config(e.to_string()))?;
+
+        let issuer = IssuerUrl::new(config.oidc_issuer.clone())
+            .map_err(|e| AuthError::Config(e.to_string()))?;
+        let redirect_url = RedirectUrl::new(config.oidc_redirect_url.clone())
+            .map_err(|e| AuthError::Config(e.to_string()))?;
+
+        let metadata = CoreProviderMetadata::discover_async(issuer, &http)
+            .await
+            .map_err(|e| AuthError::Discovery(e.to_string()))?;
+
+        let oidc = CoreClient::from_provider_metadata(
+            metadata,
+            ClientId::new(config.oidc_client_id.clone()),
+            Some(ClientSecret::new(config.oidc_client_secret.clone())),
+        )
+        .set_redirect_uri(redirect_url);
+
+        let roles = match &config.operators_file {
+            Some(path) => {
+                let contents = std::fs::read_to_string(path)
+                    .map_err(|e| AuthError::Config(format!("{}: {}", path.display(), e)))?;
+                serde_json::from_str(&contents)
+                    .map_err(|e| AuthError::Config(format!("{}: {}", path.display(), e)))?
+            }
+            None => OperatorRoles::default(),
+        };
+
+        Ok(Self {
+            oidc,
+            http,
+            key: Key::from(config.session_secret.as_bytes()),
+            session_ttl: config.session_ttl,
+            secure_cookies: config.oidc_redirect_url.starts_with("https://"),
+            role_claim: config.oidc_role_claim.clone(),
+            roles,
+        })
+    }
+
+    /// Builds the issuer's authorization URL and the flow to keep until the
+    /// callback.
+    pub fn start_login(&self, next: Option<&str>) -> (Url, LoginFlow) {
+        let (pkce_challenge, pkce_verifier) = PkceCodeChallenge::new_random_sha256();
+
+        let (auth_url, csrf_state, nonce) = self
+            .oidc
+            .authorize_url(
+                CoreAuthenticationFlow::AuthorizationCode,
+                CsrfToken::new_random,
+                Nonce::new_random,
+            )
+            .add_scope(Scope::new("email".to_string()))
+            .add_scope(Scope::new("profile".to_string()))
+            .set_pkce_challenge(pkce_challenge)
+            .url();
+
+        let flow = LoginFlow {
+            csrf_state: csrf_state.secret().clone(),
+            nonce: nonce.secret().clone(),
+            pkce_verifier: pkce_verifier.secret().clone(),
+            next: safe_next(next),
+        };
+        (auth_url, flow)
+    }
+
+    /// Exchanges the authorization code using the flow's PKCE verifier,
+    /// verifies the ID token against its nonce and resolves the operator.
+    pub async fn complete_login(
+        &self,
+        flow: &LoginFlow,
+        code: String,
+    ) -> Result<Operator, AuthError> {
+        let token_response = self
+            .oidc
+            .exchange_code(AuthorizationCode::new(code))
+            .map_err(|e| AuthError::Config(e.to_string()))?
+            .set_pkce_verifier(PkceCodeVerifier::new(flow.pkce_verifier.clone()))
+            .request_async(&self.http)
+            .await
+            .map_err(|e| AuthError::Exchange(e.to_string()))?;
+
+        let id_token = token_response
+            .id_token()
+            .ok_or_else(|| AuthError::IdToken("issuer did not return an ID token".to_string()))?;
+        let claims = id_token
+            .claims(
+                &self.oidc.id_token_verifier(),
+                &Nonce::new(flow.nonce.clone()),
+            )
+            .map_err(|e| AuthError::IdToken(e.to_string()))?;
+
+        let sub = claims.subject().to_string();
+        let email = claims.email().map(|email| email.to_string());
+        let role = self.resolve_role(
+            &id_token.to_string(),
+            &sub,
+            email.as_deref(),
+            claims.email_verified(),
+        )?;
+
+        Ok(Operator {
+            sub,
+            name: claims
+                .name()
+                .and_then(|name| name.get(None))
+                .map(|name| name.to_string()),
+            email,
+            role,
+            expires_at: unix_now() + self.session_ttl.as_secs(),
+        })
+    }
+
+    /// Resolves an operator's role from the configured ID-token claim, then
+    /// the operators file (keyed by verified email or subject), then the
+    /// default role.
+    pub fn resolve_role(
+        &self,
+        raw_id_token: &str,
+        sub: &str,
+        email: Option<&str>,
+        email_verified: Option<bool>,
+    ) -> Result<Role, AuthError> {
+        let from_claim = self
+            .role_claim
+            .as_deref()
+            .and_then(|claim| role_from_claim(raw_id_token, claim));
+
+        // An unverified email can be set to anything at sign-up, so only a
+        // verified one may pick up an operator's role.
+        let from_file = email
+            .filter(|_| email_verified == Some(true))
+            .and_then(|email| self.roles.operators.get(email))
+            .or_else(|| self.roles.operators.get(sub))
+            .copied();
+
+        from_claim
+            .or(from_file)
+            .or(self.roles.default_role)
+            .ok_or_else(|| AuthError::NoRole(sub.to_string()))
+    }
+
+    pub fn cookie(&self, name: &'static str, value: String, max_age: Duration) -> Cookie<'static> {
+        Cookie::build((name, value))
+            .path("/")
+            .http_only(true)
+            .secure(self.secure_cookies)
+            .same_site(SameSite::Lax)
+            .max_age(max_age.try_into().unwrap_or_default())
+            .build()
+    }
+
+    pub fn removal_cookie(&self, name: &'static str) -> Cookie<'static> {
+        Cookie::build(name).path("/").build()
+    }
+}
+
+/// Operator permission levels, ordered from least to most privileged.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    Viewer,
+    Support,
+    Admin,
+}
+
+impl Role {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.to_ascii_lowercase().as_str() {
+            "viewer" => Some(Role::Viewer),
+            "support" => Some(Role::Support),
+            "admin" => Some(Role::Admin),
+            _ => None,
+        }
+    }
+}
+
+impl std::fmt::Display for Role {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Role::Viewer => write!(f, "viewer"),
+            Role::Support => write!(f, "support"),
+            Role::Admin => write!(f, "admin"),
+        }
+    }
+}
+
+/// Contents of `OPERATORS_FILE`: explicit roles keyed by email or subject.
+/// Anyone not listed is refused unless the file sets `default_role`.
+#[derive(Default, Deserialize)]
+pub struct OperatorRoles {
+    #[serde(default)]
+    pub default_role: Option<Role>,
+    #[serde(default)]
+    pub operators: HashMap<String, Role>,
+}
+
+/// Reads a role claim from an already-verified ID token. The claim may be a
+/// single role name or an array of them; the most privileged one wins.
+fn role_from_claim(raw_id_token: &str, claim: &str) -> Option<Role> {
+    let payload = raw_id_token.split('.').nth(1)?;
+    let payload = URL_SAFE_NO_PAD.decode(payload).ok()?;
+    let claims: serde_json::Value = serde_json::from_slice(&payload).ok()?;
+
+    match claims.get(claim)? {
+        serde_json::Value::String(role) => Role::parse(role),
+        serde_json::Value::Array(roles) => roles
+            .iter()
+            .filter_map(|role| role.as_str().and_then(Role::parse))
+            .max(),
+        _ => None,
+    }
+}
+
+/// The signed-in operator, stored as JSON in the signed session cookie.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Operator {
+    pub sub: String,
+    pub name: Option<String>,
+    pub email: Option<String>,
+    pub role: Role,
+    pub expires_at: u64,
+}
+
+impl Operator {
+    pub fn display_name(&self) -> &str {
+        self.name
+            .as_deref()
+            .or(self.email.as_deref())
+            .unwrap_or(self.sub.as_str())
+    }
+
+    pub fn is_expired(&self) -> bool {
+        self.expires_at <= unix_now()
+    }
+
+    pub fn require(&self, role: Role) -> Result<(), AppError> {
+        if self.role >= role {
+            Ok(())
+        } else {
+            tracing::warn!(operator = %self.sub, role = %self.role, required = %role, "permission denied");
+            Err(AppError::Forbidden)
+        }
+    }
+
+    /// Whether the operator may create and edit users.
+    pub fn can_edit(&self) -> bool {
+        self.role >= Role::Support
+    }
+
+    /// Whether the operator may set passwords, block and delete.
+    pub fn can_admin(&self) -> bool {
+        self.role >= Role::Admin
+    }
+}
+
+impl<S: Send + Sync> FromRequestParts<S> for Operator {
+    type Rejection = AppError;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        parts
+            .extensions
+            .get::<Operator>()
+            .cloned()
+            .ok_or(AppError::Unauthorized)
+    }
+}
+
+macro_rules! role_extractor {
+    ($(#[$meta:meta])* $name:ident, $role:expr) => {
+        $(#[$meta])*
+        pub struct $name(pub Operator);
+
+        impl<S: Send + Sync> FromRequestParts<S> for $name {
+            type Rejection = AppError;
+
+            async fn from_request_parts(
+                parts: &mut Parts,
+                state: &S,
+            ) -> Result<Self, Self::Rejection> {
+                let operator = Operator::from_request_parts(parts, state).await?;
+                operator.require($role)?;
+                Ok(Self(operator))
+            }
+        }
+    };
+}
+
+role_extractor!(
+    /// Read-only access to users, logs, connections and applications.
+    RequireViewer,
+    Role::Viewer
+);
+role_extractor!(
+    /// Support actions such as creating users and editing profiles.
+    RequireSupport,
+    Role::Support
+);
+role_extractor!(
+    /// Destructive actions: passwords, blocking and deletion.
+    RequireAdmin,
+    Role::Admin
+);
+
+/// PKCE verifier, nonce and CSRF state carried between `/auth/login` and
+/// `/auth/callback` in an encrypted cookie.
+#[derive(Serialize, Deserialize)]
+pub struct LoginFlow {
+    pub csrf_state: String,
+    pub nonce: String,
+    pub pkce_verifier: String,
+    pub next: String,
+}
+
+impl LoginFlow {
+    /// Checks the issuer's callback against this flow and returns the
+    /// authorization code to exchange.
+    pub fn authorization_code(&self, query: CallbackQuery) -> Result<String, AuthError> {
+        if let Some(error) = query.error {
+            let description = query.error_description.unwrap_or_default();
+            return Err(AuthError::Issuer(format!("{} {}", error, description)));
+        }
+
+        if query.state.as_deref() != Some(self.csrf_state.as_str()) {
+            return Err(AuthError::StateMismatch);
+        }
+        query.code.ok_or(AuthError::MissingFlow)
+    }
+}
+
+/// Query parameters the issuer sends back to `/auth/callback`.
+#[derive(Deserialize)]
+pub struct CallbackQuery {
+    pub code: Option<String>,
+    pub state: Option<String>,
+    pub error: Option<String>,
+    pub error_description: Option<String>,
+}
+
+pub fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+/// Only allow redirects back into the console after login: a path under
+/// `/t/` that resolves against our own origin. Backslashes (raw or
+/// percent-encoded) and control characters are refused outright because
+/// browsers treat `\` like `/` and would read `/\host` as another host.
+pub fn safe_next(next: Option<&str>) -> String {
+    let origin = Url::parse("http://console.invalid/").expect("static URL");
+    next.filter(|path| {
+        path.starts_with("/t/")
+            && !path.contains('\\')
+            && !path.to_ascii_lowercase().contains("%5c")
+            && !path.chars().any(char::is_control)
+    })
+    .and_then(|path| origin.join(path).ok())
+    .filter(|url| url.origin() == origin.origin() && url.path().starts_with("/t/"))
+    .map(|url| url[url::Position::BeforePath..].to_string())
+    .unwrap_or_else(|| "/".to_string())
+}
+
+pub fn current_operator(jar: &SignedCookieJar) -> Option<Operator> {
+    let cookie = jar.get(SESSION_COOKIE)?;
+    let operator: Operator = serde_json::from_str(cookie.value()).ok()?;
+    (!operator.is_expired()).then_some(operator)
+}
+
+pub async fn require_operator(
+    State(state): State<AppState>,
+    mut request: Request,
+    next: Next,
+) -> Response {
+    let jar = SignedCookieJar::from_headers(request.headers(), state.auth.key.clone());
+
+    if let Some(operator) = current_operator(&jar) {
+        request.extensions_mut().insert(operator);
+        return next.run(request).await;
+    }
+
+    let path = request
+        .uri()
+        .path_and_query()
+        .map(|p| p.as_str())
+        .unwrap_or("/");
+    let login_url = format!(
+        "/auth/login?next={}",
+        url::form_urlencoded::byte_serialize(path.as_bytes()).collect::<String>()
+    );
+
+    if is_htmx_request(request.headers()) {
+        let mut response = StatusCode::UNAUTHORIZED.into_response();
+        if let Ok(value) = HeaderValue::from_str(&login_url) {
+            response.headers_mut().insert("hx-redirect", value);
+        }
+        response
+    } else {
+        let mut response = Redirect::to(&login_url).into_response();
+        response
+            .headers_mut()
+            .insert(header::CACHE_CONTROL, HeaderValue::from_static("no-store"));
+        response
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use axum::{extract::State, routing::get, routing::post, Form, Json, Router};
+    use openidconnect::{
+        core::{
+            CoreGenderClaim, CoreJsonWebKeySet, CoreJweContentEncryptionAlgorithm,
+            CoreJwsSigningAlgorithm, CoreRsaPrivateSigningKey,
+        },
+        AdditionalClaims, Audience, EndUserEmail, IdToken, IdTokenClaims, JsonWebKeyId,
+        PrivateSigningKey, StandardClaims, SubjectIdentifier,
+    };
+    use serde_json::json;
+    use sha2::{Digest, Sha256};
+
+    use super::*;
+
+    const CLIENT_ID: &str = "console";
+    const SIGNING_KEY: &str = include_str!("../tests/fixtures/stub-issuer-key.pem");
+
+    #[derive(Clone, Debug, Serialize, Deserialize)]
+    struct ExtraClaims {
+        #[serde(flatten)]
+        claims: serde_json::Map<String, serde_json::Value>,
+    }
+
+    impl AdditionalClaims for ExtraClaims {}
+
+    type StubIdToken = IdToken<
+        ExtraClaims,
+        CoreGenderClaim,
+        CoreJweContentEncryptionAlgorithm,
+        CoreJwsSigningAlgorithm,
+    >;
+
+    /// Who the stub issuer signs in, and any extra ID-token claims.
+    #[derive(Clone)]
+    struct Identity {
+        sub: &'static str,
+        email: Option<&'static str>,
+        email_verified: Option<bool>,
+        extra: serde_json::Value,
+    }
+
+    impl Identity {
+        fn new(sub: &'static str) -> Self {
+            Self {
+                sub,
+                email: None,
+                email_verified: None,
+                extra: json!({}),
+            }
+        }
+
+        fn email(mut self, email: &'static str, verified: Option<bool>) -> Self {
+            self.email = Some(email);
+            self.email_verified = verified;
+            self
+        }
+    }
+
+    /// An authorization code the stub issuer has handed out.
+    struct Grant {
+        code_challenge: String,
+        nonce: String,
+        identity: Identity,
+    }
+
+    /// A minimal OIDC issuer: discovery, JWKS and a token endpoint that
+    /// checks the PKCE verifier and signs an RS256 ID token.
+    #[derive(Clone)]
+    struct StubIssuer {
+        issuer: String,
+        key: Arc<CoreRsaPrivateSigningKey>,
+        grants: Arc<Mutex<HashMap<String, Grant>>>,
+    }
+
+    impl StubIssuer {
+        async fn start() -> Self {
+            let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let stub = Self {
+                issuer: format!("http://{}", listener.local_addr().unwrap()),
+                key: Arc::new(
+                    CoreRsaPrivateSigningKey::from_pem(
+                        SIGNING_KEY,
+                        Some(JsonWebKeyId::new("stub".to_string())),
+                    )
+                    .unwrap(),
+                ),
+                grants: Arc::default(),
+            };
+
+            let app = Router::new()
+                .route("/.well-known/openid-configuration", get(discovery))
+                .route("/jwks", get(jwks))
+                .route("/oauth/token", post(token))
+                .with_state(stub.clone());
+            tokio::spawn(async move { axum::serve(listener, app).await });
+            stub
+        }
+
+        fn config(&self) -> Config {
+            Config {
+                oidc_issuer: self.issuer.clone(),
+                oidc_client_id: CLIENT_ID.to_string(),
+                ..crate::test_support::config()
+            }
+        }
+
+        /// Plays the issuer's side of `/authorize`: remembers the PKCE
+        /// challenge and nonce from the login URL against a new code.
+        fn authorize(&self, auth_url: &Url, identity: Identity) -> CallbackQuery {
+            let param = |name: &str| {
+                auth_url
+                    .query_pairs()
+                    .find(|(key, _)| key == name)
+                    .map(|(_, value)| value.into_owned())
+                    .unwrap()
+            };
+            assert_eq!(param("code_challenge_method"), "S256");
+
+            let mut grants = self.grants.lock().unwrap();
+            let code = format!("code-{}", grants.len());
+            grants.insert(
+                code.clone(),
+                Grant {
+                    code_challenge: param("code_challenge"),
+                    nonce: param("nonce"),
+                    identity,
+                },
+            );
+
+            CallbackQuery {
+                code: Some(code),
+                state: Some(param("state")),
+                error: None,
+                error_description: None,
+            }
+        }
+    }
+
+    async fn discovery(State(stub): State<StubIssuer>) -> Json<serde_json::Value> {
+        Json(json!({
+            "issuer": stub.issuer,
+            "authorization_endpoint": format!("{}/authorize", stub.issuer),
+            "token_endpoint": format!("{}/oauth/token", stub.issuer),
+            "jwks_uri": format!("{}/jwks", stub.issuer),
+            "response_types_supported": ["code"],
+            "subject_types_supported": ["public"],
+            "id_token_signing_alg_values_supported": ["RS256"],
+        }))
+    }
+
+    async fn jwks(State(stub): State<StubIssuer>) -> Json<CoreJsonWebKeySet> {
+        Json(CoreJsonWebKeySet::new(vec![stub.key.as_verification_key()]))
+    }
+
+    async fn token(
+        State(stub): State<StubIssuer>,
+        Form(form): Form<HashMap<String, String>>,
+    ) -> Response {
+        let invalid_grant = || {
+            (
+                StatusCode::BAD_REQUEST,
+                Json(json!({ "error": "invalid_grant" })),
+            )
+                .into_response()
+        };
+
+        let Some(grant) = form
+            .get("code")
+            .and_then(|code| stub.grants.lock().unwrap().remove(code))
+        else {
+            return invalid_grant();
+        };
+        let verifier = form.get("code_verifier").map(String::as_str).unwrap_or("");
+        if URL_SAFE_NO_PAD.encode(Sha256::digest(verifier)) != grant.code_challenge {
+            return invalid_grant();
+        }
+
+        let identity = grant.identity;
+        let now = chrono::Utc::now();
+        let claims = IdTokenClaims::new(
+            IssuerUrl::new(stub.issuer.clone()).unwrap(),
+            vec![Audience::new(CLIENT_ID.to_string())],
+            now + chrono::Duration::minutes(5),
+            now,
+            StandardClaims::new(SubjectIdentifier::new(identity.sub.to_string()))
+                .set_email(identity.email.map(|e| EndUserEmail::new(e.to_string())))
+                .set_email_verified(identity.email_verified),
+            ExtraClaims {
+                claims: identity.extra.as_object().cloned().unwrap_or_default(),
+            },
+        )
+        .set_nonce(Some(Nonce::new(grant.nonce)));
+        let id_token = StubIdToken::new(
+            claims,
+            stub.key.as_ref(),
+            CoreJwsSigningAlgorithm::RsaSsaPkcs1V15Sha256,
+            None,
+            None,
+        )
+        .unwrap();
+
+        Json(json!({
+            "access_token": "stub-access-token",
+            "token_type": "Bearer",
+            "expires_in": 300,
+            "id_token": id_token.to_string(),
+        }))
+        .into_response()
+    }
+
+    async fn discover(stub: &StubIssuer, roles: serde_json::Value) -> Auth {
+        let mut auth = Auth::discover(&stub.config()).await.unwrap();
+        auth.roles = serde_json::from_value(roles).unwrap();
+        auth
+    }
+
+    async fn sign_in(
+        auth: &Auth,
+        stub: &StubIssuer,
+        identity: Identity,
+    ) -> Result<Role, AuthError> {
+        let (auth_url, flow) = auth.start_login(None);
+        let query = stub.authorize(&auth_url, identity);
+        let code = flow.authorization_code(query)?;
+        auth.complete_login(&flow, code)
+            .await
+            .map(|operator| operator.role)
+    }
+
+    fn flow() -> LoginFlow {
+        LoginFlow {
+            csrf_state: "state".to_string(),
+            nonce: "nonce".to_string(),
+            pkce_verifier: "verifier".to_string(),
+            next: "/".to_string(),
+        }
+    }
+
+    fn callback(code: Option<&str>, state: Option<&str>) -> CallbackQuery {
+        CallbackQuery {
+            code: code.map(str::to_string),
+            state: state.map(str::to_string),
+            error: None,
+            error_description: None,
+        }
+    }
+
+    #[test]
+    fn callback_requires_matching_state() {
+        let flow = flow();
+
+        let code = flow.authorization_code(callback(Some("code"), Some("state")));
+        assert_eq!(code.unwrap(), "code");
+
+        let mismatch = flow.authorization_code(callback(Some("code"), Some("forged")));
+        assert!(matches!(mismatch, Err(AuthError::StateMismatch)));
+
+        let missing = flow.authorization_code(callback(Some("code"), None));
+        assert!(matches!(missing, Err(AuthError::StateMismatch)));
+
+        let no_code = flow.authorization_code(callback(None, Some("state")));
+        assert!(matches!(no_code, Err(AuthError::MissingFlow)));
+    }
+
+    #[test]
+    fn callback_reports_issuer_errors() {
+        let query = CallbackQuery {
+            error: Some("access_denied".to_string()),
+            error_description: Some("user cancelled".to_string()),
+            ..callback(None, Some("state"))
+        };
+        let result = flow().authorization_code(query);
+        assert!(matches!(result, Err(AuthError::Issuer(e)) if e == "access_denied user cancelled"));
+    }
+
+    async fn extract<T>(operator: Option<Operator>) -> Result<T, AppError>
+    where
+        T: FromRequestParts<(), Rejection = AppError>,
+    {
+        let (mut parts, ()) = axum::http::Request::new(()).into_parts();
+        if let Some(operator) = operator {
+            parts.extensions.insert(operator);
+        }
+        T::from_request_parts(&mut parts, &()).await
+    }
+
+    #[tokio::test]
+    async fn role_extractors_enforce_each_boundary() {
+        // (role, viewer, support, admin)
+        let cases = [
+            (Role::Viewer, true, false, false),
+            (Role::Support, true, true, false),
+            (Role::Admin, true, true, true),
+        ];
+        for (role, viewer, support, admin) in cases {
+            let operator = || Some(crate::test_support::operator(role));
+            let allowed = |result: Result<_, AppError>| match result {
+                Ok(_) => true,
+                Err(AppError::Forbidden) => false,
+                Err(e) => panic!("{} got {:?}", role, e),
+            };
+            assert_eq!(
+                allowed(extract::<RequireViewer>(operator()).await.map(drop)),
+                viewer
+            );
+            assert_eq!(
+                allowed(extract::<RequireSupport>(operator()).await.map(drop)),
+                support
+            );
+            assert_eq!(
+                allowed(extract::<RequireAdmin>(operator()).await.map(drop)),
+                admin
+            );
+        }
+
+        let anonymous = extract::<RequireViewer>(None).await;
+        assert!(matches!(anonymous, Err(AppError::Unauthorized)));
+    }
+
+    #[test]
+    fn next_only_allows_local_paths() {
+        assert_eq!(safe_next(Some("/t/prod/users")), "/t/prod/users");
+        assert_eq!(
+            safe_next(Some("/t/prod/users?q=email%3Aa&page=2")),
+            "/t/prod/users?q=email%3Aa&page=2"
+        );
+        assert_eq!(safe_next(Some("//evil.example")), "/");
+        assert_eq!(safe_next(Some("https://evil.example")), "/");
+        assert_eq!(safe_next(Some("/\\evil.example")), "/");
+        assert_eq!(safe_next(Some("/%5Cevil.example")), "/");
+        assert_eq!(safe_next(Some("/t/prod/%5c%5cevil.example")), "/");
+        assert_eq!(safe_next(Some("/t/prod\n/users")), "/");
+        assert_eq!(safe_next(Some("/t/../evil")), "/");
+        assert_eq!(safe_next(Some("/status")), "/");
+        assert_eq!(safe_next(Some("t/prod/users")), "/");
+        assert_eq!(safe_next(None), "/");
+    }
+
+    #[tokio::test]
+    async fn login_round_trip_against_stub_issuer() {
+        let stub = StubIssuer::start().await;
+        let auth = discover(&stub, json!({ "operators": { "ops|1": "support" } })).await;
+
+        let role = sign_in(&auth, &stub, Identity::new("ops|1")).await;
+        assert_eq!(role.unwrap(), Role::Support);
+    }
+
+    #[tokio::test]
+    async fn login_rejects_wrong_pkce_verifier() {
+        let stub = StubIssuer::start().await;
+        let auth = discover(&stub, json!({})).await;
+
+        let (auth_url, mut flow) = auth.start_login(None);
+        let code = flow
+            .authorization_code(stub.authorize(&auth_url, Identity::new("ops|1")))
+            .unwrap();
+        flow.pkce_verifier = "a-different-verifier-that-is-long-enough-for-pkce".to_string();
+
+        let result = auth.complete_login(&flow, code).await;
+        assert!(matches!(result, Err(AuthError::Exchange(_))));
+    }
+
+    #[tokio::test]
+    async fn login_rejects_wrong_nonce() {
+        let stub = StubIssuer::start().await;
+        let auth = discover(&stub, json!({})).await;
+
+        let (auth_url, mut flow) = auth.start_login(None);
+        let code = flow
+            .authorization_code(stub.authorize(&auth_url, Identity::new("ops|1")))
+            .unwrap();
+        flow.nonce = "replayed".to_string();
+
+        let result = auth.complete_login(&flow, code).await;
+        assert!(matches!(result, Err(AuthError::IdToken(_))));
+    }
+
+    #[tokio::test]
+    async fn verified_email_matches_operators_file() {
+        let stub = StubIssuer::start().await;
+        let auth = discover(
+            &stub,
+            json!({ "operators": { "ada@example.com": "admin" } }),
+        )
+        .await;
+
+        let identity = Identity::new("ops|1").email("ada@example.com", Some(true));
+        assert_eq!(sign_in(&auth, &stub, identity).await.unwrap(), Role::Admin);
+    }
+
+    #[tokio::test]
+    async fn unverified_email_falls_back_to_subject() {
+        let stub = StubIssuer::start().await;
+        let auth = discover(
+            &stub,
+            json!({ "operators": { "ada@example.com": "admin", "ops|2": "support" } }),
+        )
+        .await;
+
+        let unverified = Identity::new("ops|2").email("ada@example.com", Some(false));
+        assert_eq!(
+            sign_in(&auth, &stub, unverified).await.unwrap(),
+            Role::Support
+        );
+
+        let unknown = Identity::new("ops|2").email("ada@example.com", None);
+        assert_eq!(sign_in(&auth, &stub, unknown).await.unwrap(), Role::Support);
+
+        let stranger = Identity::new("ops|3").email("ada@example.com", Some(false));
+        let result = sign_in(&auth, &stub, stranger).await;
+        assert!(matches!(result, Err(AuthError::NoRole(sub)) if sub == "ops|3"));
+    }
+
+    #[tokio::test]
+    async fn unlisted_identity_is_refused_without_a_default_role() {
+        let stub = StubIssuer::start().await;
+        let auth = discover(&stub, json!({ "operators": { "ops|1": "admin" } })).await;
+
+        let stranger = Identity::new("ops|9").email("eve@example.com", Some(true));
+        let result = sign_in(&auth, &stub, stranger).await;
+        assert!(matches!(result, Err(AuthError::NoRole(sub)) if sub == "ops|9"));
+
+        let nobody_configured = discover(&stub, json!({})).await;
+        let result = sign_in(&nobody_configured, &stub, Identity::new("ops|1")).await;
+        assert!(matches!(result, Err(AuthError::NoRole(_))));
+    }
+
+    #[tokio::test]
+    async fn explicit_default_role_admits_unlisted_identities() {
+        let stub = StubIssuer::start().await;
+        let auth = discover(&stub, json!({ "default_role": "viewer" })).await;
+
+        let stranger = Identity::new("ops|9");
+        assert_eq!(sign_in(&auth, &stub, stranger).await.unwrap(), Role::Viewer);
+    }
+
+    #[tokio::test]
+    async fn role_claim_takes_the_most_privileged_role() {
+        let stub = StubIssuer::start().await;
+        let mut auth = discover(&stub, json!({ "operators": { "ops|1": "viewer" } })).await;
+        auth.role_claim = Some("https://console.example/roles".to_string());
+
+        let mut identity = Identity::new("ops|1");
+        identity.extra = json!({ "https://console.example/roles": ["support", "admin", "owner"] });
+        assert_eq!(sign_in(&auth, &stub, identity).await.unwrap(), Role::Admin);
+
+        let without_claim = Identity::new("ops|1");
+        assert_eq!(
+            sign_in(&auth, &stub, without_claim).await.unwrap(),
+            Role::Viewer
+        );
+    }
+}
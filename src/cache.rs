@@ -0,0 +1,359 @@
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use auth0_mgmt_api::types::{
+    clients::{Client, ListClientsParams},
+    connections::{Connection, ListConnectionsParams},
+    roles::{ListRolesParams, Permission, Role},
+};
+
+use crate::client::Auth0Client;
+
+/// The largest page Auth0's list endpoints return.
+pub const PAGE_SIZE: u32 = 100;
+
+/// Slow-changing lists that several pages need, kept for `ttl` so a page
+/// render does not re-list them from Auth0 every time. Handlers that
+/// change one of these must invalidate it.
+pub struct Cache {
+    pub connections: Cached<Vec<Connection>>,
+    pub clients: Cached<Vec<Client>>,
+    pub roles: Cached<Vec<Role>>,
+    /// Each role's permissions by role ID, for the API usage card. Filled
+    /// by the caller, since it takes one listing per role.
+    pub role_permissions: Cached<HashMap<String, Vec<Permission>>>,
+}
+
+/// How old a cached list is, for the status page.
+pub struct CacheStatus {
+    pub name: &'static str,
+    pub age: Option<Duration>,
+    pub ttl: Duration,
+}
+
+impl Cache {
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            connections: Cached::new(ttl),
+            clients: Cached::new(ttl),
+            roles: Cached::new(ttl),
+            role_permissions: Cached::new(ttl),
+        }
+    }
+
+    pub async fn connections(
+        &self,
+        client: &Auth0Client,
+    ) -> Result<Arc<Vec<Connection>>, auth0_mgmt_api::Error> {
+        self.connections
+            .get_or_fetch(fetch_all(|page| async move {
+                let params = ListConnectionsParams {
+                    page: Some(page),
+                    per_page: Some(PAGE_SIZE),
+                    include_totals: Some(true),
+                    ..Default::default()
+                };
+                let params = &params;
+                let result =
+                    client
+                        .get(|c| async move {
+                            c.connections().list_paginated(Some(params.clone())).await
+                        })
+                        .await?;
+                Ok((result.items, result.total))
+            }))
+            .await
+    }
+
+    pub async fn clients(
+        &self,
+        client: &Auth0Client,
+    ) -> Result<Arc<Vec<Client>>, auth0_mgmt_api::Error> {
+        self.clients
+            .get_or_fetch(fetch_all(|page| async move {
+                let params = ListClientsParams {
+                    page: Some(page),
+                    per_page: Some(PAGE_SIZE),
+                    include_totals: Some(true),
+                    ..Default::default()
+                };
+                let params = &params;
+                let result = client
+                    .get(|c| async move { c.clients().list_paginated(Some(params.clone())).await })
+                    .await?;
+                Ok((result.items, result.total))
+            }))
+            .await
+    }
+
+    pub async fn roles(
+        &self,
+        client: &Auth0Client,
+    ) -> Result<Arc<Vec<Role>>, auth0_mgmt_api::Error> {
+        self.roles
+            .get_or_fetch(fetch_all(|page| async move {
+                let params = ListRolesParams {
+                    page: Some(page),
+                    per_page: Some(PAGE_SIZE),
+                    include_totals: Some(true),
+                    ..Default::default()
+                };
+                let params = &params;
+                let result = client
+                    .get(|c| async move { c.roles().list_paginated(Some(params.clone())).await })
+                    .await?;
+                Ok((result.items, result.total))
+            }))
+            .await
+    }
+
+    pub fn invalidate_all(&self) {
+        self.connections.invalidate();
+        self.clients.invalidate();
+        self.roles.invalidate();
+        self.role_permissions.invalidate();
+    }
+
+    pub fn status(&self) -> Vec<CacheStatus> {
+        vec![
+            self.connections.status("Connections"),
+            self.clients.status("Applications"),
+            self.roles.status("Roles"),
+            self.role_permissions.status("Role permissions"),
+        ]
+    }
+}
+
+/// Collects every page of a list, given a fetch for one page that also
+/// returns the total. Stops at a short page or once the total is reached.
+pub async fn fetch_all<T, E, F, Fut>(mut fetch_page: F) -> Result<Vec<T>, E>
+where
+    F: FnMut(u32) -> Fut,
+    Fut: Future<Output = Result<(Vec<T>, u32), E>>,
+{
+    let mut items = Vec::new();
+    for page in 0.. {
+        let (batch, total) = fetch_page(page).await?;
+        let short = batch.len() < PAGE_SIZE as usize;
+        items.extend(batch);
+        if short || items.len() >= total as usize {
+            break;
+        }
+    }
+    Ok(items)
+}
+
+/// [`fetch_all`] for checkpoint-paginated lists such as sessions:
+/// `fetch_page` gets the `next` token from the previous page (`None` for
+/// the first) and returns its items with the token for the page after.
+pub async fn fetch_all_from<T, E, F, Fut>(mut fetch_page: F) -> Result<Vec<T>, E>
+where
+    F: FnMut(Option<String>) -> Fut,
+    Fut: Future<Output = Result<(Vec<T>, Option<String>), E>>,
+{
+    let mut items = Vec::new();
+    let mut from = None;
+    loop {
+        let (batch, next) = fetch_page(from.take()).await?;
+        let empty = batch.is_empty();
+        items.extend(batch);
+        match next {
+            Some(next) if !empty => from = Some(next),
+            _ => break,
+        }
+    }
+    Ok(items)
+}
+
+impl CacheStatus {
+    pub fn age_secs(&self) -> Option<u64> {
+        self.age.map(|age| age.as_secs())
+    }
+
+    pub fn ttl_secs(&self) -> u64 {
+        self.ttl.as_secs()
+    }
+}
+
+pub struct Cached<T> {
+    ttl: Duration,
+    entry: Mutex<Entry<T>>,
+    /// Held while fetching so concurrent misses share one upstream call.
+    refresh: tokio::sync::Mutex<()>,
+}
+
+struct Entry<T> {
+    value: Option<(Instant, Arc<T>)>,
+    /// Bumped on invalidation so a fetch that started before it does not
+    /// store what it read.
+    generation: u64,
+}
+
+impl<T> Cached<T> {
+    fn new(ttl: Duration) -> Self {
+        Self {
+            ttl,
+            entry: Mutex::new(Entry {
+                value: None,
+                generation: 0,
+            }),
+            refresh: tokio::sync::Mutex::new(()),
+        }
+    }
+
+    pub async fn get_or_fetch<E, F>(&self, fetch: F) -> Result<Arc<T>, E>
+    where
+        F: Future<Output = Result<T, E>>,
+    {
+        if let Some(value) = self.fresh() {
+            return Ok(value);
+        }
+
+        let _refresh = self.refresh.lock().await;
+        // Another request may have filled the entry while we waited.
+        if let Some(value) = self.fresh() {
+            return Ok(value);
+        }
+
+        let generation = self.lock().generation;
+        let value = Arc::new(fetch.await?);
+
+        let mut entry = self.lock();
+        if entry.generation == generation {
+            entry.value = Some((Instant::now(), value.clone()));
+        }
+        Ok(value)
+    }
+
+    pub fn invalidate(&self) {
+        let mut entry = self.lock();
+        entry.value = None;
+        entry.generation += 1;
+    }
+
+    fn fresh(&self) -> Option<Arc<T>> {
+        match &self.lock().value {
+            Some((fetched_at, value)) if fetched_at.elapsed() < self.ttl => Some(value.clone()),
+            _ => None,
+        }
+    }
+
+    fn status(&self, name: &'static str) -> CacheStatus {
+        let age = self
+            .lock()
+            .value
+            .as_ref()
+            .map(|(fetched_at, _)| fetched_at.elapsed())
+            .filter(|age| *age < self.ttl);
+        CacheStatus {
+            name,
+            age,
+            ttl: self.ttl,
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Entry<T>> {
+        self.entry.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn pages(total: u32) -> (Vec<u32>, Vec<u32>) {
+        let mut requested = Vec::new();
+        let items = fetch_all(|page| {
+            requested.push(page);
+            let start = page * PAGE_SIZE;
+            let end = (start + PAGE_SIZE).min(total);
+            async move { Ok::<_, ()>(((start..end).collect(), total)) }
+        })
+        .await
+        .unwrap();
+        (items, requested)
+    }
+
+    #[tokio::test]
+    async fn fetch_all_reads_every_page() {
+        let (items, requested) = pages(250).await;
+        assert_eq!(items, (0..250).collect::<Vec<_>>());
+        assert_eq!(requested, vec![0, 1, 2]);
+    }
+
+    #[tokio::test]
+    async fn fetch_all_stops_at_the_total() {
+        let (items, requested) = pages(200).await;
+        assert_eq!(items.len(), 200);
+        assert_eq!(requested, vec![0, 1]);
+
+        let (items, requested) = pages(0).await;
+        assert!(items.is_empty());
+        assert_eq!(requested, vec![0]);
+    }
+
+    #[tokio::test]
+    async fn fetch_all_stops_on_error() {
+        let result = fetch_all(|page| async move {
+            match page {
+                0 => Ok(((0..PAGE_SIZE).collect::<Vec<_>>(), 500)),
+                _ => Err("rate limited"),
+            }
+        })
+        .await;
+        assert_eq!(result, Err("rate limited"));
+    }
+
+    #[tokio::test]
+    async fn fetch_all_from_follows_next_until_it_runs_out() {
+        let mut requested = Vec::new();
+        let items = fetch_all_from(|from| {
+            requested.push(from.clone());
+            let page = match from.as_deref() {
+                None => (vec![1, 2], Some("b".to_string())),
+                Some("b") => (vec![3], Some("c".to_string())),
+                // Auth0 may hand out a token for a page that turns out empty.
+                _ => (Vec::new(), Some("d".to_string())),
+            };
+            async move { Ok::<_, ()>(page) }
+        })
+        .await
+        .unwrap();
+
+        assert_eq!(items, vec![1, 2, 3]);
+        assert_eq!(
+            requested,
+            vec![None, Some("b".to_string()), Some("c".to_string())]
+        );
+    }
+
+    #[tokio::test]
+    async fn cached_serves_until_invalidated() {
+        let cached = Cached::new(Duration::from_secs(60));
+        let first = cached.get_or_fetch(async { Ok::<_, ()>(1) }).await;
+        assert_eq!(*first.unwrap(), 1);
+
+        let hit = cached.get_or_fetch(async { Ok::<_, ()>(2) }).await;
+        assert_eq!(*hit.unwrap(), 1);
+        assert!(cached.status("test").age.is_some());
+
+        cached.invalidate();
+        let refetched = cached.get_or_fetch(async { Ok::<_, ()>(3) }).await;
+        assert_eq!(*refetched.unwrap(), 3);
+    }
+
+    #[tokio::test]
+    async fn fetch_started_before_invalidation_is_not_stored() {
+        let cached = Cached::new(Duration::from_secs(60));
+        let stale = cached
+            .get_or_fetch(async {
+                cached.invalidate();
+                Ok::<_, ()>(1)
+            })
+            .await;
+        assert_eq!(*stale.unwrap(), 1);
+        assert!(cached.status("test").age.is_none());
+    }
+}
@@ -0,0 +1,510 @@
+use std::future::Future;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime};
+
+use auth0_mgmt_api::{Error, ManagementClient};
+use reqwest::header::HeaderMap;
+use serde::Deserialize;
+use time::{format_description::well_known::Rfc3339, OffsetDateTime};
+use tokio::sync::{Semaphore, SemaphorePermit};
+
+use crate::config::TenantConfig;
+
+const BASE_DELAY: Duration = Duration::from_millis(250);
+const MAX_DELAY: Duration = Duration::from_secs(10);
+/// How long to hold off when Auth0 answers 429 without saying when the
+/// limit resets.
+const DEFAULT_PAUSE: Duration = Duration::from_secs(1);
+/// How often the status page may ask Auth0 for fresh rate-limit headers.
+const PROBE_INTERVAL: Duration = Duration::from_secs(60);
+/// Renew the probe's token this long before Auth0 says it expires.
+const TOKEN_MARGIN: Duration = Duration::from_secs(60);
+
+/// Wraps the Management API client so the whole app shares one rate-limit
+/// budget. Every request waits for a concurrency slot and for any back-off
+/// Auth0 has asked for; reads are retried, mutations are sent once.
+///
+/// Requests are passed in as closures taking the underlying client so a
+/// read can be issued again on retry:
+///
+/// ```ignore
+/// let user = tenant.client.get(|c| async move { c.users().get(UserId::new(id)).await }).await?;
+/// ```
+pub struct Auth0Client {
+    inner: Arc<ManagementClient>,
+    /// Which credentials to check when Auth0 rejects them, e.g. `Check
+    /// AUTH0_CLIENT_ID and AUTH0_CLIENT_SECRET for tenant "default".`
+    credentials: String,
+    /// The rate-limit probe talks to Auth0 directly, because the Management
+    /// API client does not expose response headers.
+    http: reqwest::Client,
+    domain: String,
+    client_id: String,
+    client_secret: String,
+    token: tokio::sync::Mutex<Option<(String, Instant)>>,
+    observed: Mutex<Option<RateLimit>>,
+    last_probe: Mutex<Option<(Instant, Result<(), String>)>>,
+    permits: Semaphore,
+    max_concurrency: usize,
+    max_retries: u32,
+    paused_until: Mutex<Option<Instant>>,
+    last_limited: Mutex<Option<SystemTime>>,
+    waiting: AtomicU64,
+    requests: AtomicU64,
+    retries: AtomicU64,
+    rate_limited: AtomicU64,
+}
+
+/// Snapshot of the shared budget for the status page.
+pub struct Budget {
+    pub max_concurrency: usize,
+    pub in_flight: usize,
+    pub waiting: u64,
+    pub paused_for: Option<Duration>,
+    pub last_limited: Option<String>,
+    pub requests: u64,
+    pub retries: u64,
+    pub rate_limited: u64,
+    pub max_retries: u32,
+    /// What Auth0 last reported about the tenant's own limit.
+    pub auth0: Option<RateLimit>,
+}
+
+/// Auth0's `x-ratelimit-*` headers from the last probe.
+#[derive(Clone)]
+pub struct RateLimit {
+    pub limit: Option<u64>,
+    pub remaining: Option<u64>,
+    /// When the bucket refills, as RFC 3339.
+    pub reset: Option<String>,
+    pub observed_at: String,
+}
+
+impl RateLimit {
+    fn from_headers(headers: &HeaderMap) -> Self {
+        let number = |name: &str| {
+            headers
+                .get(name)
+                .and_then(|v| v.to_str().ok())
+                .and_then(|v| v.trim().parse::<u64>().ok())
+        };
+        Self {
+            limit: number("x-ratelimit-limit"),
+            remaining: number("x-ratelimit-remaining"),
+            reset: number("x-ratelimit-reset")
+                .and_then(|secs| OffsetDateTime::from_unix_timestamp(secs as i64).ok())
+                .and_then(|at| at.format(&Rfc3339).ok()),
+            observed_at: OffsetDateTime::now_utc()
+                .format(&Rfc3339)
+                .unwrap_or_default(),
+        }
+    }
+
+    /// How long until the bucket refills, if Auth0 said.
+    fn reset_in(&self) -> Option<Duration> {
+        let reset = OffsetDateTime::parse(self.reset.as_deref()?, &Rfc3339).ok()?;
+        Duration::try_from(reset - OffsetDateTime::now_utc()).ok()
+    }
+}
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    access_token: String,
+    expires_in: u64,
+}
+
+impl Budget {
+    pub fn available(&self) -> usize {
+        self.max_concurrency - self.in_flight
+    }
+
+    /// Seconds left on the current back-off, rounded up.
+    pub fn paused_secs(&self) -> Option<u64> {
+        self.paused_for
+            .map(|d| d.as_secs() + u64::from(d.subsec_nanos() > 0))
+    }
+}
+
+impl Auth0Client {
+    pub fn new(
+        inner: ManagementClient,
+        tenant: &TenantConfig,
+        max_concurrency: usize,
+        max_retries: u32,
+    ) -> Self {
+        let max_concurrency = max_concurrency.max(1);
+        Self {
+            inner: Arc::new(inner),
+            credentials: format!("Check {} for tenant \"{}\".", tenant.source, tenant.name),
+            http: reqwest::Client::new(),
+            domain: tenant.domain.clone(),
+            client_id: tenant.client_id.clone(),
+            client_secret: tenant.client_secret.clone(),
+            token: tokio::sync::Mutex::new(None),
+            observed: Mutex::new(None),
+            last_probe: Mutex::new(None),
+            permits: Semaphore::new(max_concurrency),
+            max_concurrency,
+            max_retries,
+            paused_until: Mutex::new(None),
+            last_limited: Mutex::new(None),
+            waiting: AtomicU64::new(0),
+            requests: AtomicU64::new(0),
+            retries: AtomicU64::new(0),
+            rate_limited: AtomicU64::new(0),
+        }
+    }
+
+    /// Issues an idempotent request, retrying rate-limit and transient
+    /// upstream failures with jittered exponential backoff.
+    pub async fn get<T, F, Fut>(&self, mut call: F) -> Result<T, Error>
+    where
+        F: FnMut(Arc<ManagementClient>) -> Fut,
+        Fut: Future<Output = Result<T, Error>>,
+    {
+        let mut attempt = 0;
+        loop {
+            match self.send(&mut call).await {
+                Err(e) if attempt < self.max_retries && is_retryable(&e) => {
+                    attempt += 1;
+                    self.retries.fetch_add(1, Ordering::Relaxed);
+                    let delay = backoff(attempt);
+                    tracing::warn!(error = ?e, attempt, ?delay, "retrying Auth0 request");
+                    tokio::time::sleep(delay).await;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    /// Issues a request exactly once. Used for mutations, which are not
+    /// safe to repeat blindly.
+    pub async fn send<T, F, Fut>(&self, call: F) -> Result<T, Error>
+    where
+        F: FnOnce(Arc<ManagementClient>) -> Fut,
+        Fut: Future<Output = Result<T, Error>>,
+    {
+        let _permit = self.slot().await;
+        let mut result = call(self.inner.clone()).await;
+        if let Err(e) = &mut result {
+            if let Some(pause) = rate_limit_pause(e) {
+                self.pause(pause);
+            }
+            // Auth0 only says the token was refused; each tenant has its own
+            // credentials, so say which ones.
+            if let Error::Api {
+                status: 401,
+                message,
+                ..
+            } = e
+            {
+                *message = format!("{} Auth0 said: {}", self.credentials, message);
+            }
+        }
+        result
+    }
+
+    /// Reads one field of our own client to see Auth0's `x-ratelimit-*`
+    /// headers for the tenant. At most one probe a minute, failed or not, so
+    /// the status page does not spend the budget it reports on.
+    pub async fn probe_rate_limit(&self) -> Result<(), String> {
+        let last = self
+            .last_probe
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone();
+        if let Some((at, result)) = last {
+            if at.elapsed() < PROBE_INTERVAL {
+                return result;
+            }
+        }
+
+        let result = self.probe().await;
+        *self.last_probe.lock().unwrap_or_else(|e| e.into_inner()) =
+            Some((Instant::now(), result.clone()));
+        result
+    }
+
+    async fn probe(&self) -> Result<(), String> {
+        let token = self.token().await?;
+        let _permit = self.slot().await;
+        let response = self
+            .http
+            .get(format!(
+                "https://{}/api/v2/clients/{}",
+                self.domain, self.client_id
+            ))
+            .query(&[("fields", "client_id"), ("include_fields", "true")])
+            .bearer_auth(token)
+            .send()
+            .await
+            .map_err(|e| e.to_string())?;
+
+        let seen = RateLimit::from_headers(response.headers());
+        let status = response.status();
+        if status.as_u16() == 429 {
+            self.pause(seen.reset_in().unwrap_or(DEFAULT_PAUSE));
+        }
+        *self.observed.lock().unwrap_or_else(|e| e.into_inner()) = Some(seen);
+
+        if status.is_success() {
+            Ok(())
+        } else {
+            Err(format!(
+                "Auth0 returned HTTP {} to the probe",
+                status.as_u16()
+            ))
+        }
+    }
+
+    /// A Management API token for the probe, fetched with the tenant's
+    /// client credentials and reused until shortly before it expires.
+    async fn token(&self) -> Result<String, String> {
+        let mut token = self.token.lock().await;
+        if let Some((value, renew_at)) = token.as_ref() {
+            if *renew_at > Instant::now() {
+                return Ok(value.clone());
+            }
+        }
+
+        let response = self
+            .http
+            .post(format!("https://{}/oauth/token", self.domain))
+            .json(&serde_json::json!({
+                "grant_type": "client_credentials",
+                "client_id": self.client_id,
+                "client_secret": self.client_secret,
+                "audience": format!("https://{}/api/v2/", self.domain),
+            }))
+            .send()
+            .await
+            .map_err(|e| e.to_string())?;
+        if response.status().as_u16() == 401 {
+            return Err(self.credentials.clone());
+        }
+        let issued: TokenResponse = response
+            .error_for_status()
+            .map_err(|e| e.to_string())?
+            .json()
+            .await
+            .map_err(|e| e.to_string())?;
+
+        let lifetime = Duration::from_secs(issued.expires_in).saturating_sub(TOKEN_MARGIN);
+        *token = Some((issued.access_token.clone(), Instant::now() + lifetime));
+        Ok(issued.access_token)
+    }
+
+    /// Waits for a concurrency slot and any back-off, then counts the
+    /// request. Hold the permit until the response is in.
+    async fn slot(&self) -> SemaphorePermit<'_> {
+        self.waiting.fetch_add(1, Ordering::Relaxed);
+        let permit = self.permits.acquire().await;
+        self.waiting.fetch_sub(1, Ordering::Relaxed);
+        let permit = permit.expect("the semaphore is never closed");
+
+        self.wait_for_reset().await;
+        self.requests.fetch_add(1, Ordering::Relaxed);
+        permit
+    }
+
+    /// How many requests may be in flight at once; callers fanning out
+    /// many reads use it to bound how many they start.
+    pub fn max_concurrency(&self) -> usize {
+        self.max_concurrency
+    }
+
+    pub fn budget(&self) -> Budget {
+        let now = Instant::now();
+        let paused_for = self
+            .paused_until
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .and_then(|until| until.checked_duration_since(now))
+            .filter(|d| !d.is_zero());
+        let last_limited = self
+            .last_limited
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .and_then(|at| OffsetDateTime::from(at).format(&Rfc3339).ok());
+
+        Budget {
+            max_concurrency: self.max_concurrency,
+            in_flight: self.max_concurrency - self.permits.available_permits(),
+            waiting: self.waiting.load(Ordering::Relaxed),
+            paused_for,
+            last_limited,
+            requests: self.requests.load(Ordering::Relaxed),
+            retries: self.retries.load(Ordering::Relaxed),
+            rate_limited: self.rate_limited.load(Ordering::Relaxed),
+            max_retries: self.max_retries,
+            auth0: self
+                .observed
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .clone(),
+        }
+    }
+
+    /// Sleeps until the back-off window closes. Loops because another
+    /// request may extend the window while we wait.
+    async fn wait_for_reset(&self) {
+        loop {
+            let until = *self.paused_until.lock().unwrap_or_else(|e| e.into_inner());
+            match until {
+                Some(until) if until > Instant::now() => {
+                    tokio::time::sleep_until(until.into()).await;
+                }
+                _ => return,
+            }
+        }
+    }
+
+    fn pause(&self, pause: Duration) {
+        let until = Instant::now() + pause;
+        {
+            let mut paused_until = self.paused_until.lock().unwrap_or_else(|e| e.into_inner());
+            if paused_until.is_none_or(|current| current < until) {
+                *paused_until = Some(until);
+            }
+        }
+        *self.last_limited.lock().unwrap_or_else(|e| e.into_inner()) = Some(SystemTime::now());
+        self.rate_limited.fetch_add(1, Ordering::Relaxed);
+        tracing::warn!(?pause, "Auth0 rate limit hit, holding off requests");
+    }
+}
+
+/// How long Auth0 wants us to wait, if this is a rate-limit response. The
+/// client turns `x-ratelimit-reset` into `retry_after`.
+fn rate_limit_pause(e: &Error) -> Option<Duration> {
+    match e {
+        Error::RateLimited { retry_after } => Some(
+            retry_after
+                .map(Duration::from_secs)
+                .unwrap_or(DEFAULT_PAUSE),
+        ),
+        Error::Api { status: 429, .. } => Some(DEFAULT_PAUSE),
+        _ => None,
+    }
+}
+
+fn is_retryable(e: &Error) -> bool {
+    matches!(
+        e,
+        Error::RateLimited { .. }
+            | Error::Api {
+                status: 429 | 500 | 502 | 503 | 504,
+                ..
+            }
+    )
+}
+
+/// Exponential backoff with "equal jitter": somewhere between half and
+/// all of the doubled delay, so retrying callers spread out.
+fn backoff(attempt: u32) -> Duration {
+    let delay = BASE_DELAY
+        .saturating_mul(1 << attempt.saturating_sub(1).min(16))
+        .min(MAX_DELAY);
+    let millis = delay.as_millis() as u64;
+    Duration::from_millis(rand::random_range(millis / 2..=millis))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support;
+
+    fn client() -> Auth0Client {
+        let inner = ManagementClient::builder()
+            .domain("test.auth0.invalid")
+            .client_id("client")
+            .client_secret("secret")
+            .build()
+            .unwrap();
+        Auth0Client::new(inner, &test_support::tenant_config("test"), 4, 2)
+    }
+
+    #[test]
+    fn backoff_doubles_within_jitter_and_caps() {
+        for attempt in 1..=20 {
+            let ceiling = BASE_DELAY
+                .saturating_mul(1 << (attempt - 1).min(16))
+                .min(MAX_DELAY);
+            let delay = backoff(attempt);
+            assert!(
+                delay >= ceiling / 2 && delay <= ceiling,
+                "attempt {}",
+                attempt
+            );
+        }
+        assert!(backoff(30) <= MAX_DELAY);
+    }
+
+    #[test]
+    fn rate_limits_pause_for_as_long_as_auth0_asks() {
+        let limited = Error::RateLimited {
+            retry_after: Some(3),
+        };
+        assert_eq!(rate_limit_pause(&limited), Some(Duration::from_secs(3)));
+        assert!(is_retryable(&limited));
+
+        let unspecified = Error::RateLimited { retry_after: None };
+        assert_eq!(rate_limit_pause(&unspecified), Some(DEFAULT_PAUSE));
+    }
+
+    #[test]
+    fn pause_keeps_the_later_deadline() {
+        let client = client();
+        assert_eq!(client.budget().paused_secs(), None);
+
+        client.pause(Duration::from_secs(10));
+        client.pause(Duration::from_secs(1));
+
+        let budget = client.budget();
+        assert_eq!(budget.paused_secs(), Some(10));
+        assert_eq!(budget.rate_limited, 2);
+        assert!(budget.last_limited.is_some());
+        assert_eq!(budget.available(), 4);
+    }
+
+    #[test]
+    fn rate_limit_headers_are_read_and_reset_is_a_timestamp() {
+        let mut headers = HeaderMap::new();
+        headers.insert("x-ratelimit-limit", "50".parse().unwrap());
+        headers.insert("x-ratelimit-remaining", "48".parse().unwrap());
+        headers.insert("x-ratelimit-reset", "1700000000".parse().unwrap());
+
+        let seen = RateLimit::from_headers(&headers);
+        assert_eq!(seen.limit, Some(50));
+        assert_eq!(seen.remaining, Some(48));
+        assert_eq!(seen.reset.as_deref(), Some("2023-11-14T22:13:20Z"));
+        assert_eq!(seen.reset_in(), None, "a reset in the past is not a wait");
+
+        let missing = RateLimit::from_headers(&HeaderMap::new());
+        assert_eq!((missing.limit, missing.remaining), (None, None));
+        assert!(missing.reset.is_none());
+    }
+
+    #[test]
+    fn budget_shows_the_last_observed_headers() {
+        let client = client();
+        assert!(client.budget().auth0.is_none());
+
+        let mut headers = HeaderMap::new();
+        headers.insert("x-ratelimit-remaining", "7".parse().unwrap());
+        *client.observed.lock().unwrap() = Some(RateLimit::from_headers(&headers));
+
+        let auth0 = client.budget().auth0.unwrap();
+        assert_eq!(auth0.remaining, Some(7));
+        assert_eq!(auth0.limit, None);
+    }
+
+    #[tokio::test]
+    async fn probes_are_spaced_out_even_when_they_fail() {
+        let client = client();
+        *client.last_probe.lock().unwrap() = Some((Instant::now(), Err("refused".to_string())));
+
+        assert_eq!(client.probe_rate_limit().await, Err("refused".to_string()));
+        assert_eq!(client.budget().requests, 0);
+    }
+}
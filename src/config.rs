@@ -1,12 +1,48 @@
 use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use serde::Deserialize;
 use thiserror::Error;
 
 #[derive(Debug)]
 pub struct Config {
-    pub auth0_domain: String,
-    pub auth0_client_id: String,
-    pub auth0_client_secret: String,
+    pub tenants: Vec<TenantConfig>,
+    pub auth0_max_concurrency: usize,
+    pub auth0_max_retries: u32,
     pub bind_addr: SocketAddr,
+    pub oidc_issuer: String,
+    pub oidc_client_id: String,
+    pub oidc_client_secret: String,
+    pub oidc_redirect_url: String,
+    pub session_secret: String,
+    pub session_ttl: Duration,
+    pub oidc_role_claim: Option<String>,
+    pub operators_file: Option<PathBuf>,
+    pub audit_log_path: PathBuf,
+    pub cache_ttl: Duration,
+}
+
+/// One Auth0 tenant the app can manage, from `TENANTS_FILE` or the
+/// single-tenant `AUTH0_*` variables.
+#[derive(Debug, Deserialize)]
+pub struct TenantConfig {
+    /// Used in URLs (`/t/{name}/...`).
+    pub name: String,
+    pub label: Option<String>,
+    pub domain: String,
+    pub client_id: String,
+    pub client_secret: String,
+    /// Banner colour as `#rrggbb`.
+    pub colour: Option<String>,
+    /// Where the credentials were configured, named when Auth0 rejects them.
+    #[serde(skip)]
+    pub source: String,
+}
+
+#[derive(Deserialize)]
+struct TenantsFile {
+    tenants: Vec<TenantConfig>,
 }
 
 #[derive(Debug, Error)]
@@ -15,20 +51,120 @@ pub enum ConfigError {
     Missing(&'static str),
     #[error("invalid bind address")]
     Addr(#[from] std::net::AddrParseError),
+    #[error("invalid number in {0}")]
+    Number(&'static str),
+    #[error("SESSION_SECRET must be at least 64 bytes")]
+    SessionSecret,
+    #[error("invalid tenants: {0}")]
+    Tenants(String),
 }
 
 impl Config {
     pub fn from_env() -> Result<Self, ConfigError> {
+        let session_secret =
+            std::env::var("SESSION_SECRET").map_err(|_| ConfigError::Missing("SESSION_SECRET"))?;
+        if session_secret.len() < 64 {
+            return Err(ConfigError::SessionSecret);
+        }
+
+        let session_ttl_secs = env_number("SESSION_TTL_SECS", 8 * 60 * 60)?;
+
         Ok(Self {
-            auth0_domain: std::env::var("AUTH0_DOMAIN")
-                .map_err(|_| ConfigError::Missing("AUTH0_DOMAIN"))?,
-            auth0_client_id: std::env::var("AUTH0_CLIENT_ID")
-                .map_err(|_| ConfigError::Missing("AUTH0_CLIENT_ID"))?,
-            auth0_client_secret: std::env::var("AUTH0_CLIENT_SECRET")
-                .map_err(|_| ConfigError::Missing("AUTH0_CLIENT_SECRET"))?,
+            tenants: load_tenants()?,
+            auth0_max_concurrency: env_number("AUTH0_MAX_CONCURRENCY", 4)?,
+            auth0_max_retries: env_number("AUTH0_MAX_RETRIES", 3)?,
             bind_addr: std::env::var("BIND_ADDR")
                 .unwrap_or_else(|_| "0.0.0.0:3000".to_string())
                 .parse()?,
+            oidc_issuer: std::env::var("OIDC_ISSUER")
+                .map_err(|_| ConfigError::Missing("OIDC_ISSUER"))?,
+            oidc_client_id: std::env::var("OIDC_CLIENT_ID")
+                .map_err(|_| ConfigError::Missing("OIDC_CLIENT_ID"))?,
+            oidc_client_secret: std::env::var("OIDC_CLIENT_SECRET")
+                .map_err(|_| ConfigError::Missing("OIDC_CLIENT_SECRET"))?,
+            oidc_redirect_url: std::env::var("OIDC_REDIRECT_URL")
+                .unwrap_or_else(|_| "http://localhost:3000/auth/callback".to_string()),
+            session_secret,
+            session_ttl: Duration::from_secs(session_ttl_secs),
+            oidc_role_claim: std::env::var("OIDC_ROLE_CLAIM").ok(),
+            operators_file: std::env::var("OPERATORS_FILE").ok().map(PathBuf::from),
+            audit_log_path: std::env::var("AUDIT_LOG_PATH")
+                .unwrap_or_else(|_| "audit.jsonl".to_string())
+                .into(),
+            cache_ttl: Duration::from_secs(env_number("CACHE_TTL_SECS", 5 * 60)?),
         })
     }
 }
+
+fn env_number<T: std::str::FromStr>(name: &'static str, default: T) -> Result<T, ConfigError> {
+    match std::env::var(name) {
+        Ok(value) => value.parse().map_err(|_| ConfigError::Number(name)),
+        Err(_) => Ok(default),
+    }
+}
+
+/// Reads `TENANTS_FILE` if set, otherwise builds a single tenant from
+/// `AUTH0_DOMAIN`, `AUTH0_CLIENT_ID` and `AUTH0_CLIENT_SECRET`.
+fn load_tenants() -> Result<Vec<TenantConfig>, ConfigError> {
+    let tenants = match std::env::var("TENANTS_FILE") {
+        Ok(path) => {
+            let contents = std::fs::read_to_string(&path)
+                .map_err(|e| ConfigError::Tenants(format!("{}: {}", path, e)))?;
+            let mut tenants = serde_json::from_str::<TenantsFile>(&contents)
+                .map_err(|e| ConfigError::Tenants(format!("{}: {}", path, e)))?
+                .tenants;
+            for tenant in &mut tenants {
+                tenant.source =
+                    format!("its client_id and client_secret in TENANTS_FILE ({})", path);
+            }
+            tenants
+        }
+        Err(_) => vec![TenantConfig {
+            name: std::env::var("AUTH0_TENANT_NAME").unwrap_or_else(|_| "default".to_string()),
+            label: None,
+            domain: std::env::var("AUTH0_DOMAIN")
+                .map_err(|_| ConfigError::Missing("AUTH0_DOMAIN"))?,
+            client_id: std::env::var("AUTH0_CLIENT_ID")
+                .map_err(|_| ConfigError::Missing("AUTH0_CLIENT_ID"))?,
+            client_secret: std::env::var("AUTH0_CLIENT_SECRET")
+                .map_err(|_| ConfigError::Missing("AUTH0_CLIENT_SECRET"))?,
+            colour: None,
+            source: "AUTH0_CLIENT_ID and AUTH0_CLIENT_SECRET".to_string(),
+        }],
+    };
+
+    if tenants.is_empty() {
+        return Err(ConfigError::Tenants("no tenants configured".to_string()));
+    }
+    for (index, tenant) in tenants.iter().enumerate() {
+        let valid_name = !tenant.name.is_empty()
+            && tenant
+                .name
+                .chars()
+                .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-' || c == '_');
+        if !valid_name {
+            return Err(ConfigError::Tenants(format!(
+                "name {:?} must be lowercase letters, digits, '-' or '_'",
+                tenant.name
+            )));
+        }
+        if tenants[..index].iter().any(|t| t.name == tenant.name) {
+            return Err(ConfigError::Tenants(format!(
+                "duplicate name {:?}",
+                tenant.name
+            )));
+        }
+        if let Some(colour) = &tenant.colour {
+            let valid_colour = colour.len() == 7
+                && colour.starts_with('#')
+                && colour[1..].chars().all(|c| c.is_ascii_hexdigit());
+            if !valid_colour {
+                return Err(ConfigError::Tenants(format!(
+                    "{}: colour {:?} must look like #rrggbb",
+                    tenant.name, colour
+                )));
+            }
+        }
+    }
+    Ok(tenants)
+}
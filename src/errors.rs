@@ -1,6 +1,7 @@
+use askama::Template;
 use axum::{
-    http::StatusCode,
-    response::{IntoResponse, Response},
+    http::{header, StatusCode},
+    response::{Html, IntoResponse, Response},
 };
 use thiserror::Error;
 
@@ -14,22 +15,225 @@ pub enum AppError {
     #[error("Auth0 API error: {0}")]
     Auth0(String),
 
+    /// Carries which tenant's credentials to check, filled in by
+    /// [`crate::client::Auth0Client`].
+    #[error("Auth0 rejected the Management API credentials")]
+    Auth0Unauthorized(String),
+
+    #[error("Management API scope missing: {message}")]
+    Auth0Forbidden {
+        scope: Option<String>,
+        message: String,
+    },
+
+    #[error("Auth0 rate limit exceeded")]
+    RateLimited { retry_after: Option<u64> },
+
+    #[error("Auth0 unavailable (HTTP {status})")]
+    Auth0Unavailable { status: u16 },
+
     #[error("not found")]
     NotFound,
+
+    #[error("not signed in")]
+    Unauthorized,
+
+    #[error("forbidden")]
+    Forbidden,
+
+    #[error("audit log error: {0}")]
+    Audit(String),
 }
 
-impl IntoResponse for AppError {
-    fn into_response(self) -> Response {
+impl From<auth0_mgmt_api::Error> for AppError {
+    fn from(e: auth0_mgmt_api::Error) -> Self {
+        match e {
+            auth0_mgmt_api::Error::Api {
+                status, message, ..
+            } => match status {
+                401 => AppError::Auth0Unauthorized(message),
+                403 => AppError::Auth0Forbidden {
+                    scope: missing_scope(&message),
+                    message,
+                },
+                404 => AppError::NotFound,
+                429 => AppError::RateLimited { retry_after: None },
+                500..=599 => AppError::Auth0Unavailable { status },
+                _ => AppError::Auth0(message),
+            },
+            auth0_mgmt_api::Error::RateLimited { retry_after } => {
+                AppError::RateLimited { retry_after }
+            }
+            other => AppError::Auth0(other.to_string()),
+        }
+    }
+}
+
+/// Pulls the scope out of Auth0's "Insufficient scope, expected any of:
+/// read:users" style messages.
+fn missing_scope(message: &str) -> Option<String> {
+    let (_, scopes) = message.split_once("expected")?;
+    let (_, scopes) = scopes.split_once(':')?;
+    let scopes = scopes.trim().trim_end_matches('.');
+    (!scopes.is_empty()).then(|| scopes.to_string())
+}
+
+impl AppError {
+    pub fn status_code(&self) -> StatusCode {
+        match self {
+            AppError::Template(_) | AppError::Audit(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            AppError::Auth0(_)
+            | AppError::Auth0Unauthorized(_)
+            | AppError::Auth0Forbidden { .. } => StatusCode::BAD_GATEWAY,
+            AppError::RateLimited { .. } => StatusCode::TOO_MANY_REQUESTS,
+            AppError::Auth0Unavailable { .. } => StatusCode::SERVICE_UNAVAILABLE,
+            AppError::NotFound => StatusCode::NOT_FOUND,
+            AppError::Unauthorized => StatusCode::UNAUTHORIZED,
+            AppError::Forbidden => StatusCode::FORBIDDEN,
+        }
+    }
+
+    pub fn title(&self) -> &'static str {
+        match self {
+            AppError::Template(_) => "Template error",
+            AppError::Auth0(_) => "Auth0 request failed",
+            AppError::Auth0Unauthorized(_) => "Auth0 credentials rejected",
+            AppError::Auth0Forbidden { .. } => "Missing Management API scope",
+            AppError::RateLimited { .. } => "Rate limited by Auth0",
+            AppError::Auth0Unavailable { .. } => "Auth0 is unavailable",
+            AppError::NotFound => "Not found",
+            AppError::Unauthorized => "Not signed in",
+            AppError::Forbidden => "Permission denied",
+            AppError::Audit(_) => "Audit log error",
+        }
+    }
+
+    pub fn detail(&self) -> String {
         match self {
-            AppError::Template(e) => {
-                tracing::error!(error = ?e, "template rendering failed");
-                (StatusCode::INTERNAL_SERVER_ERROR, "Template error").into_response()
+            AppError::Template(_) => "The page could not be rendered.".to_string(),
+            AppError::Auth0(msg) => msg.clone(),
+            AppError::Auth0Unauthorized(msg) => msg.clone(),
+            AppError::Auth0Forbidden { message, .. } => message.clone(),
+            AppError::RateLimited { .. } => {
+                "Too many Management API requests. Wait a moment and try again.".to_string()
             }
-            AppError::Auth0(msg) => {
-                tracing::error!(error = %msg, "Auth0 API error");
-                (StatusCode::BAD_GATEWAY, "Upstream error").into_response()
+            AppError::Auth0Unavailable { status } => {
+                format!("Auth0 returned HTTP {}. Try again shortly.", status)
             }
-            AppError::NotFound => (StatusCode::NOT_FOUND, "Not found").into_response(),
+            AppError::NotFound => "The requested resource does not exist.".to_string(),
+            AppError::Unauthorized => "Sign in to continue.".to_string(),
+            AppError::Forbidden => "Your operator role does not allow this action.".to_string(),
+            AppError::Audit(msg) => msg.clone(),
+        }
+    }
+
+    pub fn missing_scope(&self) -> Option<&str> {
+        match self {
+            AppError::Auth0Forbidden { scope, .. } => scope.as_deref(),
+            _ => None,
+        }
+    }
+
+    pub fn retry_after(&self) -> Option<u64> {
+        match self {
+            AppError::RateLimited { retry_after } => *retry_after,
+            _ => None,
+        }
+    }
+
+    /// Bootstrap alert class for the banner.
+    pub fn level(&self) -> &'static str {
+        match self {
+            AppError::RateLimited { .. } | AppError::Auth0Unavailable { .. } => "warning",
+            _ => "danger",
+        }
+    }
+}
+
+#[derive(Template)]
+#[template(path = "error.html")]
+struct ErrorPageTemplate<'a> {
+    error: &'a AppError,
+}
+
+impl IntoResponse for AppError {
+    fn into_response(self) -> Response {
+        match &self {
+            AppError::Template(e) => tracing::error!(error = ?e, "template rendering failed"),
+            AppError::Audit(msg) => tracing::error!(error = %msg, "audit log error"),
+            AppError::NotFound | AppError::Unauthorized | AppError::Forbidden => {}
+            other => tracing::error!(error = %other, "Auth0 API error"),
         }
+
+        let status = self.status_code();
+        let body = match (ErrorPageTemplate { error: &self }).render() {
+            Ok(html) => Html(html).into_response(),
+            Err(_) => self.title().into_response(),
+        };
+
+        let mut response = (status, body).into_response();
+        if let Some(secs) = self.retry_after() {
+            response
+                .headers_mut()
+                .insert(header::RETRY_AFTER, secs.into());
+        }
+        response
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn missing_scope_is_read_from_auth0s_message() {
+        assert_eq!(
+            missing_scope("Insufficient scope, expected any of: read:users"),
+            Some("read:users".to_string())
+        );
+        assert_eq!(
+            missing_scope("Insufficient scope, expected all of: read:users,update:users."),
+            Some("read:users,update:users".to_string())
+        );
+        assert_eq!(missing_scope("Insufficient scope"), None);
+        assert_eq!(missing_scope("Insufficient scope, expected any of: "), None);
+    }
+
+    #[test]
+    fn missing_scope_is_shown_as_a_bad_gateway() {
+        let message = "Insufficient scope, expected any of: read:logs".to_string();
+        let error = AppError::Auth0Forbidden {
+            scope: missing_scope(&message),
+            message: message.clone(),
+        };
+        assert_eq!(error.missing_scope(), Some("read:logs"));
+        assert_eq!(error.status_code(), StatusCode::BAD_GATEWAY);
+        assert_eq!(error.detail(), message);
+        assert_eq!(error.level(), "danger");
+    }
+
+    #[test]
+    fn rejected_credentials_name_the_tenant() {
+        let error = AppError::Auth0Unauthorized(
+            "Check AUTH0_CLIENT_ID and AUTH0_CLIENT_SECRET for tenant \"default\". \
+             Auth0 said: Unauthorized"
+                .to_string(),
+        );
+        assert_eq!(error.status_code(), StatusCode::BAD_GATEWAY);
+        assert_eq!(error.title(), "Auth0 credentials rejected");
+        assert!(error.detail().contains("for tenant \"default\""));
+    }
+
+    #[test]
+    fn rate_limits_keep_auth0s_retry_after() {
+        let error = AppError::from(auth0_mgmt_api::Error::RateLimited {
+            retry_after: Some(7),
+        });
+        assert_eq!(error.status_code(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(error.level(), "warning");
+
+        let response = error.into_response();
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(response.headers()[header::RETRY_AFTER], "7");
     }
 }
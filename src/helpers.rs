@@ -1,4 +1,13 @@
+use std::{fmt::Display, str::FromStr};
+
 use axum::http::HeaderMap;
+use serde::{Deserialize, Deserializer};
+
+pub const PAGE_SIZES: &[u32] = &[10, 20, 50, 100];
+
+/// Highest zero-based page a query may ask for, so `page * per_page` and
+/// the pager's arithmetic stay within `u32` for every offered page size.
+pub const MAX_PAGE: u32 = u32::MAX / PAGE_SIZES[PAGE_SIZES.len() - 1] - 1;
 
 pub fn is_htmx_request(headers: &HeaderMap) -> bool {
     headers.get("hx-request").is_some()
@@ -16,3 +25,241 @@ pub fn total_pages(total_items: usize, per_page: u32) -> u32 {
     let per_page = per_page.max(1);
     (total_items as u32).div_ceil(per_page).max(1)
 }
+
+/// Treats an empty query value (e.g. a blank number input) as absent.
+pub fn empty_as_none<'de, D, T>(deserializer: D) -> Result<Option<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: FromStr,
+    T::Err: Display,
+{
+    match Option::<String>::deserialize(deserializer)?.as_deref().map(str::trim) {
+        None | Some("") => Ok(None),
+        Some(value) => value.parse().map(Some).map_err(serde::de::Error::custom),
+    }
+}
+
+/// Picks the zero-based page to fetch. `jump` comes from the 1-based
+/// "go to page" input and wins over `page`.
+pub fn requested_page(page: Option<u32>, jump: Option<u32>) -> u32 {
+    jump.map(|j| j.saturating_sub(1))
+        .or(page)
+        .unwrap_or(0)
+        .min(MAX_PAGE)
+}
+
+/// Only allow the page sizes offered in the UI.
+pub fn requested_per_page(per_page: Option<u32>, default: u32) -> u32 {
+    per_page
+        .filter(|n| PAGE_SIZES.contains(n))
+        .unwrap_or(default)
+}
+
+/// Pager state built from the `start`/`limit`/`total` Auth0 returns with
+/// `include_totals=true`, plus what is needed to link to other pages.
+#[derive(Clone)]
+pub struct Pagination {
+    pub page: u32,
+    pub per_page: u32,
+    pub total: u32,
+    pub total_pages: u32,
+    pub base_url: String,
+    pub target: String,
+    pub params: Vec<(String, String)>,
+}
+
+impl Pagination {
+    pub fn new(base_url: &str, target: &str, start: u32, limit: u32, total: u32) -> Self {
+        let per_page = limit.max(1);
+        Self {
+            page: start / per_page,
+            per_page,
+            total,
+            total_pages: total_pages(total as usize, per_page),
+            base_url: base_url.to_string(),
+            target: target.to_string(),
+            params: Vec::new(),
+        }
+    }
+
+    /// Stops the pager at the first `max_results` items, for searches Auth0
+    /// will not page beyond however many match.
+    pub fn within(mut self, max_results: u32) -> Self {
+        self.total_pages = self
+            .total_pages
+            .min(total_pages(max_results as usize, self.per_page));
+        self
+    }
+
+    /// Whether more items match than the pager can reach.
+    pub fn is_truncated(&self) -> bool {
+        u64::from(self.total_pages) * u64::from(self.per_page) < u64::from(self.total)
+    }
+
+    /// Carries a filter through to page links; empty values are dropped.
+    pub fn with_param(mut self, name: &str, value: Option<&str>) -> Self {
+        if let Some(value) = value.filter(|v| !v.is_empty()) {
+            self.params.push((name.to_string(), value.to_string()));
+        }
+        self
+    }
+
+    pub fn url(&self, page: u32) -> String {
+        let mut query = url::form_urlencoded::Serializer::new(String::new());
+        query.append_pair("page", &page.to_string());
+        query.append_pair("per_page", &self.per_page.to_string());
+        for (name, value) in &self.params {
+            query.append_pair(name, value);
+        }
+        format!("{}?{}", self.base_url, query.finish())
+    }
+
+    pub fn first_item(&self) -> u32 {
+        if self.total == 0 {
+            0
+        } else {
+            (self.page * self.per_page + 1).min(self.total)
+        }
+    }
+
+    pub fn last_item(&self) -> u32 {
+        ((self.page + 1) * self.per_page).min(self.total)
+    }
+
+    pub fn has_previous(&self) -> bool {
+        self.page > 0
+    }
+
+    pub fn has_next(&self) -> bool {
+        self.page + 1 < self.total_pages
+    }
+
+    /// Page numbers to link directly: the first, the last and two either
+    /// side of the current page. `None` marks a gap.
+    pub fn window(&self) -> Vec<Option<u32>> {
+        let last = self.total_pages.saturating_sub(1);
+        let from = self.page.saturating_sub(2);
+        let to = (self.page + 2).min(last);
+
+        let mut pages = Vec::new();
+        if from > 0 {
+            pages.push(Some(0));
+            if from > 1 {
+                pages.push(None);
+            }
+        }
+        pages.extend((from..=to).map(Some));
+        if to < last {
+            if to + 1 < last {
+                pages.push(None);
+            }
+            pages.push(Some(last));
+        }
+        pages
+    }
+
+    pub fn page_sizes(&self) -> &'static [u32] {
+        PAGE_SIZES
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn jump_wins_over_page_and_both_are_clamped() {
+        assert_eq!(requested_page(None, None), 0);
+        assert_eq!(requested_page(Some(3), None), 3);
+        assert_eq!(requested_page(Some(3), Some(1)), 0);
+        assert_eq!(requested_page(None, Some(0)), 0);
+        assert_eq!(requested_page(Some(u32::MAX), None), MAX_PAGE);
+        assert_eq!(requested_page(None, Some(u32::MAX)), MAX_PAGE);
+
+        let largest = PAGE_SIZES[PAGE_SIZES.len() - 1];
+        assert!((MAX_PAGE + 1).checked_mul(largest).is_some());
+    }
+
+    #[test]
+    fn only_offered_page_sizes_are_accepted() {
+        assert_eq!(requested_per_page(Some(50), 20), 50);
+        assert_eq!(requested_per_page(Some(51), 20), 20);
+        assert_eq!(requested_per_page(Some(0), 20), 20);
+        assert_eq!(requested_per_page(None, 20), 20);
+    }
+
+    #[test]
+    fn pagination_follows_auth0_totals() {
+        let pagination = Pagination::new("/t/prod/users", "#users-table", 40, 20, 95);
+        assert_eq!(pagination.page, 2);
+        assert_eq!(pagination.total_pages, 5);
+        assert_eq!(pagination.first_item(), 41);
+        assert_eq!(pagination.last_item(), 60);
+        assert!(pagination.has_previous());
+        assert!(pagination.has_next());
+
+        let last = Pagination::new("/t/prod/users", "#users-table", 80, 20, 95);
+        assert_eq!(last.last_item(), 95);
+        assert!(!last.has_next());
+
+        let empty = Pagination::new("/t/prod/users", "#users-table", 0, 0, 0);
+        assert_eq!(empty.total_pages, 1);
+        assert_eq!(empty.first_item(), 0);
+        assert!(!empty.has_previous() && !empty.has_next());
+    }
+
+    #[test]
+    fn window_links_the_ends_and_marks_gaps() {
+        let at = |page: u32| Pagination::new("/x", "#x", page * 10, 10, 200);
+        assert_eq!(at(0).window(), [Some(0), Some(1), Some(2), None, Some(19)]);
+        assert_eq!(
+            at(10).window(),
+            [
+                Some(0),
+                None,
+                Some(8),
+                Some(9),
+                Some(10),
+                Some(11),
+                Some(12),
+                None,
+                Some(19)
+            ]
+        );
+        assert_eq!(
+            at(17).window(),
+            [
+                Some(0),
+                None,
+                Some(15),
+                Some(16),
+                Some(17),
+                Some(18),
+                Some(19)
+            ]
+        );
+    }
+
+    #[test]
+    fn within_caps_the_reachable_pages() {
+        let pagination = Pagination::new("/x", "#x", 0, 50, 4321).within(1000);
+        assert_eq!(pagination.total_pages, 20);
+        assert_eq!(pagination.total, 4321);
+        assert!(pagination.is_truncated());
+
+        let small = Pagination::new("/x", "#x", 0, 50, 120).within(1000);
+        assert_eq!(small.total_pages, 3);
+        assert!(!small.is_truncated());
+    }
+
+    #[test]
+    fn page_links_keep_filters_and_drop_empty_ones() {
+        let pagination = Pagination::new("/t/prod/users", "#users-table", 0, 20, 100)
+            .with_param("q", Some("email:\"a@b.c\""))
+            .with_param("connection", Some(""));
+        assert_eq!(
+            pagination.url(3),
+            "/t/prod/users?page=3&per_page=20&q=email%3A%22a%40b.c%22"
+        );
+    }
+}
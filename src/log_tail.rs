@@ -0,0 +1,477 @@
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use auth0_mgmt_api::types::logs::{ListLogsParams, LogEvent};
+use tokio::sync::broadcast;
+
+use crate::client::Auth0Client;
+
+const POLL_INTERVAL: Duration = Duration::from_secs(5);
+/// Auth0 rejects checkpoint requests with `take` above 100.
+const BATCH_SIZE: u32 = 100;
+/// Full batches fetched back to back in one tick before waiting for the
+/// next, so a burst of events is caught up on without hammering the API.
+const MAX_BATCHES_PER_TICK: usize = 5;
+/// Batches a slow subscriber may fall behind before it starts skipping.
+const CHANNEL_CAPACITY: usize = 16;
+
+/// A batch of new events, newest first.
+pub type LogBatch = Arc<Vec<LogEvent>>;
+
+/// Polls the logs API on behalf of live-tail subscribers. There is one
+/// poller per tenant, following Auth0's `from` checkpoint so no event is
+/// missed or repeated; each subscriber applies its own search with
+/// [`LogFilter`]. The poller only runs while someone is listening and shuts
+/// itself down once the last subscriber goes away.
+pub struct LogTail {
+    client: Arc<Auth0Client>,
+    sender: Mutex<Option<broadcast::Sender<LogBatch>>>,
+}
+
+impl LogTail {
+    pub fn new(client: Arc<Auth0Client>) -> Self {
+        Self {
+            client,
+            sender: Mutex::new(None),
+        }
+    }
+
+    pub fn subscribe(self: &Arc<Self>) -> broadcast::Receiver<LogBatch> {
+        let mut current = self.sender.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(sender) = current.as_ref() {
+            return sender.subscribe();
+        }
+
+        let (sender, receiver) = broadcast::channel(CHANNEL_CAPACITY);
+        *current = Some(sender.clone());
+        tokio::spawn(Arc::clone(self).poll(sender));
+        receiver
+    }
+
+    async fn poll(self: Arc<Self>, sender: broadcast::Sender<LogBatch>) {
+        tracing::debug!("starting log tail");
+        let mut last_seen: Option<String> = None;
+        let mut primed = false;
+        let mut interval = tokio::time::interval(POLL_INTERVAL);
+        interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
+
+        loop {
+            interval.tick().await;
+
+            // Checked under the same lock `subscribe` takes, so a subscriber
+            // arriving now either sees this sender or starts a new poller.
+            {
+                let mut current = self.sender.lock().unwrap_or_else(|e| e.into_inner());
+                if sender.receiver_count() == 0 {
+                    *current = None;
+                    tracing::debug!("stopping log tail");
+                    return;
+                }
+            }
+
+            for _ in 0..MAX_BATCHES_PER_TICK {
+                let events = match self.fetch(last_seen.as_deref()).await {
+                    Ok(events) => events,
+                    Err(e) => {
+                        tracing::warn!(error = ?e, "log tail poll failed");
+                        break;
+                    }
+                };
+
+                if let Some(newest) = events.first().and_then(|e| e.log_id.clone()) {
+                    last_seen = Some(newest);
+                }
+
+                // The first successful poll only establishes where "now" is;
+                // those events are already on the page the subscriber came
+                // from. Until it succeeds there is no checkpoint to follow.
+                if !primed {
+                    primed = true;
+                    break;
+                }
+                let full = events.len() >= BATCH_SIZE as usize;
+                if !events.is_empty() {
+                    let _ = sender.send(Arc::new(events));
+                }
+                if !full {
+                    break;
+                }
+            }
+        }
+    }
+
+    /// Returns events newer than `last_seen`, newest first. Without a
+    /// checkpoint yet (an empty log) it falls back to the newest events.
+    async fn fetch(&self, last_seen: Option<&str>) -> Result<Vec<LogEvent>, auth0_mgmt_api::Error> {
+        let params = match last_seen {
+            Some(from) => ListLogsParams {
+                from: Some(from.to_string()),
+                take: Some(BATCH_SIZE),
+                ..Default::default()
+            },
+            None => ListLogsParams {
+                page: Some(0),
+                per_page: Some(BATCH_SIZE),
+                include_totals: Some(false),
+                sort: Some("date:-1".to_string()),
+                ..Default::default()
+            },
+        };
+        let params = &params;
+        let mut events = self
+            .client
+            .get(|c| async move { c.logs().list(Some(params.clone())).await })
+            .await?;
+
+        // Checkpoint results come back oldest first.
+        events.sort_by(|a, b| b.date.cmp(&a.date));
+        Ok(events)
+    }
+}
+
+/// The part of Auth0's log search syntax a live-tail subscriber can apply
+/// to polled events: terms joined by `AND` (or just a space) and `OR`,
+/// `NOT` or `-` to negate a term or group, and parentheses for grouping. A
+/// term is `field:value` or free text; values may be quoted, and a trailing
+/// `*` matches a prefix. Matching ignores case.
+///
+/// Searches that use anything else, such as a field the polled events
+/// don't carry or a date range, are refused by [`LogFilter::parse`] rather
+/// than matched loosely.
+#[derive(Debug, Default)]
+pub struct LogFilter {
+    /// `None` matches every event.
+    expr: Option<Expr>,
+}
+
+#[derive(Debug)]
+enum Expr {
+    Any(Vec<Expr>),
+    All(Vec<Expr>),
+    Not(Box<Expr>),
+    Term(Term),
+}
+
+#[derive(Debug)]
+struct Term {
+    field: Option<String>,
+    value: String,
+    prefix: bool,
+}
+
+#[derive(Debug, PartialEq)]
+enum Token {
+    Open,
+    Close,
+    Word(String),
+}
+
+type Tokens = std::iter::Peekable<std::vec::IntoIter<Token>>;
+
+impl LogFilter {
+    /// Parses a search, or explains why live tail can't apply it.
+    pub fn parse(q: &str) -> Result<Self, String> {
+        let mut tokens = tokenize(q)?.into_iter().peekable();
+        if tokens.peek().is_none() {
+            return Ok(Self::default());
+        }
+        let expr = parse_or(&mut tokens)?;
+        match tokens.next() {
+            None => Ok(Self { expr: Some(expr) }),
+            Some(_) => Err("a `)` has no matching `(`".to_string()),
+        }
+    }
+
+    pub fn matches(&self, event: &LogEvent) -> bool {
+        self.matches_fields(|field| event_field(event, field))
+    }
+
+    fn matches_fields<'a>(&self, field: impl Fn(&str) -> Option<&'a str>) -> bool {
+        self.expr.as_ref().is_none_or(|expr| expr.matches(&field))
+    }
+}
+
+fn is_operator(token: Option<&Token>, operator: &str) -> bool {
+    matches!(token, Some(Token::Word(word)) if word == operator)
+}
+
+fn parse_or(tokens: &mut Tokens) -> Result<Expr, String> {
+    let mut any_of = vec![parse_and(tokens)?];
+    while tokens.next_if(|t| is_operator(Some(t), "OR")).is_some() {
+        any_of.push(parse_and(tokens)?);
+    }
+    Ok(match any_of.len() {
+        1 => any_of.remove(0),
+        _ => Expr::Any(any_of),
+    })
+}
+
+fn parse_and(tokens: &mut Tokens) -> Result<Expr, String> {
+    let mut all_of = vec![parse_unary(tokens)?];
+    loop {
+        match tokens.peek() {
+            None | Some(Token::Close) => break,
+            next if is_operator(next, "OR") => break,
+            next if is_operator(next, "AND") => {
+                tokens.next();
+            }
+            _ => {}
+        }
+        all_of.push(parse_unary(tokens)?);
+    }
+    Ok(match all_of.len() {
+        1 => all_of.remove(0),
+        _ => Expr::All(all_of),
+    })
+}
+
+fn parse_unary(tokens: &mut Tokens) -> Result<Expr, String> {
+    match tokens.next() {
+        Some(Token::Open) => {
+            let group = parse_or(tokens)?;
+            match tokens.next() {
+                Some(Token::Close) => Ok(group),
+                _ => Err("a `(` is never closed".to_string()),
+            }
+        }
+        Some(Token::Close) => Err("a `)` has no matching `(`".to_string()),
+        Some(Token::Word(word)) => match word.as_str() {
+            "NOT" | "-" => Ok(Expr::Not(Box::new(parse_unary(tokens)?))),
+            "AND" | "OR" => Err(format!("`{}` needs a term on each side", word)),
+            _ => match word.strip_prefix('-') {
+                Some(rest) => Ok(Expr::Not(Box::new(Expr::Term(Term::parse(rest)?)))),
+                None => Ok(Expr::Term(Term::parse(&word)?)),
+            },
+        },
+        None => Err("the search ends where a term was expected".to_string()),
+    }
+}
+
+impl Expr {
+    fn matches<'a>(&self, field: &impl Fn(&str) -> Option<&'a str>) -> bool {
+        match self {
+            Expr::Any(any_of) => any_of.iter().any(|expr| expr.matches(field)),
+            Expr::All(all_of) => all_of.iter().all(|expr| expr.matches(field)),
+            Expr::Not(expr) => !expr.matches(field),
+            Expr::Term(term) => term.matches(field),
+        }
+    }
+}
+
+impl Term {
+    fn parse(token: &str) -> Result<Self, String> {
+        let (field, value) = match token.split_once(':') {
+            Some((field, value)) if !field.is_empty() && !field.starts_with('"') => {
+                let field = field.to_ascii_lowercase();
+                if !FIELDS.contains(&field.as_str()) {
+                    return Err(format!("`{}` isn't a field live tail can match", field));
+                }
+                (Some(field), value)
+            }
+            _ => (None, token),
+        };
+        if value.contains(['[', ']', '{', '}']) {
+            return Err("range searches aren't supported".to_string());
+        }
+        let value = value.trim_matches('"');
+        let (value, prefix) = match value.strip_suffix('*') {
+            Some(value) => (value, true),
+            None => (value, false),
+        };
+        if value.is_empty() && !prefix {
+            return Err(format!("`{}` has no value to match", token));
+        }
+        Ok(Self {
+            field,
+            value: value.to_lowercase(),
+            prefix,
+        })
+    }
+
+    fn matches<'a>(&self, field: &impl Fn(&str) -> Option<&'a str>) -> bool {
+        match &self.field {
+            Some(name) => field(name).is_some_and(|value| self.matches_value(value)),
+            // Free text matches anywhere in the event's text fields.
+            None => TEXT_FIELDS
+                .iter()
+                .filter_map(|name| field(name))
+                .any(|value| value.to_lowercase().contains(&self.value)),
+        }
+    }
+
+    fn matches_value(&self, value: &str) -> bool {
+        let value = value.to_lowercase();
+        if self.prefix {
+            value.starts_with(&self.value)
+        } else {
+            value == self.value
+        }
+    }
+}
+
+const TEXT_FIELDS: &[&str] = &[
+    "type",
+    "description",
+    "user_name",
+    "user_id",
+    "client_name",
+    "ip",
+];
+
+/// Every field [`event_field`] can read.
+const FIELDS: &[&str] = &[
+    "type",
+    "description",
+    "user_name",
+    "user_id",
+    "client_name",
+    "ip",
+    "date",
+    "log_id",
+];
+
+fn event_field<'a>(event: &'a LogEvent, field: &str) -> Option<&'a str> {
+    match field {
+        "type" => Some(event.event_type.as_str()),
+        "description" => event.description.as_deref(),
+        "user_name" => event.user_name.as_deref(),
+        "user_id" => event.user_id.as_deref(),
+        "client_name" => event.client_name.as_deref(),
+        "ip" => event.ip.as_deref(),
+        "date" => event.date.as_deref(),
+        "log_id" => event.log_id.as_deref(),
+        _ => None,
+    }
+}
+
+/// Splits on whitespace and parentheses outside double quotes.
+fn tokenize(q: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut current = String::new();
+    let mut quoted = false;
+
+    for c in q.chars() {
+        match c {
+            '"' => {
+                quoted = !quoted;
+                current.push(c);
+            }
+            '(' | ')' if !quoted => {
+                if !current.is_empty() {
+                    tokens.push(Token::Word(std::mem::take(&mut current)));
+                }
+                tokens.push(if c == '(' { Token::Open } else { Token::Close });
+            }
+            c if c.is_whitespace() && !quoted => {
+                if !current.is_empty() {
+                    tokens.push(Token::Word(std::mem::take(&mut current)));
+                }
+            }
+            c => current.push(c),
+        }
+    }
+    if quoted {
+        return Err("a `\"` is never closed".to_string());
+    }
+    if !current.is_empty() {
+        tokens.push(Token::Word(current));
+    }
+    Ok(tokens)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+
+    fn matches(q: &str, event: &[(&str, &str)]) -> bool {
+        let event: HashMap<&str, &str> = event.iter().copied().collect();
+        LogFilter::parse(q)
+            .unwrap()
+            .matches_fields(|field| event.get(field).copied())
+    }
+
+    const FAILED_LOGIN: &[(&str, &str)] = &[
+        ("type", "f"),
+        ("description", "Wrong email or password."),
+        ("user_name", "ada@example.com"),
+        ("user_id", "auth0|123"),
+        ("client_name", "Console"),
+        ("ip", "10.0.0.1"),
+    ];
+
+    #[test]
+    fn empty_search_matches_everything() {
+        assert!(matches("", FAILED_LOGIN));
+        assert!(matches("  ", &[]));
+    }
+
+    #[test]
+    fn field_terms_match_exactly_or_by_prefix() {
+        assert!(matches("type:f", FAILED_LOGIN));
+        assert!(!matches("type:fp", FAILED_LOGIN));
+        assert!(matches("user_id:\"auth0|123\"", FAILED_LOGIN));
+        assert!(matches("user_name:ADA*", FAILED_LOGIN));
+        assert!(!matches("user_name:bob*", FAILED_LOGIN));
+    }
+
+    #[test]
+    fn free_text_searches_text_fields() {
+        assert!(matches("password", FAILED_LOGIN));
+        assert!(matches("\"wrong email\"", FAILED_LOGIN));
+        assert!(!matches("blocked", FAILED_LOGIN));
+    }
+
+    #[test]
+    fn terms_combine_with_and_or_not() {
+        assert!(matches("type:f AND ip:10.0.0.1", FAILED_LOGIN));
+        assert!(matches("type:f client_name:console", FAILED_LOGIN));
+        assert!(!matches("type:f AND ip:10.0.0.2", FAILED_LOGIN));
+        assert!(matches("type:s OR type:f", FAILED_LOGIN));
+        assert!(matches("(type:s OR type:f)", FAILED_LOGIN));
+        assert!(!matches("NOT type:f", FAILED_LOGIN));
+        assert!(matches("-type:s", FAILED_LOGIN));
+        assert!(!matches("type:f -user_name:ada*", FAILED_LOGIN));
+    }
+
+    #[test]
+    fn parentheses_group_terms() {
+        // Without grouping this would read as `type:s OR (type:f AND ip:10.0.0.2)`.
+        assert!(!matches("(type:s OR type:f) AND ip:10.0.0.2", FAILED_LOGIN));
+        assert!(matches("type:s OR type:f AND ip:10.0.0.1", FAILED_LOGIN));
+        assert!(matches("(type:s OR type:f) (ip:10.0.0.1)", FAILED_LOGIN));
+        assert!(!matches("NOT (type:s OR type:f)", FAILED_LOGIN));
+        assert!(!matches("-(type:f)", FAILED_LOGIN));
+        assert!(matches(
+            "type:f AND (NOT (ip:10.0.0.2 OR user_name:bob*))",
+            FAILED_LOGIN
+        ));
+        assert!(matches(
+            "\"(wrong email\"",
+            &[("description", "(Wrong email")]
+        ));
+    }
+
+    #[test]
+    fn searches_live_tail_cannot_apply_are_refused() {
+        let refused = |q: &str| LogFilter::parse(q).unwrap_err();
+        assert_eq!(
+            refused("connection:db"),
+            "`connection` isn't a field live tail can match"
+        );
+        assert_eq!(
+            refused("date:[2024-01-01 TO *]"),
+            "range searches aren't supported"
+        );
+        assert_eq!(refused("(type:f"), "a `(` is never closed");
+        assert_eq!(refused("type:f)"), "a `)` has no matching `(`");
+        assert_eq!(
+            refused("type:f OR"),
+            "the search ends where a term was expected"
+        );
+        assert_eq!(refused("OR type:f"), "`OR` needs a term on each side");
+        assert_eq!(refused("\"wrong email"), "a `\"` is never closed");
+        assert_eq!(refused("type:"), "`type:` has no value to match");
+    }
+}
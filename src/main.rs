@@ -1,15 +1,25 @@
+mod audit;
+mod auth;
+mod cache;
+mod client;
 mod config;
 mod errors;
 mod helpers;
+mod log_tail;
 mod routes;
 mod state;
 mod templates;
+mod tenant;
+#[cfg(test)]
+mod test_support;
 
-use auth0_mgmt_api::ManagementClient;
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
 
+use crate::audit::AuditLog;
+use crate::auth::Auth;
 use crate::config::Config;
 use crate::state::build_app;
+use crate::tenant::Tenants;
 
 fn init_tracing() {
     tracing_subscriber::registry()
@@ -25,13 +35,11 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     let config = Config::from_env()?;
 
-    let client = ManagementClient::builder()
-        .domain(&config.auth0_domain)
-        .client_id(&config.auth0_client_id)
-        .client_secret(&config.auth0_client_secret)
-        .build()?;
+    let tenants = Tenants::build(&config)?;
+    let auth = Auth::discover(&config).await?;
+    let audit = AuditLog::open(config.audit_log_path.clone()).await?;
 
-    let app = build_app(client);
+    let app = build_app(tenants, auth, audit);
 
     let listener = tokio::net::TcpListener::bind(config.bind_addr).await?;
     tracing::info!("listening on http://{}", listener.local_addr()?);
@@ -0,0 +1,974 @@
+use std::collections::HashMap;
+
+use askama::Template;
+use auth0_mgmt_api::{
+    types::{
+        resource_servers::{
+            CreateResourceServerRequest, ResourceServer, ResourceServerScope,
+            UpdateResourceServerRequest,
+        },
+        roles::{Permission, Role},
+    },
+    ResourceServerId,
+};
+use axum::{
+    extract::{Path, Query, State},
+    response::{IntoResponse, Redirect, Response},
+};
+use axum_extra::extract::Form;
+use futures::stream::{self, StreamExt, TryStreamExt};
+use htmx_form_errors::FormErrors;
+use serde::Deserialize;
+use validator::{Validate, ValidationError, ValidationErrors};
+
+use crate::audit::{Action, FieldChange};
+use crate::auth::{Operator, RequireAdmin, RequireViewer};
+use crate::errors::{AppError, AppResult};
+use crate::helpers::empty_as_none;
+use crate::routes::helpers::{
+    confirm_error, fetch_apis, fetch_grants, fetch_role_permissions, invalid, GrantsOf,
+};
+use crate::state::AppState;
+use crate::templates::{render, ErrorBannerTemplate};
+use crate::tenant::Tenant;
+
+/// Algorithms Auth0 signs access tokens with, in the order the dashboard
+/// lists them.
+const SIGNING_ALGS: &[&str] = &["RS256", "HS256", "PS256"];
+/// Auth0 caps access token lifetimes at 30 days, and those issued through
+/// the implicit and hybrid flows at one day.
+const MAX_TOKEN_LIFETIME: u32 = 2592000;
+const MAX_TOKEN_LIFETIME_FOR_WEB: u32 = 86400;
+
+#[derive(Template)]
+#[template(path = "apis/list.html")]
+struct ListTemplate {
+    operator: Operator,
+    tenant: Tenant,
+    apis: Vec<ResourceServer>,
+    error: Option<AppError>,
+    form: CreateForm,
+    errors: FormErrors,
+}
+
+#[derive(Template)]
+#[template(path = "apis/create_form.html")]
+struct CreateFormTemplate {
+    tenant: Tenant,
+    form: CreateForm,
+    errors: FormErrors,
+}
+
+#[derive(Template)]
+#[template(path = "apis/detail.html")]
+struct DetailTemplate {
+    operator: Operator,
+    tenant: Tenant,
+    api: ResourceServer,
+    form: SettingsForm,
+    scope: ScopeForm,
+    errors: FormErrors,
+}
+
+#[derive(Template)]
+#[template(path = "apis/usage.html")]
+struct UsageTemplate {
+    tenant: Tenant,
+    /// Applications with a client grant for the API, with the scopes granted.
+    grants: Vec<GrantUsage>,
+    scopes: Vec<ScopeUsage>,
+}
+
+struct GrantUsage {
+    client_id: String,
+    name: String,
+    scopes: Vec<String>,
+}
+
+/// Who can get a token carrying a scope: users through a role, and
+/// applications through a client grant.
+struct ScopeUsage {
+    value: String,
+    roles: Vec<(String, String)>,
+    clients: Vec<(String, String)>,
+}
+
+#[derive(Deserialize)]
+pub struct ApiPath {
+    id: String,
+}
+
+pub async fn list(RequireViewer(operator): RequireViewer, tenant: Tenant) -> AppResult<Response> {
+    let (apis, error) = match fetch_apis(&tenant).await {
+        Ok(apis) => (apis, None),
+        Err(e) => {
+            tracing::error!(error = ?e, "failed to list APIs");
+            (Vec::new(), Some(e.into()))
+        }
+    };
+
+    render(ListTemplate {
+        operator,
+        tenant,
+        apis,
+        error,
+        form: CreateForm::default(),
+        errors: FormErrors::new(),
+    })
+}
+
+async fn fetch_api(tenant: &Tenant, id: &str) -> Result<ResourceServer, auth0_mgmt_api::Error> {
+    tenant
+        .client
+        .get(|c| async move { c.resource_servers().get(ResourceServerId::new(id)).await })
+        .await
+}
+
+fn validate_signing_alg(alg: &str) -> Result<(), ValidationError> {
+    if SIGNING_ALGS.contains(&alg) {
+        Ok(())
+    } else {
+        Err(invalid(
+            "signing_alg",
+            "Unknown signing algorithm".to_string(),
+        ))
+    }
+}
+
+/// Identifiers become the `aud` of access tokens and cannot be changed
+/// later, so they are checked more strictly than Auth0 does.
+fn validate_identifier(identifier: &str) -> Result<(), ValidationError> {
+    if identifier.trim() != identifier || identifier.chars().any(char::is_whitespace) {
+        return Err(invalid(
+            "identifier",
+            "Identifier cannot contain spaces".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+#[derive(Clone, Deserialize, Validate)]
+pub struct CreateForm {
+    #[serde(default)]
+    #[validate(length(min = 1, max = 200, message = "Name must be 1 to 200 characters"))]
+    name: String,
+    #[serde(default)]
+    #[validate(
+        length(min = 1, max = 600, message = "Identifier is required"),
+        custom(function = "validate_identifier")
+    )]
+    identifier: String,
+    #[serde(default)]
+    #[validate(custom(function = "validate_signing_alg"))]
+    signing_alg: String,
+    #[serde(default, deserialize_with = "empty_as_none")]
+    #[validate(range(
+        min = 1,
+        max = 2592000,
+        message = "Must be between 1 second and 30 days"
+    ))]
+    token_lifetime: Option<u32>,
+}
+
+impl Default for CreateForm {
+    fn default() -> Self {
+        Self {
+            name: String::new(),
+            identifier: String::new(),
+            signing_alg: SIGNING_ALGS[0].to_string(),
+            token_lifetime: Some(MAX_TOKEN_LIFETIME_FOR_WEB),
+        }
+    }
+}
+
+impl CreateForm {
+    pub fn signing_algs(&self) -> &'static [&'static str] {
+        SIGNING_ALGS
+    }
+}
+
+pub async fn create(
+    State(state): State<AppState>,
+    RequireAdmin(operator): RequireAdmin,
+    tenant: Tenant,
+    Form(form): Form<CreateForm>,
+) -> AppResult<Response> {
+    if let Err(e) = form.validate() {
+        return render(CreateFormTemplate {
+            tenant,
+            form,
+            errors: FormErrors::from(e),
+        });
+    }
+
+    let name = form.name.trim().to_string();
+    let lifetime = form.token_lifetime.map(|v| v.to_string());
+    let changes = vec![
+        FieldChange::new("name", None, Some(&name)),
+        FieldChange::new("identifier", None, Some(&form.identifier)),
+        FieldChange::new("signing_alg", None, Some(&form.signing_alg)),
+        FieldChange::new("token_lifetime", None, lifetime.as_deref()),
+    ];
+    let request = CreateResourceServerRequest {
+        name: Some(name),
+        identifier: form.identifier.clone(),
+        signing_alg: Some(form.signing_alg.clone()),
+        token_lifetime: form.token_lifetime,
+        ..Default::default()
+    };
+
+    let result = tenant
+        .client
+        .send(|c| async move { c.resource_servers().create(request).await })
+        .await;
+    let target = match &result {
+        Ok(api) => api.id.clone(),
+        Err(_) => form.identifier.clone(),
+    };
+    let error = result.as_ref().err().map(|e| e.to_string());
+    state
+        .audit
+        .record(
+            &operator,
+            &tenant,
+            Action::CreateApi,
+            &target,
+            changes,
+            error,
+        )
+        .await?;
+
+    match result {
+        Ok(api) => {
+            let detail = tenant.path(&format!("/apis/{}", api.id));
+            Ok([("hx-redirect", detail)].into_response())
+        }
+        Err(e) => {
+            tracing::error!(error = ?e, "failed to create API");
+            let mut errors = FormErrors::new();
+            errors.add_base(&format!(
+                "Failed to create API: {}",
+                AppError::from(e).detail()
+            ));
+            render(CreateFormTemplate {
+                tenant,
+                form,
+                errors,
+            })
+        }
+    }
+}
+
+#[derive(Clone, Deserialize, Validate)]
+pub struct SettingsForm {
+    #[serde(default)]
+    #[validate(length(min = 1, max = 200, message = "Name must be 1 to 200 characters"))]
+    name: String,
+    #[serde(default)]
+    #[validate(custom(function = "validate_signing_alg"))]
+    signing_alg: String,
+    #[serde(default, deserialize_with = "empty_as_none")]
+    #[validate(range(
+        min = 1,
+        max = 2592000,
+        message = "Must be between 1 second and 30 days"
+    ))]
+    token_lifetime: Option<u32>,
+    #[serde(default, deserialize_with = "empty_as_none")]
+    #[validate(range(min = 1, max = 86400, message = "Must be between 1 second and one day"))]
+    token_lifetime_for_web: Option<u32>,
+    allow_offline_access: Option<String>,
+    skip_consent_for_verifiable_first_party_clients: Option<String>,
+}
+
+impl SettingsForm {
+    fn from_api(api: &ResourceServer) -> Self {
+        let flag = |v: Option<bool>| v.unwrap_or(false).then(|| "on".to_string());
+        Self {
+            name: api.name.clone().unwrap_or_default(),
+            signing_alg: api
+                .signing_alg
+                .clone()
+                .unwrap_or_else(|| SIGNING_ALGS[0].to_string()),
+            token_lifetime: api.token_lifetime,
+            token_lifetime_for_web: api.token_lifetime_for_web,
+            allow_offline_access: flag(api.allow_offline_access),
+            skip_consent_for_verifiable_first_party_clients: flag(
+                api.skip_consent_for_verifiable_first_party_clients,
+            ),
+        }
+    }
+
+    /// The web lifetime cannot outlast the normal one; checked here because
+    /// it spans two fields.
+    fn validate_all(&self) -> Result<(), ValidationErrors> {
+        let mut errors = self.validate().err().unwrap_or_else(ValidationErrors::new);
+        if let (Some(lifetime), Some(web)) = (self.token_lifetime, self.token_lifetime_for_web) {
+            if web > lifetime {
+                errors.add(
+                    "token_lifetime_for_web",
+                    invalid(
+                        "token_lifetime_for_web",
+                        "Cannot be longer than the token lifetime".to_string(),
+                    ),
+                );
+            }
+        }
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+
+    pub fn signing_algs(&self) -> &'static [&'static str] {
+        SIGNING_ALGS
+    }
+
+    pub fn max_token_lifetime(&self) -> u32 {
+        MAX_TOKEN_LIFETIME
+    }
+
+    pub fn max_token_lifetime_for_web(&self) -> u32 {
+        MAX_TOKEN_LIFETIME_FOR_WEB
+    }
+}
+
+#[derive(Clone, Deserialize, Default, Validate)]
+pub struct ScopeForm {
+    #[serde(default)]
+    #[validate(
+        length(min = 1, max = 280, message = "Scope must be 1 to 280 characters"),
+        custom(function = "validate_scope_value")
+    )]
+    value: String,
+    #[serde(default)]
+    #[validate(length(max = 500, message = "Description must be at most 500 characters"))]
+    description: String,
+}
+
+/// Scopes are space-separated in tokens, so a space would split one in two.
+fn validate_scope_value(value: &str) -> Result<(), ValidationError> {
+    if value.chars().any(char::is_whitespace) {
+        Err(invalid("value", "Scope cannot contain spaces".to_string()))
+    } else {
+        Ok(())
+    }
+}
+
+fn detail_page(
+    operator: Operator,
+    tenant: Tenant,
+    api: ResourceServer,
+    form: SettingsForm,
+    scope: ScopeForm,
+    errors: FormErrors,
+) -> AppResult<Response> {
+    render(DetailTemplate {
+        operator,
+        tenant,
+        api,
+        form,
+        scope,
+        errors,
+    })
+}
+
+pub async fn get(
+    RequireViewer(operator): RequireViewer,
+    tenant: Tenant,
+    Path(ApiPath { id }): Path<ApiPath>,
+) -> AppResult<Response> {
+    let api = fetch_api(&tenant, &id).await.map_err(|e| {
+        tracing::warn!(error = ?e, %id, "failed to get API");
+        AppError::from(e)
+    })?;
+
+    let form = SettingsForm::from_api(&api);
+    detail_page(
+        operator,
+        tenant,
+        api,
+        form,
+        ScopeForm::default(),
+        FormErrors::new(),
+    )
+}
+
+/// The Auth0 Management API and other system APIs can be looked at but
+/// not changed.
+fn refuse_system(api: &ResourceServer) -> Option<FormErrors> {
+    api.is_system.unwrap_or(false).then(|| {
+        let mut errors = FormErrors::new();
+        errors.add_base("System APIs cannot be changed");
+        errors
+    })
+}
+
+pub async fn update(
+    State(state): State<AppState>,
+    RequireAdmin(operator): RequireAdmin,
+    tenant: Tenant,
+    Path(ApiPath { id }): Path<ApiPath>,
+    Form(form): Form<SettingsForm>,
+) -> AppResult<Response> {
+    let api = fetch_api(&tenant, &id).await.map_err(AppError::from)?;
+
+    if let Some(errors) = refuse_system(&api) {
+        let current = SettingsForm::from_api(&api);
+        return detail_page(operator, tenant, api, current, ScopeForm::default(), errors);
+    }
+    if let Err(e) = form.validate_all() {
+        return detail_page(
+            operator,
+            tenant,
+            api,
+            form,
+            ScopeForm::default(),
+            FormErrors::from(e),
+        );
+    }
+
+    let current = SettingsForm::from_api(&api);
+    let mut changes = Vec::new();
+    let mut request = UpdateResourceServerRequest::default();
+
+    let name = form.name.trim().to_string();
+    if name != current.name {
+        changes.push(FieldChange::new("name", Some(&current.name), Some(&name)));
+        request.name = Some(name);
+    }
+    if form.signing_alg != current.signing_alg {
+        changes.push(FieldChange::new(
+            "signing_alg",
+            Some(&current.signing_alg),
+            Some(&form.signing_alg),
+        ));
+        request.signing_alg = Some(form.signing_alg.clone());
+    }
+
+    let lifetime = |v: Option<u32>| v.map(|v| v.to_string());
+    if form.token_lifetime.is_some() && form.token_lifetime != current.token_lifetime {
+        changes.push(FieldChange::new(
+            "token_lifetime",
+            lifetime(current.token_lifetime).as_deref(),
+            lifetime(form.token_lifetime).as_deref(),
+        ));
+        request.token_lifetime = form.token_lifetime;
+    }
+    if form.token_lifetime_for_web.is_some()
+        && form.token_lifetime_for_web != current.token_lifetime_for_web
+    {
+        changes.push(FieldChange::new(
+            "token_lifetime_for_web",
+            lifetime(current.token_lifetime_for_web).as_deref(),
+            lifetime(form.token_lifetime_for_web).as_deref(),
+        ));
+        request.token_lifetime_for_web = form.token_lifetime_for_web;
+    }
+
+    let flags = [
+        (
+            "allow_offline_access",
+            current.allow_offline_access.is_some(),
+            form.allow_offline_access.is_some(),
+        ),
+        (
+            "skip_consent_for_verifiable_first_party_clients",
+            current
+                .skip_consent_for_verifiable_first_party_clients
+                .is_some(),
+            form.skip_consent_for_verifiable_first_party_clients
+                .is_some(),
+        ),
+    ];
+    for (field, before, after) in flags {
+        if before == after {
+            continue;
+        }
+        changes.push(FieldChange::new(
+            field,
+            Some(&before.to_string()),
+            Some(&after.to_string()),
+        ));
+        match field {
+            "allow_offline_access" => request.allow_offline_access = Some(after),
+            _ => request.skip_consent_for_verifiable_first_party_clients = Some(after),
+        }
+    }
+
+    if changes.is_empty() {
+        return detail_page(
+            operator,
+            tenant,
+            api,
+            current,
+            ScopeForm::default(),
+            FormErrors::new(),
+        );
+    }
+
+    let api_id = ResourceServerId::new(&id);
+    let result = tenant
+        .client
+        .send(|c| async move { c.resource_servers().update(api_id, request).await })
+        .await;
+    let error = result.as_ref().err().map(|e| e.to_string());
+    state
+        .audit
+        .record(&operator, &tenant, Action::UpdateApi, &id, changes, error)
+        .await?;
+
+    match result {
+        Ok(updated) => {
+            let form = SettingsForm::from_api(&updated);
+            detail_page(
+                operator,
+                tenant,
+                updated,
+                form,
+                ScopeForm::default(),
+                FormErrors::new(),
+            )
+        }
+        Err(e) => {
+            tracing::error!(error = ?e, %id, "failed to update API");
+            let mut errors = FormErrors::new();
+            errors.add_base(&format!(
+                "Failed to update API: {}",
+                AppError::from(e).detail()
+            ));
+            detail_page(operator, tenant, api, form, ScopeForm::default(), errors)
+        }
+    }
+}
+
+#[derive(Deserialize)]
+pub struct DeleteQuery {
+    #[serde(default)]
+    confirm: String,
+}
+
+/// Deleting an API invalidates every token issued for it, so the operator
+/// has to type its identifier.
+pub async fn delete(
+    State(state): State<AppState>,
+    RequireAdmin(operator): RequireAdmin,
+    tenant: Tenant,
+    Path(ApiPath { id }): Path<ApiPath>,
+    Query(query): Query<DeleteQuery>,
+) -> AppResult<Response> {
+    let api = fetch_api(&tenant, &id).await.map_err(AppError::from)?;
+
+    let refusal = if api.is_system.unwrap_or(false) {
+        Some("System APIs cannot be deleted".to_string())
+    } else if query.confirm.trim() != api.identifier {
+        Some("Type the API identifier to confirm".to_string())
+    } else {
+        None
+    };
+    if let Some(message) = refusal {
+        let form = SettingsForm::from_api(&api);
+        return detail_page(
+            operator,
+            tenant,
+            api,
+            form,
+            ScopeForm::default(),
+            confirm_error(message),
+        );
+    }
+
+    let changes = vec![
+        FieldChange::new("name", api.name.as_deref(), None),
+        FieldChange::new("identifier", Some(&api.identifier), None),
+    ];
+    let api_id = ResourceServerId::new(&id);
+    let result = tenant
+        .client
+        .send(|c| async move { c.resource_servers().delete(api_id).await })
+        .await;
+    let error = result.as_ref().err().map(|e| e.to_string());
+    state
+        .audit
+        .record(&operator, &tenant, Action::DeleteApi, &id, changes, error)
+        .await?;
+
+    match result {
+        Ok(_) => {
+            tenant.cache.role_permissions.invalidate();
+            Ok(Redirect::to(&tenant.path("/apis")).into_response())
+        }
+        Err(e) => {
+            tracing::error!(error = ?e, %id, "failed to delete API");
+            let errors = confirm_error(format!(
+                "Failed to delete API: {}",
+                AppError::from(e).detail()
+            ));
+            let form = SettingsForm::from_api(&api);
+            detail_page(operator, tenant, api, form, ScopeForm::default(), errors)
+        }
+    }
+}
+
+/// One scope to remove, sent as a query parameter by `hx-delete`.
+#[derive(Deserialize)]
+pub struct ScopeQuery {
+    value: String,
+}
+
+pub async fn add_scope(
+    State(state): State<AppState>,
+    RequireAdmin(operator): RequireAdmin,
+    tenant: Tenant,
+    Path(ApiPath { id }): Path<ApiPath>,
+    Form(scope): Form<ScopeForm>,
+) -> AppResult<Response> {
+    let api = fetch_api(&tenant, &id).await.map_err(AppError::from)?;
+    let form = SettingsForm::from_api(&api);
+
+    if let Some(errors) = refuse_system(&api) {
+        return detail_page(operator, tenant, api, form, scope, errors);
+    }
+    let mut scopes = api.scopes.clone().unwrap_or_default();
+    let mut errors = scope.validate().err().unwrap_or_else(ValidationErrors::new);
+    if scopes.iter().any(|s| s.value == scope.value) {
+        errors.add(
+            "value",
+            invalid("value", format!("{} already exists", scope.value)),
+        );
+    }
+    if !errors.is_empty() {
+        return detail_page(operator, tenant, api, form, scope, FormErrors::from(errors));
+    }
+
+    scopes.push(ResourceServerScope {
+        value: scope.value.clone(),
+        description: Some(scope.description.trim().to_string()).filter(|d| !d.is_empty()),
+    });
+    let changes = vec![FieldChange::new("scopes", None, Some(&scope.value))];
+    let result = update_scopes(&tenant, &id, scopes).await;
+    let error = result.as_ref().err().map(|e| e.to_string());
+    state
+        .audit
+        .record(&operator, &tenant, Action::AddApiScope, &id, changes, error)
+        .await?;
+
+    match result {
+        Ok(updated) => {
+            let form = SettingsForm::from_api(&updated);
+            detail_page(
+                operator,
+                tenant,
+                updated,
+                form,
+                ScopeForm::default(),
+                FormErrors::new(),
+            )
+        }
+        Err(e) => {
+            tracing::error!(error = ?e, %id, "failed to add API scope");
+            let mut errors = FormErrors::new();
+            errors.add_base(&format!(
+                "Failed to add scope: {}",
+                AppError::from(e).detail()
+            ));
+            detail_page(operator, tenant, api, form, scope, errors)
+        }
+    }
+}
+
+pub async fn remove_scope(
+    State(state): State<AppState>,
+    RequireAdmin(operator): RequireAdmin,
+    tenant: Tenant,
+    Path(ApiPath { id }): Path<ApiPath>,
+    Query(query): Query<ScopeQuery>,
+) -> AppResult<Response> {
+    let api = fetch_api(&tenant, &id).await.map_err(AppError::from)?;
+    let form = SettingsForm::from_api(&api);
+
+    if let Some(errors) = refuse_system(&api) {
+        return detail_page(operator, tenant, api, form, ScopeForm::default(), errors);
+    }
+    let before = api.scopes.clone().unwrap_or_default();
+    let scopes: Vec<ResourceServerScope> = before
+        .iter()
+        .filter(|s| s.value != query.value)
+        .cloned()
+        .collect();
+    if scopes.len() == before.len() {
+        return detail_page(
+            operator,
+            tenant,
+            api,
+            form,
+            ScopeForm::default(),
+            FormErrors::new(),
+        );
+    }
+
+    let changes = vec![FieldChange::new("scopes", Some(&query.value), None)];
+    let result = update_scopes(&tenant, &id, scopes).await;
+    let error = result.as_ref().err().map(|e| e.to_string());
+    state
+        .audit
+        .record(
+            &operator,
+            &tenant,
+            Action::RemoveApiScope,
+            &id,
+            changes,
+            error,
+        )
+        .await?;
+
+    match result {
+        Ok(updated) => {
+            // Auth0 drops the scope from roles that had it.
+            tenant.cache.role_permissions.invalidate();
+            let form = SettingsForm::from_api(&updated);
+            detail_page(
+                operator,
+                tenant,
+                updated,
+                form,
+                ScopeForm::default(),
+                FormErrors::new(),
+            )
+        }
+        Err(e) => {
+            tracing::error!(error = ?e, %id, "failed to remove API scope");
+            let mut errors = FormErrors::new();
+            errors.add_base(&format!(
+                "Failed to remove scope: {}",
+                AppError::from(e).detail()
+            ));
+            detail_page(operator, tenant, api, form, ScopeForm::default(), errors)
+        }
+    }
+}
+
+/// Auth0 replaces the scope list wholesale, so callers pass the full list
+/// built from a fresh read.
+async fn update_scopes(
+    tenant: &Tenant,
+    id: &str,
+    scopes: Vec<ResourceServerScope>,
+) -> Result<ResourceServer, auth0_mgmt_api::Error> {
+    let request = UpdateResourceServerRequest {
+        scopes: Some(scopes),
+        ..Default::default()
+    };
+    let api_id = ResourceServerId::new(id);
+    tenant
+        .client
+        .send(|c| async move { c.resource_servers().update(api_id, request).await })
+        .await
+}
+
+/// Loaded into the API page after it renders, because it needs the
+/// permissions of every role: at least one Management API call per role,
+/// cached afterwards.
+pub async fn usage(
+    RequireViewer(_operator): RequireViewer,
+    tenant: Tenant,
+    Path(ApiPath { id }): Path<ApiPath>,
+) -> AppResult<Response> {
+    match scope_usage(&tenant, &id).await {
+        Ok((grants, scopes)) => render(UsageTemplate {
+            tenant,
+            grants,
+            scopes,
+        }),
+        Err(e) => {
+            tracing::error!(error = ?e, %id, "failed to load API usage");
+            render(ErrorBannerTemplate { error: e.into() })
+        }
+    }
+}
+
+async fn scope_usage(
+    tenant: &Tenant,
+    id: &str,
+) -> Result<(Vec<GrantUsage>, Vec<ScopeUsage>), auth0_mgmt_api::Error> {
+    let api = fetch_api(tenant, id).await?;
+    let (client_grants, clients, roles) = tokio::try_join!(
+        fetch_grants(tenant, GrantsOf::Api(&api.identifier)),
+        tenant.cache.clients(&tenant.client),
+        tenant.cache.roles(&tenant.client),
+    )?;
+    let permissions = tenant
+        .cache
+        .role_permissions
+        .get_or_fetch(all_role_permissions(tenant, &roles))
+        .await?;
+
+    let client_name = |client_id: &str| {
+        clients
+            .iter()
+            .find(|c| c.client_id == client_id)
+            .and_then(|c| c.name.clone())
+            .unwrap_or_else(|| client_id.to_string())
+    };
+    let grants: Vec<GrantUsage> = client_grants
+        .into_iter()
+        .map(|grant| GrantUsage {
+            name: client_name(&grant.client_id),
+            client_id: grant.client_id,
+            scopes: grant.scope,
+        })
+        .collect();
+
+    let mut role_scopes = Vec::new();
+    for role in roles.iter() {
+        let scopes: Vec<&str> = permissions
+            .get(&role.id)
+            .into_iter()
+            .flatten()
+            .filter(|p| p.resource_server_identifier == api.identifier)
+            .map(|p| p.permission_name.as_str())
+            .collect();
+        if !scopes.is_empty() {
+            let name = role.name.clone().unwrap_or_else(|| role.id.clone());
+            role_scopes.push((role.id.clone(), name, scopes));
+        }
+    }
+
+    let scopes = api
+        .scopes
+        .unwrap_or_default()
+        .into_iter()
+        .map(|scope| ScopeUsage {
+            roles: role_scopes
+                .iter()
+                .filter(|(_, _, scopes)| scopes.contains(&scope.value.as_str()))
+                .map(|(id, name, _)| (id.clone(), name.clone()))
+                .collect(),
+            clients: grants
+                .iter()
+                .filter(|g| g.scopes.contains(&scope.value))
+                .map(|g| (g.client_id.clone(), g.name.clone()))
+                .collect(),
+            value: scope.value,
+        })
+        .collect();
+    Ok((grants, scopes))
+}
+
+/// Every role's permissions by role ID. The listings run side by side, as
+/// many at once as the client lets through.
+async fn all_role_permissions(
+    tenant: &Tenant,
+    roles: &[Role],
+) -> Result<HashMap<String, Vec<Permission>>, auth0_mgmt_api::Error> {
+    stream::iter(roles)
+        .map(|role| async move {
+            fetch_role_permissions(tenant, &role.id)
+                .await
+                .map(|permissions| (role.id.clone(), permissions))
+        })
+        .buffer_unordered(tenant.client.max_concurrency())
+        .try_collect()
+        .await
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::{Method, StatusCode};
+    use serde_json::json;
+
+    use super::*;
+    use crate::auth::Role as OperatorRole;
+    use crate::test_support;
+
+    fn settings(lifetime: u32, web: u32) -> SettingsForm {
+        SettingsForm {
+            name: "Orders".to_string(),
+            signing_alg: SIGNING_ALGS[0].to_string(),
+            token_lifetime: Some(lifetime),
+            token_lifetime_for_web: Some(web),
+            allow_offline_access: None,
+            skip_consent_for_verifiable_first_party_clients: None,
+        }
+    }
+
+    #[test]
+    fn identifiers_scopes_and_algorithms_are_checked() {
+        assert!(validate_identifier("https://orders.example.com").is_ok());
+        assert!(validate_identifier(" https://orders.example.com").is_err());
+        assert!(validate_identifier("orders api").is_err());
+
+        assert!(validate_scope_value("read:orders").is_ok());
+        assert!(validate_scope_value("read orders").is_err());
+
+        assert!(SIGNING_ALGS
+            .iter()
+            .all(|alg| validate_signing_alg(alg).is_ok()));
+        assert!(validate_signing_alg("none").is_err());
+    }
+
+    #[test]
+    fn web_lifetime_cannot_outlast_the_token_lifetime() {
+        assert!(settings(3600, 3600).validate_all().is_ok());
+        let errors = settings(3600, 7200).validate_all().unwrap_err();
+        assert!(errors.field_errors().contains_key("token_lifetime_for_web"));
+    }
+
+    #[test]
+    fn only_system_apis_are_refused() {
+        let api = |is_system: bool| -> ResourceServer {
+            serde_json::from_value(json!({
+                "id": "api_1",
+                "name": "Orders",
+                "identifier": "https://orders.example.com",
+                "is_system": is_system,
+            }))
+            .unwrap()
+        };
+        assert!(refuse_system(&api(true)).is_some());
+        assert!(refuse_system(&api(false)).is_none());
+    }
+
+    #[tokio::test]
+    async fn invalid_new_api_is_shown_again_without_reaching_auth0() {
+        let state = test_support::state().await;
+        let tenant = state.tenants.default_tenant().clone();
+        let form = CreateForm {
+            identifier: "orders api".to_string(),
+            ..CreateForm::default()
+        };
+
+        create(
+            State(state.clone()),
+            RequireAdmin(test_support::operator(OperatorRole::Admin)),
+            tenant,
+            Form(form),
+        )
+        .await
+        .unwrap();
+        assert!(state.audit.entries().await.unwrap().entries.is_empty());
+    }
+
+    #[tokio::test]
+    async fn only_admins_change_apis_or_their_scopes() {
+        let cases = [
+            (Method::POST, "/t/prod/apis"),
+            (Method::PATCH, "/t/prod/apis/api_1"),
+            (Method::DELETE, "/t/prod/apis/api_1"),
+            (Method::POST, "/t/prod/apis/api_1/scopes"),
+            (Method::DELETE, "/t/prod/apis/api_1/scopes"),
+        ];
+        for (method, uri) in cases {
+            let response =
+                test_support::send_as(Some(OperatorRole::Support), method.clone(), uri, "").await;
+            assert_eq!(
+                response.status(),
+                StatusCode::FORBIDDEN,
+                "{} {}",
+                method,
+                uri
+            );
+        }
+    }
+}
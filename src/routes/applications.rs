@@ -1,46 +1,1202 @@
+use std::collections::BTreeSet;
+
 use askama::Template;
-use auth0_mgmt_api::types::clients::ListClientsParams;
-use axum::{extract::State, http::HeaderMap, response::Response};
+use auth0_mgmt_api::{
+    types::{
+        client_grants::{ClientGrant, CreateClientGrantRequest, UpdateClientGrantRequest},
+        clients::{AppType, Client, CreateClientRequest, ListClientsParams, UpdateClientRequest},
+        resource_servers::ResourceServer,
+    },
+    ClientGrantId, ClientId,
+};
+use axum::{
+    extract::{Path, Query, State},
+    http::{header, HeaderMap},
+    response::{IntoResponse, Redirect, Response},
+};
+use axum_extra::extract::Form;
+use htmx_form_errors::FormErrors;
+use serde::Deserialize;
+use validator::{Validate, ValidationError};
 
-use crate::errors::AppResult;
-use crate::helpers::is_htmx_request;
+use crate::audit::{Action, FieldChange};
+use crate::auth::{Operator, RequireAdmin, RequireViewer};
+use crate::errors::{AppError, AppResult};
+use crate::helpers::{
+    empty_as_none, is_htmx_request, requested_page, requested_per_page, Pagination,
+};
+use crate::routes::helpers::{confirm_error, fetch_apis, fetch_grants, invalid, GrantsOf};
 use crate::state::AppState;
-use crate::templates::render;
+use crate::templates::{render, ErrorBannerTemplate};
+use crate::tenant::Tenant;
+
+const GRANT_TYPES: &[(&str, &str)] = &[
+    ("authorization_code", "Authorization Code"),
+    ("implicit", "Implicit"),
+    ("refresh_token", "Refresh Token"),
+    ("client_credentials", "Client Credentials"),
+    ("password", "Password"),
+    (
+        "urn:ietf:params:oauth:grant-type:device_code",
+        "Device Code",
+    ),
+];
+const APP_TYPES: &[(&str, &str)] = &[
+    ("spa", "Single Page Application"),
+    ("regular_web", "Regular Web Application"),
+    ("native", "Native"),
+    ("non_interactive", "Machine to Machine"),
+];
+const AUTH_METHODS: &[(&str, &str)] = &[
+    ("none", "None (public client)"),
+    ("client_secret_post", "Client secret (POST)"),
+    ("client_secret_basic", "Client secret (Basic)"),
+];
+const JWT_ALGS: &[&str] = &["RS256", "HS256"];
 
 #[derive(Template)]
 #[template(path = "applications/list.html")]
 struct ListTemplate {
-    applications: Vec<auth0_mgmt_api::types::clients::Client>,
+    operator: Operator,
+    tenant: Tenant,
+    applications: Vec<Client>,
+    pagination: Pagination,
+    error: Option<AppError>,
+    form: CreateForm,
+    errors: FormErrors,
 }
 
 #[derive(Template)]
 #[template(path = "applications/table.html")]
 struct TableTemplate {
-    applications: Vec<auth0_mgmt_api::types::clients::Client>,
+    tenant: Tenant,
+    applications: Vec<Client>,
+    pagination: Pagination,
+    error: Option<AppError>,
+}
+
+#[derive(Template)]
+#[template(path = "applications/create_form.html")]
+struct CreateFormTemplate {
+    tenant: Tenant,
+    form: CreateForm,
+    errors: FormErrors,
+}
+
+#[derive(Template)]
+#[template(path = "applications/secret.html")]
+struct SecretTemplate {
+    secret: String,
+    error: Option<AppError>,
+}
+
+#[derive(Template)]
+#[template(path = "applications/grants.html")]
+struct GrantsTemplate {
+    operator: Operator,
+    tenant: Tenant,
+    client_id: String,
+    grants: Vec<GrantRow>,
+    /// APIs the application has no grant for yet, as (identifier, name).
+    available: Vec<(String, String)>,
+    error: Option<AppError>,
+}
+
+/// A client grant with every scope its API defines, so the scopes can be
+/// edited as checkboxes.
+struct GrantRow {
+    id: String,
+    audience: String,
+    api_id: Option<String>,
+    api_name: String,
+    scopes: Vec<ScopeChoice>,
+}
+
+struct ScopeChoice {
+    value: String,
+    description: String,
+    granted: bool,
+}
+
+#[derive(Template)]
+#[template(path = "applications/detail.html")]
+struct DetailTemplate {
+    operator: Operator,
+    tenant: Tenant,
+    application: Client,
+    form: SettingsForm,
+    grant_types: Vec<(String, String)>,
+    errors: FormErrors,
+}
+
+#[derive(Deserialize)]
+pub struct ApplicationPath {
+    client_id: String,
+}
+
+#[derive(Deserialize, Default)]
+pub struct ListQuery {
+    #[serde(default, deserialize_with = "empty_as_none")]
+    page: Option<u32>,
+    #[serde(default, deserialize_with = "empty_as_none")]
+    per_page: Option<u32>,
+    #[serde(default, deserialize_with = "empty_as_none")]
+    jump: Option<u32>,
 }
 
 pub async fn list(
-    State(state): State<AppState>,
+    RequireViewer(operator): RequireViewer,
+    tenant: Tenant,
+    Query(query): Query<ListQuery>,
     headers: HeaderMap,
 ) -> AppResult<Response> {
+    let page = requested_page(query.page, query.jump);
+    let per_page = requested_per_page(query.per_page, 50);
+
     let params = ListClientsParams {
-        page: Some(0),
-        per_page: Some(100),
-        include_totals: Some(false),
+        page: Some(page),
+        per_page: Some(per_page),
+        include_totals: Some(true),
         ..Default::default()
     };
 
-    let applications = match state.client.clients().list(Some(params)).await {
-        Ok(applications) => applications,
+    let params = &params;
+    let (applications, start, limit, total, error) = match tenant
+        .client
+        .get(|c| async move { c.clients().list_paginated(Some(params.clone())).await })
+        .await
+    {
+        Ok(result) => (result.items, result.start, result.limit, result.total, None),
         Err(e) => {
             tracing::error!(error = ?e, "failed to list applications");
-            Vec::new()
+            (Vec::new(), page * per_page, per_page, 0, Some(e.into()))
         }
     };
+    let pagination = Pagination::new(
+        &tenant.path("/applications"),
+        "#applications-list",
+        start,
+        limit,
+        total,
+    );
 
     if is_htmx_request(&headers) {
-        render(TableTemplate { applications })
+        render(TableTemplate {
+            tenant,
+            applications,
+            pagination,
+            error,
+        })
+    } else {
+        render(ListTemplate {
+            operator,
+            tenant,
+            applications,
+            pagination,
+            error,
+            form: CreateForm::default(),
+            errors: FormErrors::new(),
+        })
+    }
+}
+
+#[derive(Clone, Deserialize, Validate)]
+pub struct CreateForm {
+    #[validate(length(min = 1, message = "Name is required"))]
+    name: String,
+    #[validate(custom(function = "validate_app_type"))]
+    app_type: String,
+    #[serde(default)]
+    #[validate(custom(function = "validate_url_list"))]
+    callbacks: String,
+}
+
+impl Default for CreateForm {
+    fn default() -> Self {
+        Self {
+            name: String::new(),
+            app_type: APP_TYPES[0].0.to_string(),
+            callbacks: String::new(),
+        }
+    }
+}
+
+impl CreateForm {
+    pub fn app_types(&self) -> &'static [(&'static str, &'static str)] {
+        APP_TYPES
+    }
+}
+
+/// Whether two lists hold the same values, ignoring order and repeats;
+/// Auth0 may return grant types and scopes in any order.
+fn same_items(a: &[String], b: &[String]) -> bool {
+    a.iter().collect::<BTreeSet<_>>() == b.iter().collect::<BTreeSet<_>>()
+}
+
+/// The Auth0 type for one of the `APP_TYPES` values the form offers.
+fn parse_app_type(value: &str) -> Option<AppType> {
+    match value {
+        "spa" => Some(AppType::Spa),
+        "regular_web" => Some(AppType::RegularWeb),
+        "native" => Some(AppType::Native),
+        "non_interactive" => Some(AppType::NonInteractive),
+        _ => None,
+    }
+}
+
+fn validate_app_type(app_type: &str) -> Result<(), ValidationError> {
+    if parse_app_type(app_type).is_some() {
+        Ok(())
+    } else {
+        Err(invalid(
+            "app_type",
+            "Choose an application type".to_string(),
+        ))
+    }
+}
+
+pub async fn create(
+    State(state): State<AppState>,
+    RequireAdmin(operator): RequireAdmin,
+    tenant: Tenant,
+    Form(form): Form<CreateForm>,
+) -> AppResult<Response> {
+    if let Err(e) = form.validate() {
+        return render(CreateFormTemplate {
+            tenant,
+            form,
+            errors: FormErrors::from(e),
+        });
+    }
+
+    let callbacks = split_lines(&form.callbacks);
+    let changes = vec![
+        FieldChange::new("name", None, Some(&form.name)),
+        FieldChange::new("app_type", None, Some(&form.app_type)),
+        FieldChange::new("callbacks", None, Some(&callbacks.join(", "))),
+    ];
+
+    let request = CreateClientRequest {
+        name: form.name.trim().to_string(),
+        app_type: parse_app_type(&form.app_type),
+        callbacks: Some(callbacks).filter(|c| !c.is_empty()),
+        ..Default::default()
+    };
+
+    let result = tenant
+        .client
+        .send(|c| async move { c.clients().create(request).await })
+        .await;
+    let target = match &result {
+        Ok(application) => application.client_id.clone(),
+        Err(_) => form.name.clone(),
+    };
+    let error = result.as_ref().err().map(|e| e.to_string());
+    state
+        .audit
+        .record(
+            &operator,
+            &tenant,
+            Action::CreateApplication,
+            &target,
+            changes,
+            error,
+        )
+        .await?;
+
+    match result {
+        Ok(application) => {
+            tenant.cache.clients.invalidate();
+            let settings = tenant.path(&format!("/applications/{}", application.client_id));
+            Ok([("hx-redirect", settings)].into_response())
+        }
+        Err(e) => {
+            tracing::error!(error = ?e, "failed to create application");
+            let mut errors = FormErrors::new();
+            errors.add_base(&format!(
+                "Failed to create application: {}",
+                AppError::from(e).detail()
+            ));
+            render(CreateFormTemplate {
+                tenant,
+                form,
+                errors,
+            })
+        }
+    }
+}
+
+async fn fetch_application(
+    tenant: &Tenant,
+    client_id: &str,
+) -> Result<Client, auth0_mgmt_api::Error> {
+    tenant
+        .client
+        .get(|c| async move { c.clients().get(ClientId::new(client_id)).await })
+        .await
+}
+
+/// The editable settings as the form shows them: URL lists are one entry
+/// per line.
+#[derive(Clone, Deserialize, Default, Validate)]
+pub struct SettingsForm {
+    #[serde(default)]
+    #[validate(custom(function = "validate_url_list"))]
+    callbacks: String,
+    #[serde(default)]
+    #[validate(custom(function = "validate_url_list"))]
+    allowed_logout_urls: String,
+    #[serde(default)]
+    #[validate(custom(function = "validate_origin_list"))]
+    web_origins: String,
+    #[serde(default)]
+    #[validate(custom(function = "validate_origin_list"))]
+    allowed_origins: String,
+    #[serde(default)]
+    grant_types: Vec<String>,
+    #[serde(default)]
+    #[validate(custom(function = "validate_auth_method"))]
+    token_endpoint_auth_method: String,
+    #[serde(default)]
+    #[validate(custom(function = "validate_jwt_alg"))]
+    jwt_alg: String,
+    #[serde(default, deserialize_with = "empty_as_none")]
+    #[validate(range(
+        min = 60,
+        max = 2592000,
+        message = "Must be between 60 seconds and 30 days"
+    ))]
+    jwt_lifetime_in_seconds: Option<u32>,
+}
+
+impl SettingsForm {
+    fn from_client(client: &Client) -> Self {
+        let jwt = client.jwt_configuration.clone().unwrap_or_default();
+        Self {
+            callbacks: join_lines(client.callbacks.as_deref()),
+            allowed_logout_urls: join_lines(client.allowed_logout_urls.as_deref()),
+            web_origins: join_lines(client.web_origins.as_deref()),
+            allowed_origins: join_lines(client.allowed_origins.as_deref()),
+            grant_types: client.grant_types.clone().unwrap_or_default(),
+            token_endpoint_auth_method: client
+                .token_endpoint_auth_method
+                .clone()
+                .unwrap_or_default(),
+            jwt_alg: jwt.alg.unwrap_or_default(),
+            jwt_lifetime_in_seconds: jwt.lifetime_in_seconds,
+        }
+    }
+
+    pub fn auth_methods(&self) -> &'static [(&'static str, &'static str)] {
+        AUTH_METHODS
+    }
+
+    pub fn jwt_algs(&self) -> &'static [&'static str] {
+        JWT_ALGS
+    }
+
+    pub fn has_grant(&self, grant: &str) -> bool {
+        self.grant_types.iter().any(|g| g == grant)
+    }
+
+    /// Public clients (SPAs, native apps) authenticate without a secret,
+    /// so there is nothing to rotate.
+    pub fn has_secret(&self) -> bool {
+        self.token_endpoint_auth_method != "none"
+    }
+}
+
+fn join_lines(values: Option<&[String]>) -> String {
+    values.unwrap_or_default().join("\n")
+}
+
+fn split_lines(text: &str) -> Vec<String> {
+    text.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+/// Callback and logout URLs: absolute URLs, where Auth0 also allows a `*`
+/// wildcard subdomain and custom schemes for native apps.
+fn validate_url_list(text: &str) -> Result<(), ValidationError> {
+    for line in split_lines(text) {
+        let url = url::Url::parse(&line.replace("*.", "wildcard."))
+            .map_err(|_| invalid("url", format!("{} is not a valid URL", line)))?;
+        if url.fragment().is_some() {
+            return Err(invalid(
+                "url",
+                format!("{} must not contain a fragment", line),
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Web and CORS origins: scheme, host and optional port only.
+fn validate_origin_list(text: &str) -> Result<(), ValidationError> {
+    for line in split_lines(text) {
+        let url = url::Url::parse(&line.replace("*.", "wildcard."))
+            .map_err(|_| invalid("origin", format!("{} is not a valid origin", line)))?;
+        let is_origin = matches!(url.scheme(), "http" | "https")
+            && url.host().is_some()
+            && url.path() == "/"
+            && !line.trim_end().ends_with('/')
+            && url.query().is_none()
+            && url.fragment().is_none();
+        if !is_origin {
+            return Err(invalid(
+                "origin",
+                format!("{} must be an origin like https://app.example.com", line),
+            ));
+        }
+    }
+    Ok(())
+}
+
+fn validate_auth_method(method: &str) -> Result<(), ValidationError> {
+    if method.is_empty() || AUTH_METHODS.iter().any(|(m, _)| *m == method) {
+        Ok(())
+    } else {
+        Err(invalid(
+            "auth_method",
+            "Unknown authentication method".to_string(),
+        ))
+    }
+}
+
+fn validate_jwt_alg(alg: &str) -> Result<(), ValidationError> {
+    if alg.is_empty() || JWT_ALGS.contains(&alg) {
+        Ok(())
     } else {
-        render(ListTemplate { applications })
+        Err(invalid("jwt_alg", "Unknown signing algorithm".to_string()))
+    }
+}
+
+/// The grant types offered as checkboxes, plus any the application already
+/// has that we do not list, so saving does not drop them.
+fn grant_type_choices(form: &SettingsForm) -> Vec<(String, String)> {
+    let mut choices: Vec<(String, String)> = GRANT_TYPES
+        .iter()
+        .map(|(grant, label)| (grant.to_string(), label.to_string()))
+        .collect();
+    for grant in &form.grant_types {
+        if !choices.iter().any(|(g, _)| g == grant) {
+            choices.push((grant.clone(), grant.clone()));
+        }
+    }
+    choices
+}
+
+fn detail_page(
+    operator: Operator,
+    tenant: Tenant,
+    application: Client,
+    form: SettingsForm,
+    errors: FormErrors,
+) -> AppResult<Response> {
+    render(DetailTemplate {
+        operator,
+        tenant,
+        application,
+        grant_types: grant_type_choices(&form),
+        form,
+        errors,
+    })
+}
+
+pub async fn get(
+    RequireViewer(operator): RequireViewer,
+    tenant: Tenant,
+    Path(ApplicationPath { client_id }): Path<ApplicationPath>,
+) -> AppResult<Response> {
+    let application = fetch_application(&tenant, &client_id).await.map_err(|e| {
+        tracing::warn!(error = ?e, %client_id, "failed to get application");
+        AppError::from(e)
+    })?;
+
+    let form = SettingsForm::from_client(&application);
+    detail_page(operator, tenant, application, form, FormErrors::new())
+}
+
+pub async fn update(
+    State(state): State<AppState>,
+    RequireAdmin(operator): RequireAdmin,
+    tenant: Tenant,
+    Path(ApplicationPath { client_id }): Path<ApplicationPath>,
+    Form(form): Form<SettingsForm>,
+) -> AppResult<Response> {
+    let application = fetch_application(&tenant, &client_id)
+        .await
+        .map_err(AppError::from)?;
+
+    if let Err(e) = form.validate() {
+        return detail_page(operator, tenant, application, form, FormErrors::from(e));
+    }
+
+    let current = SettingsForm::from_client(&application);
+    let mut changes = Vec::new();
+    let mut request = UpdateClientRequest::default();
+
+    let url_lists = [
+        ("callbacks", &current.callbacks, &form.callbacks),
+        (
+            "allowed_logout_urls",
+            &current.allowed_logout_urls,
+            &form.allowed_logout_urls,
+        ),
+        ("web_origins", &current.web_origins, &form.web_origins),
+        (
+            "allowed_origins",
+            &current.allowed_origins,
+            &form.allowed_origins,
+        ),
+    ];
+    for (field, before, after) in url_lists {
+        let (before, after) = (split_lines(before), split_lines(after));
+        if before == after {
+            continue;
+        }
+        changes.push(FieldChange::new(
+            field,
+            Some(&before.join(", ")),
+            Some(&after.join(", ")),
+        ));
+        match field {
+            "callbacks" => request.callbacks = Some(after),
+            "allowed_logout_urls" => request.allowed_logout_urls = Some(after),
+            "web_origins" => request.web_origins = Some(after),
+            _ => request.allowed_origins = Some(after),
+        }
+    }
+
+    if !same_items(&current.grant_types, &form.grant_types) {
+        changes.push(FieldChange::new(
+            "grant_types",
+            Some(&current.grant_types.join(", ")),
+            Some(&form.grant_types.join(", ")),
+        ));
+        request.grant_types = Some(form.grant_types.clone());
+    }
+
+    if !form.token_endpoint_auth_method.is_empty()
+        && current.token_endpoint_auth_method != form.token_endpoint_auth_method
+    {
+        changes.push(FieldChange::new(
+            "token_endpoint_auth_method",
+            Some(&current.token_endpoint_auth_method),
+            Some(&form.token_endpoint_auth_method),
+        ));
+        request.token_endpoint_auth_method = Some(form.token_endpoint_auth_method.clone());
+    }
+
+    let alg_changed = !form.jwt_alg.is_empty() && current.jwt_alg != form.jwt_alg;
+    let lifetime_changed = form.jwt_lifetime_in_seconds.is_some()
+        && current.jwt_lifetime_in_seconds != form.jwt_lifetime_in_seconds;
+    if alg_changed || lifetime_changed {
+        let mut jwt = application.jwt_configuration.clone().unwrap_or_default();
+        if alg_changed {
+            changes.push(FieldChange::new(
+                "jwt_configuration.alg",
+                Some(&current.jwt_alg),
+                Some(&form.jwt_alg),
+            ));
+            jwt.alg = Some(form.jwt_alg.clone());
+        }
+        if lifetime_changed {
+            let lifetime = |v: Option<u32>| v.map(|v| v.to_string());
+            changes.push(FieldChange::new(
+                "jwt_configuration.lifetime_in_seconds",
+                lifetime(current.jwt_lifetime_in_seconds).as_deref(),
+                lifetime(form.jwt_lifetime_in_seconds).as_deref(),
+            ));
+            jwt.lifetime_in_seconds = form.jwt_lifetime_in_seconds;
+        }
+        request.jwt_configuration = Some(jwt);
+    }
+
+    if changes.is_empty() {
+        return detail_page(operator, tenant, application, current, FormErrors::new());
+    }
+
+    let id = ClientId::new(&client_id);
+    let result = tenant
+        .client
+        .send(|c| async move { c.clients().update(id, request).await })
+        .await;
+    let error = result.as_ref().err().map(|e| e.to_string());
+    state
+        .audit
+        .record(
+            &operator,
+            &tenant,
+            Action::UpdateApplication,
+            &client_id,
+            changes,
+            error,
+        )
+        .await?;
+
+    match result {
+        Ok(updated) => {
+            tenant.cache.clients.invalidate();
+            let form = SettingsForm::from_client(&updated);
+            detail_page(operator, tenant, updated, form, FormErrors::new())
+        }
+        Err(e) => {
+            tracing::error!(error = ?e, %client_id, "failed to update application");
+            let mut errors = FormErrors::new();
+            errors.add_base(&format!(
+                "Failed to update application: {}",
+                AppError::from(e).detail()
+            ));
+            detail_page(operator, tenant, application, form, errors)
+        }
+    }
+}
+
+#[derive(Deserialize)]
+pub struct DeleteQuery {
+    #[serde(default)]
+    confirm: String,
+}
+
+/// Deletes an application once the operator has typed its name. The
+/// client this app uses for the Management API is refused outright.
+pub async fn delete(
+    State(state): State<AppState>,
+    RequireAdmin(operator): RequireAdmin,
+    tenant: Tenant,
+    Path(ApplicationPath { client_id }): Path<ApplicationPath>,
+    Query(query): Query<DeleteQuery>,
+) -> AppResult<Response> {
+    let application = fetch_application(&tenant, &client_id)
+        .await
+        .map_err(AppError::from)?;
+    let name = application.name.clone().unwrap_or_default();
+
+    let refusal = if client_id == tenant.client_id {
+        Some("This is the application this console uses to reach Auth0".to_string())
+    } else if query.confirm.trim() != name {
+        Some("Type the application name to confirm".to_string())
+    } else {
+        None
+    };
+    if let Some(message) = refusal {
+        let form = SettingsForm::from_client(&application);
+        return detail_page(operator, tenant, application, form, confirm_error(message));
+    }
+
+    let changes = vec![FieldChange::new("name", Some(&name), None)];
+    let id = ClientId::new(&client_id);
+    let result = tenant
+        .client
+        .send(|c| async move { c.clients().delete(id).await })
+        .await;
+    let error = result.as_ref().err().map(|e| e.to_string());
+    state
+        .audit
+        .record(
+            &operator,
+            &tenant,
+            Action::DeleteApplication,
+            &client_id,
+            changes,
+            error,
+        )
+        .await?;
+
+    match result {
+        Ok(_) => {
+            tenant.cache.clients.invalidate();
+            Ok(Redirect::to(&tenant.path("/applications")).into_response())
+        }
+        Err(e) => {
+            tracing::error!(error = ?e, %client_id, "failed to delete application");
+            let errors = confirm_error(format!(
+                "Failed to delete application: {}",
+                AppError::from(e).detail()
+            ));
+            let form = SettingsForm::from_client(&application);
+            detail_page(operator, tenant, application, form, errors)
+        }
+    }
+}
+
+/// Refuses changes to the client this console uses for the Management API:
+/// rotating its secret, or narrowing or revoking its grants, would lock the
+/// console out of the tenant until someone fixes it in the Auth0 dashboard.
+fn refuse_own_client(tenant: &Tenant, client_id: &str) -> AppResult<()> {
+    if client_id == tenant.client_id {
+        tracing::warn!(tenant = %tenant.name, %client_id, "refused to change own client");
+        return Err(AppError::Forbidden);
+    }
+    Ok(())
+}
+
+/// Rotates the client secret and returns the new one in a fragment. The
+/// rotation is audited, but the secret itself is never stored, logged or
+/// written to the audit log: this response is the only place it appears,
+/// and the page never shows the current one. The client this app uses for
+/// the Management API is refused.
+pub async fn rotate_secret(
+    State(state): State<AppState>,
+    RequireAdmin(operator): RequireAdmin,
+    tenant: Tenant,
+    Path(ApplicationPath { client_id }): Path<ApplicationPath>,
+) -> AppResult<Response> {
+    refuse_own_client(&tenant, &client_id)?;
+
+    let id = ClientId::new(&client_id);
+    let result = tenant
+        .client
+        .send(|c| async move { c.clients().rotate_secret(id).await })
+        .await;
+    // Log and audit only the outcome; the response body holds the secret.
+    let error = result.as_ref().err().map(|e| e.to_string());
+    let audited = state
+        .audit
+        .record(
+            &operator,
+            &tenant,
+            Action::RotateApplicationSecret,
+            &client_id,
+            vec![FieldChange::redacted("client_secret")],
+            error,
+        )
+        .await;
+
+    let secret = match result {
+        Ok(application) => {
+            tenant.cache.clients.invalidate();
+            application.client_secret
+        }
+        Err(e) => {
+            tracing::error!(error = %e, %client_id, "failed to rotate client secret");
+            return render(ErrorBannerTemplate { error: e.into() });
+        }
+    };
+    let Some(secret) = secret else {
+        return render(ErrorBannerTemplate {
+            error: AppError::Auth0("Auth0 did not return the new secret".to_string()),
+        });
+    };
+
+    // The old secret is already gone, so the new one is shown even if the
+    // rotation could not be audited.
+    let mut response = render(SecretTemplate {
+        secret,
+        error: audited.err(),
+    })?;
+    response.headers_mut().insert(
+        header::CACHE_CONTROL,
+        header::HeaderValue::from_static("no-store"),
+    );
+    Ok(response)
+}
+
+#[derive(Deserialize)]
+pub struct GrantPath {
+    client_id: String,
+    grant_id: String,
+}
+
+/// Pairs each grant with its API's scope list. Scopes granted that the API
+/// no longer defines are kept as checked choices, so saving does not drop
+/// them silently.
+fn grant_rows(grants: Vec<ClientGrant>, apis: &[ResourceServer]) -> Vec<GrantRow> {
+    grants
+        .into_iter()
+        .map(|grant| {
+            let api = apis.iter().find(|api| api.identifier == grant.audience);
+            let mut scopes: Vec<ScopeChoice> = api
+                .and_then(|api| api.scopes.clone())
+                .unwrap_or_default()
+                .into_iter()
+                .map(|scope| ScopeChoice {
+                    granted: grant.scope.contains(&scope.value),
+                    value: scope.value,
+                    description: scope.description.unwrap_or_default(),
+                })
+                .collect();
+            for value in &grant.scope {
+                if !scopes.iter().any(|s| &s.value == value) {
+                    scopes.push(ScopeChoice {
+                        value: value.clone(),
+                        description: "Not defined by the API".to_string(),
+                        granted: true,
+                    });
+                }
+            }
+            GrantRow {
+                id: grant.id,
+                api_id: api.map(|api| api.id.clone()),
+                api_name: api
+                    .and_then(|api| api.name.clone())
+                    .unwrap_or_else(|| grant.audience.clone()),
+                audience: grant.audience,
+                scopes,
+            }
+        })
+        .collect()
+}
+
+async fn grants_card(
+    operator: Operator,
+    tenant: Tenant,
+    client_id: String,
+    error: Option<AppError>,
+) -> AppResult<Response> {
+    let lists = tokio::try_join!(
+        fetch_grants(&tenant, GrantsOf::Client(&client_id)),
+        fetch_apis(&tenant),
+    );
+    let (grants, apis) = match lists {
+        Ok(lists) => lists,
+        Err(e) => {
+            tracing::error!(error = ?e, %client_id, "failed to list client grants");
+            return render(ErrorBannerTemplate { error: e.into() });
+        }
+    };
+
+    let available = apis
+        .iter()
+        .filter(|api| !grants.iter().any(|g| g.audience == api.identifier))
+        .map(|api| {
+            let name = api.name.clone().unwrap_or_else(|| api.identifier.clone());
+            (api.identifier.clone(), name)
+        })
+        .collect();
+
+    render(GrantsTemplate {
+        operator,
+        tenant,
+        client_id,
+        grants: grant_rows(grants, &apis),
+        available,
+        error,
+    })
+}
+
+pub async fn get_grants(
+    RequireViewer(operator): RequireViewer,
+    tenant: Tenant,
+    Path(ApplicationPath { client_id }): Path<ApplicationPath>,
+) -> AppResult<Response> {
+    grants_card(operator, tenant, client_id, None).await
+}
+
+#[derive(Deserialize)]
+pub struct CreateGrantForm {
+    #[serde(default)]
+    audience: String,
+}
+
+/// Grants start with no scopes; they are ticked afterwards on the card.
+pub async fn create_grant(
+    State(state): State<AppState>,
+    RequireAdmin(operator): RequireAdmin,
+    tenant: Tenant,
+    Path(ApplicationPath { client_id }): Path<ApplicationPath>,
+    Form(form): Form<CreateGrantForm>,
+) -> AppResult<Response> {
+    if form.audience.is_empty() {
+        return grants_card(operator, tenant, client_id, None).await;
+    }
+
+    let changes = vec![FieldChange::new("audience", None, Some(&form.audience))];
+    let request = CreateClientGrantRequest {
+        client_id: client_id.clone(),
+        audience: form.audience,
+        scope: Vec::new(),
+    };
+    let result = tenant
+        .client
+        .send(|c| async move { c.client_grants().create(request).await })
+        .await;
+    let error = result.as_ref().err().map(|e| e.to_string());
+    state
+        .audit
+        .record(
+            &operator,
+            &tenant,
+            Action::CreateClientGrant,
+            &client_id,
+            changes,
+            error,
+        )
+        .await?;
+
+    let error = result.err().map(|e| {
+        tracing::error!(error = ?e, %client_id, "failed to create client grant");
+        AppError::from(e)
+    });
+    grants_card(operator, tenant, client_id, error).await
+}
+
+/// Finds one of the client's grants, so a grant ID from the URL is only
+/// acted on if it belongs to the client in the same URL.
+async fn find_grant(
+    tenant: &Tenant,
+    client_id: &str,
+    grant_id: &str,
+) -> Result<ClientGrant, AppError> {
+    fetch_grants(tenant, GrantsOf::Client(client_id))
+        .await?
+        .into_iter()
+        .find(|g| g.id == grant_id)
+        .ok_or(AppError::NotFound)
+}
+
+/// Checked scopes, one `scope` value per checkbox.
+#[derive(Deserialize)]
+pub struct GrantScopesForm {
+    #[serde(default)]
+    scope: Vec<String>,
+}
+
+pub async fn update_grant(
+    State(state): State<AppState>,
+    RequireAdmin(operator): RequireAdmin,
+    tenant: Tenant,
+    Path(GrantPath {
+        client_id,
+        grant_id,
+    }): Path<GrantPath>,
+    Form(form): Form<GrantScopesForm>,
+) -> AppResult<Response> {
+    refuse_own_client(&tenant, &client_id)?;
+
+    let grant = match find_grant(&tenant, &client_id, &grant_id).await {
+        Ok(grant) => grant,
+        Err(e) => return grants_card(operator, tenant, client_id, Some(e)).await,
+    };
+    if same_items(&grant.scope, &form.scope) {
+        return grants_card(operator, tenant, client_id, None).await;
+    }
+
+    let changes = vec![FieldChange::new(
+        &format!("scope:{}", grant.audience),
+        Some(&grant.scope.join(" ")),
+        Some(&form.scope.join(" ")),
+    )];
+    let request = UpdateClientGrantRequest {
+        scope: Some(form.scope),
+    };
+    let id = ClientGrantId::new(&grant_id);
+    let result = tenant
+        .client
+        .send(|c| async move { c.client_grants().update(id, request).await })
+        .await;
+    let error = result.as_ref().err().map(|e| e.to_string());
+    state
+        .audit
+        .record(
+            &operator,
+            &tenant,
+            Action::UpdateClientGrant,
+            &client_id,
+            changes,
+            error,
+        )
+        .await?;
+
+    let error = result.err().map(|e| {
+        tracing::error!(error = ?e, %client_id, %grant_id, "failed to update client grant");
+        AppError::from(e)
+    });
+    grants_card(operator, tenant, client_id, error).await
+}
+
+pub async fn delete_grant(
+    State(state): State<AppState>,
+    RequireAdmin(operator): RequireAdmin,
+    tenant: Tenant,
+    Path(GrantPath {
+        client_id,
+        grant_id,
+    }): Path<GrantPath>,
+) -> AppResult<Response> {
+    refuse_own_client(&tenant, &client_id)?;
+
+    let grant = match find_grant(&tenant, &client_id, &grant_id).await {
+        Ok(grant) => grant,
+        Err(e) => return grants_card(operator, tenant, client_id, Some(e)).await,
+    };
+    let changes = vec![FieldChange::new("audience", Some(&grant.audience), None)];
+
+    let id = ClientGrantId::new(&grant_id);
+    let result = tenant
+        .client
+        .send(|c| async move { c.client_grants().delete(id).await })
+        .await;
+    let error = result.as_ref().err().map(|e| e.to_string());
+    state
+        .audit
+        .record(
+            &operator,
+            &tenant,
+            Action::DeleteClientGrant,
+            &client_id,
+            changes,
+            error,
+        )
+        .await?;
+
+    let error = result.err().map(|e| {
+        tracing::error!(error = ?e, %client_id, %grant_id, "failed to delete client grant");
+        AppError::from(e)
+    });
+    grants_card(operator, tenant, client_id, error).await
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::{Method, StatusCode};
+    use futures::future::{BoxFuture, FutureExt};
+
+    use super::*;
+    use crate::auth::Role;
+    use crate::test_support::{self, OWN_CLIENT_ID};
+
+    #[tokio::test]
+    async fn changes_to_own_client_are_refused_and_not_audited() {
+        let state = test_support::state().await;
+        let tenant = state.tenants.default_tenant().clone();
+        let admin = || RequireAdmin(test_support::operator(Role::Admin));
+        let own_grant = || {
+            Path(GrantPath {
+                client_id: OWN_CLIENT_ID.to_string(),
+                grant_id: "cgr_console".to_string(),
+            })
+        };
+
+        let cases: [(&str, BoxFuture<AppResult<Response>>); 3] = [
+            (
+                "rotate secret",
+                rotate_secret(
+                    State(state.clone()),
+                    admin(),
+                    tenant.clone(),
+                    Path(ApplicationPath {
+                        client_id: OWN_CLIENT_ID.to_string(),
+                    }),
+                )
+                .boxed(),
+            ),
+            (
+                "update grant",
+                update_grant(
+                    State(state.clone()),
+                    admin(),
+                    tenant.clone(),
+                    own_grant(),
+                    Form(GrantScopesForm { scope: Vec::new() }),
+                )
+                .boxed(),
+            ),
+            (
+                "delete grant",
+                delete_grant(State(state.clone()), admin(), tenant.clone(), own_grant()).boxed(),
+            ),
+        ];
+        for (action, response) in cases {
+            assert!(
+                matches!(response.await, Err(AppError::Forbidden)),
+                "{}",
+                action
+            );
+        }
+        assert!(state.audit.entries().await.unwrap().entries.is_empty());
+        assert!(refuse_own_client(&tenant, "other-app").is_ok());
+    }
+
+    #[tokio::test]
+    async fn invalid_new_application_is_shown_again_without_reaching_auth0() {
+        let state = test_support::state().await;
+        let tenant = state.tenants.default_tenant().clone();
+
+        let response = create(
+            State(state.clone()),
+            RequireAdmin(test_support::operator(Role::Admin)),
+            tenant,
+            Form(CreateForm {
+                name: String::new(),
+                app_type: "regular-web".to_string(),
+                callbacks: "https://app.example/callback#done".to_string(),
+            }),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = test_support::body_text(response).await;
+        assert!(body.contains("Name is required"), "{}", body);
+        assert!(body.contains("must not contain a fragment"), "{}", body);
+        assert!(state.audit.entries().await.unwrap().entries.is_empty());
+    }
+
+    #[test]
+    fn callback_lists_allow_wildcards_and_custom_schemes() {
+        assert!(
+            validate_url_list("https://*.app.example/callback\n\n  com.example.app://cb  ").is_ok()
+        );
+        assert!(validate_url_list("").is_ok());
+        assert!(validate_url_list("app.example/callback").is_err());
+        assert_eq!(
+            split_lines(" https://a.example \n\nhttps://b.example"),
+            vec!["https://a.example", "https://b.example"]
+        );
+    }
+
+    #[tokio::test]
+    async fn only_admins_create_or_delete_applications() {
+        let cases = [
+            (
+                Method::POST,
+                "/t/prod/applications",
+                "name=Billing&app_type=spa",
+            ),
+            (
+                Method::DELETE,
+                "/t/prod/applications/app_1?confirm=Billing",
+                "",
+            ),
+        ];
+        for (method, uri, form) in cases {
+            let response =
+                test_support::send_as(Some(Role::Support), method.clone(), uri, form).await;
+            assert_eq!(
+                response.status(),
+                StatusCode::FORBIDDEN,
+                "{} {}",
+                method,
+                uri
+            );
+        }
+    }
+
+    #[test]
+    fn every_offered_app_type_parses() {
+        for (value, _) in APP_TYPES {
+            assert!(parse_app_type(value).is_some(), "{} has no AppType", value);
+        }
+        assert!(parse_app_type("regular-web").is_none());
+        assert!(validate_app_type("").is_err());
+    }
+
+    #[test]
+    fn same_items_ignores_order() {
+        let list = |items: &[&str]| items.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+        assert!(same_items(
+            &list(&["implicit", "authorization_code"]),
+            &list(&["authorization_code", "implicit"])
+        ));
+        assert!(!same_items(
+            &list(&["authorization_code"]),
+            &list(&["authorization_code", "refresh_token"])
+        ));
+        assert!(same_items(&list(&[]), &list(&[])));
     }
 }
@@ -0,0 +1,306 @@
+use askama::Template;
+use axum::{
+    extract::{Query, State},
+    http::{header, HeaderMap},
+    response::{IntoResponse, Response},
+};
+use serde::Deserialize;
+
+use crate::audit::{verify_chain, Action, AuditEntry, ChainStatus};
+use crate::auth::{Operator, RequireAdmin};
+use crate::errors::{AppError, AppResult};
+use crate::helpers::is_htmx_request;
+use crate::state::AppState;
+use crate::templates::render;
+use crate::tenant::Tenant;
+
+#[derive(Template)]
+#[template(path = "audit/list.html")]
+struct ListTemplate {
+    operator: Operator,
+    tenant: Tenant,
+    entries: Vec<AuditEntry>,
+    chain: ChainStatus,
+    actions: &'static [Action],
+    filter: AuditFilter,
+    export_query: String,
+}
+
+#[derive(Template)]
+#[template(path = "audit/table.html")]
+struct TableTemplate {
+    tenant: Tenant,
+    entries: Vec<AuditEntry>,
+    chain: ChainStatus,
+    export_query: String,
+}
+
+#[derive(Clone, Deserialize, Default)]
+pub struct AuditFilter {
+    operator: Option<String>,
+    /// The log is shared by all tenants; this narrows it to one.
+    tenant: Option<String>,
+    action: Option<String>,
+    target: Option<String>,
+    /// Inclusive `YYYY-MM-DD` bounds compared against the RFC 3339 timestamp.
+    from: Option<String>,
+    to: Option<String>,
+}
+
+impl AuditFilter {
+    fn matches(&self, entry: &AuditEntry) -> bool {
+        let contains = |needle: &Option<String>, haystack: &str| match needle.as_deref() {
+            Some(needle) if !needle.is_empty() => {
+                haystack.to_lowercase().contains(&needle.to_lowercase())
+            }
+            _ => true,
+        };
+        let day = entry.timestamp.get(..10).unwrap_or("");
+
+        (contains(&self.operator, &entry.operator)
+            || contains(&self.operator, &entry.operator_name))
+            && self
+                .tenant
+                .as_deref()
+                .filter(|t| !t.is_empty())
+                .is_none_or(|t| entry.tenant == t)
+            && self
+                .action
+                .as_deref()
+                .filter(|a| !a.is_empty())
+                .is_none_or(|a| a == entry.action.as_str())
+            && contains(&self.target, &entry.target)
+            && self
+                .from
+                .as_deref()
+                .filter(|d| !d.is_empty())
+                .is_none_or(|from| day >= from)
+            && self
+                .to
+                .as_deref()
+                .filter(|d| !d.is_empty())
+                .is_none_or(|to| day <= to)
+    }
+
+    fn query_string(&self) -> String {
+        let pairs = [
+            ("operator", &self.operator),
+            ("tenant", &self.tenant),
+            ("action", &self.action),
+            ("target", &self.target),
+            ("from", &self.from),
+            ("to", &self.to),
+        ];
+        url::form_urlencoded::Serializer::new(String::new())
+            .extend_pairs(
+                pairs
+                    .iter()
+                    .filter_map(|(k, v)| v.as_deref().filter(|v| !v.is_empty()).map(|v| (*k, v))),
+            )
+            .finish()
+    }
+}
+
+async fn filtered_entries(
+    state: &AppState,
+    filter: &AuditFilter,
+) -> AppResult<(Vec<AuditEntry>, ChainStatus)> {
+    let log = state.audit.entries().await.map_err(|e| {
+        tracing::error!(error = ?e, "failed to read audit log");
+        AppError::Audit(format!("The audit log could not be read: {}", e))
+    })?;
+    let chain = verify_chain(&log);
+    let entries = log
+        .entries
+        .into_iter()
+        .rev()
+        .filter(|entry| filter.matches(entry))
+        .collect();
+    Ok((entries, chain))
+}
+
+pub async fn list(
+    State(state): State<AppState>,
+    RequireAdmin(operator): RequireAdmin,
+    tenant: Tenant,
+    Query(filter): Query<AuditFilter>,
+    headers: HeaderMap,
+) -> AppResult<Response> {
+    let (entries, chain) = filtered_entries(&state, &filter).await?;
+    let export_query = filter.query_string();
+
+    if is_htmx_request(&headers) {
+        render(TableTemplate {
+            tenant,
+            entries,
+            chain,
+            export_query,
+        })
+    } else {
+        render(ListTemplate {
+            operator,
+            tenant,
+            entries,
+            chain,
+            actions: Action::ALL,
+            filter,
+            export_query,
+        })
+    }
+}
+
+#[derive(Deserialize)]
+pub struct ExportQuery {
+    format: Option<String>,
+    #[serde(flatten)]
+    filter: AuditFilter,
+}
+
+pub async fn export(
+    State(state): State<AppState>,
+    _: RequireAdmin,
+    Query(query): Query<ExportQuery>,
+) -> AppResult<Response> {
+    let (mut entries, _) = filtered_entries(&state, &query.filter).await?;
+    entries.reverse();
+
+    let (content_type, filename, body) = match query.format.as_deref() {
+        Some("csv") => ("text/csv", "audit.csv", to_csv(&entries)),
+        _ => {
+            let mut body = String::new();
+            for entry in &entries {
+                body.push_str(&serde_json::to_string(entry).unwrap_or_default());
+                body.push('\n');
+            }
+            ("application/x-ndjson", "audit.jsonl", body)
+        }
+    };
+
+    Ok((
+        [
+            (header::CONTENT_TYPE, content_type.to_string()),
+            (
+                header::CONTENT_DISPOSITION,
+                format!("attachment; filename=\"{}\"", filename),
+            ),
+        ],
+        body,
+    )
+        .into_response())
+}
+
+fn to_csv(entries: &[AuditEntry]) -> String {
+    fn field(value: &str) -> String {
+        // Spreadsheets run cells starting with these as formulas; a leading
+        // quote makes them plain text.
+        let value = if value.starts_with(['=', '+', '-', '@', '\t', '\r']) {
+            format!("'{}", value)
+        } else {
+            value.to_string()
+        };
+        if value.contains([',', '"', '\n', '\r']) {
+            format!("\"{}\"", value.replace('"', "\"\""))
+        } else {
+            value
+        }
+    }
+
+    let mut csv = String::from(
+        "seq,timestamp,operator,operator_name,tenant,action,target,changes,error,hash\n",
+    );
+    for entry in entries {
+        let changes = serde_json::to_string(&entry.changes).unwrap_or_default();
+        let row = [
+            entry.seq.to_string(),
+            entry.timestamp.clone(),
+            entry.operator.clone(),
+            entry.operator_name.clone(),
+            entry.tenant.clone(),
+            entry.action.to_string(),
+            entry.target.clone(),
+            changes,
+            entry.error.clone().unwrap_or_default(),
+            entry.hash.clone(),
+        ];
+        let row: Vec<String> = row.iter().map(|v| field(v)).collect();
+        csv.push_str(&row.join(","));
+        csv.push('\n');
+    }
+    csv
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audit::FieldChange;
+
+    fn entry(operator_name: &str, target: &str) -> AuditEntry {
+        AuditEntry {
+            seq: 1,
+            timestamp: "2024-05-01T12:00:00Z".to_string(),
+            operator: "auth0|ops".to_string(),
+            operator_name: operator_name.to_string(),
+            tenant: "prod".to_string(),
+            action: Action::UpdateUser,
+            target: target.to_string(),
+            changes: vec![FieldChange::new("name", Some("Old"), Some("New"))],
+            error: None,
+            prev_hash: String::new(),
+            hash: "abc".to_string(),
+        }
+    }
+
+    #[test]
+    fn csv_quotes_fields_with_separators() {
+        let csv = to_csv(&[entry("Ops, Team", "say \"hi\"")]);
+        let mut lines = csv.lines();
+
+        assert_eq!(
+            lines.next(),
+            Some("seq,timestamp,operator,operator_name,tenant,action,target,changes,error,hash")
+        );
+        assert_eq!(
+            lines.next(),
+            Some(
+                "1,2024-05-01T12:00:00Z,auth0|ops,\"Ops, Team\",prod,user.update,\"say \"\"hi\"\"\",\
+                 \"[{\"\"field\"\":\"\"name\"\",\"\"before\"\":\"\"Old\"\",\"\"after\"\":\"\"New\"\"}]\",,abc"
+            )
+        );
+        assert_eq!(lines.next(), None);
+    }
+
+    #[test]
+    fn csv_neutralises_formulas() {
+        for value in ["=HYPERLINK(\"x\")", "+1", "-1+2", "@SUM(A1)", "\tcmd"] {
+            let csv = to_csv(&[entry(value, "auth0|1")]);
+            let row = csv.lines().nth(1).unwrap();
+            let cell = row.split(',').nth(3).unwrap();
+            assert!(
+                cell.starts_with('\'') || cell.starts_with("\"'"),
+                "{:?} exported as {:?}",
+                value,
+                cell
+            );
+        }
+
+        let csv = to_csv(&[entry("Ops", "auth0|1")]);
+        assert!(csv.lines().nth(1).unwrap().contains(",Ops,"));
+    }
+
+    #[test]
+    fn filter_matches_day_range_and_action() {
+        let filter = AuditFilter {
+            action: Some("user.update".to_string()),
+            from: Some("2024-05-01".to_string()),
+            to: Some("2024-05-01".to_string()),
+            ..Default::default()
+        };
+        assert!(filter.matches(&entry("Ops", "auth0|1")));
+
+        let later = AuditFilter {
+            from: Some("2024-05-02".to_string()),
+            ..Default::default()
+        };
+        assert!(!later.matches(&entry("Ops", "auth0|1")));
+    }
+}
@@ -0,0 +1,69 @@
+use std::time::Duration;
+
+use askama::Template;
+use axum::{
+    extract::{Query, State},
+    response::{IntoResponse, Redirect, Response},
+};
+use axum_extra::extract::cookie::{PrivateCookieJar, SignedCookieJar};
+use serde::Deserialize;
+
+use crate::auth::{AuthError, CallbackQuery, LoginFlow, LOGIN_FLOW_COOKIE, SESSION_COOKIE};
+use crate::errors::AppResult;
+use crate::state::AppState;
+use crate::templates::render;
+
+const LOGIN_FLOW_TTL: Duration = Duration::from_secs(10 * 60);
+
+#[derive(Template)]
+#[template(path = "auth/signed_out.html")]
+struct SignedOutTemplate;
+
+#[derive(Deserialize)]
+pub struct LoginQuery {
+    next: Option<String>,
+}
+
+pub async fn login(
+    State(state): State<AppState>,
+    jar: PrivateCookieJar,
+    Query(query): Query<LoginQuery>,
+) -> Response {
+    let (auth_url, flow) = state.auth.start_login(query.next.as_deref());
+    let flow = serde_json::to_string(&flow).unwrap_or_default();
+
+    let jar = jar.add(state.auth.cookie(LOGIN_FLOW_COOKIE, flow, LOGIN_FLOW_TTL));
+    (jar, Redirect::to(auth_url.as_str())).into_response()
+}
+
+pub async fn callback(
+    State(state): State<AppState>,
+    flow_jar: PrivateCookieJar,
+    session_jar: SignedCookieJar,
+    Query(query): Query<CallbackQuery>,
+) -> Result<Response, AuthError> {
+    let flow: LoginFlow = flow_jar
+        .get(LOGIN_FLOW_COOKIE)
+        .and_then(|c| serde_json::from_str(c.value()).ok())
+        .ok_or(AuthError::MissingFlow)?;
+    let flow_jar = flow_jar.remove(state.auth.removal_cookie(LOGIN_FLOW_COOKIE));
+
+    let code = flow.authorization_code(query)?;
+    let operator = state.auth.complete_login(&flow, code).await?;
+    tracing::info!(operator = %operator.sub, role = %operator.role, "operator signed in");
+
+    let session = serde_json::to_string(&operator).unwrap_or_default();
+    let session_jar = session_jar.add(state.auth.cookie(
+        SESSION_COOKIE,
+        session,
+        state.auth.session_ttl,
+    ));
+
+    Ok((flow_jar, session_jar, Redirect::to(&flow.next)).into_response())
+}
+
+pub async fn logout(State(state): State<AppState>, jar: SignedCookieJar) -> AppResult<Response> {
+    let jar = jar.remove(state.auth.removal_cookie(SESSION_COOKIE));
+    let page = render(SignedOutTemplate)?;
+    Ok((jar, page).into_response())
+}
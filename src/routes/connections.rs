@@ -1,56 +1,1331 @@
+use std::collections::BTreeSet;
+
 use askama::Template;
-use auth0_mgmt_api::{types::connections::ListConnectionsParams, ManagementClient};
-use axum::{extract::State, http::HeaderMap, response::Response};
+use auth0_mgmt_api::{
+    types::connections::{
+        Connection, ConnectionStrategy, CreateConnectionRequest, ListConnectionsParams,
+        UpdateConnectionRequest,
+    },
+    ConnectionId,
+};
+use axum::{
+    extract::{Path, Query, State},
+    http::HeaderMap,
+    response::{IntoResponse, Response},
+};
+use axum_extra::extract::Form;
+use htmx_form_errors::FormErrors;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use validator::{Validate, ValidationError, ValidationErrors};
 
-use crate::errors::AppResult;
-use crate::helpers::is_htmx_request;
+use crate::audit::{Action, FieldChange};
+use crate::auth::{Operator, RequireAdmin, RequireViewer};
+use crate::errors::{AppError, AppResult};
+use crate::helpers::{
+    empty_as_none, is_htmx_request, requested_page, requested_per_page, Pagination,
+};
+use crate::routes::helpers::invalid;
 use crate::state::AppState;
 use crate::templates::render;
+use crate::tenant::Tenant;
+
+/// Options of database (`auth0` strategy) connections offered as switches.
+/// Everything else in `options` is shown read-only.
+const DATABASE_OPTIONS: &[(&str, &str)] = &[
+    ("disable_signup", "Disable sign ups"),
+    ("brute_force_protection", "Brute-force protection"),
+    ("requires_username", "Require username"),
+    (
+        "import_mode",
+        "Import users from the legacy database on login",
+    ),
+];
+
+/// Auth0's password strength presets, from `none` to `excellent`.
+const PASSWORD_POLICIES: &[(&str, &str)] = &[
+    ("none", "None (at least 1 character)"),
+    ("low", "Low (at least 6 characters)"),
+    ("fair", "Fair (8 characters, 3 character types)"),
+    ("good", "Good (8 characters, 3 of 4 character types)"),
+    (
+        "excellent",
+        "Excellent (10 characters, 3 of 4 types, no repeats)",
+    ),
+];
+/// Auth0 keeps at most this many previous passwords.
+const MAX_PASSWORD_HISTORY: u32 = 24;
+const MAX_DICTIONARY_WORD: usize = 50;
 
 #[derive(Template)]
 #[template(path = "connections/list.html")]
 struct ListTemplate {
+    operator: Operator,
+    tenant: Tenant,
     connections: Vec<auth0_mgmt_api::types::connections::Connection>,
+    pagination: Pagination,
+    error: Option<AppError>,
+    form: CreateForm,
+    policy: PasswordPolicyForm,
+    errors: FormErrors,
+}
+
+#[derive(Template)]
+#[template(path = "connections/create_form.html")]
+struct CreateFormTemplate {
+    tenant: Tenant,
+    form: CreateForm,
+    policy: PasswordPolicyForm,
+    errors: FormErrors,
 }
 
 #[derive(Template)]
 #[template(path = "connections/table.html")]
 struct TableTemplate {
+    tenant: Tenant,
     connections: Vec<auth0_mgmt_api::types::connections::Connection>,
+    pagination: Pagination,
+    error: Option<AppError>,
+}
+
+#[derive(Template)]
+#[template(path = "connections/detail.html")]
+struct DetailTemplate {
+    operator: Operator,
+    tenant: Tenant,
+    connection: Connection,
+    strategy: String,
+    clients: Vec<ClientChoice>,
+    toggles: Vec<OptionToggle>,
+    options_json: String,
+    metadata: Vec<(String, String)>,
+    /// Only for database connections.
+    policy: Option<PasswordPolicyForm>,
+    errors: FormErrors,
+}
+
+#[derive(Template)]
+#[template(path = "connections/matrix.html")]
+struct MatrixTemplate {
+    operator: Operator,
+    tenant: Tenant,
+    applications: Vec<(String, String)>,
+    rows: Vec<MatrixRow>,
+    error: Option<AppError>,
+}
+
+#[derive(Template)]
+#[template(path = "connections/matrix_cell.html")]
+struct MatrixCellTemplate {
+    operator: Operator,
+    tenant: Tenant,
+    cell: MatrixCell,
+}
+
+struct MatrixRow {
+    id: String,
+    name: String,
+    strategy: String,
+    cells: Vec<MatrixCell>,
+}
+
+struct MatrixCell {
+    connection_id: String,
+    client_id: String,
+    enabled: bool,
+    /// Why the last toggle failed, shown as a tooltip.
+    error: Option<String>,
+}
+
+/// An application that can be enabled for the connection.
+struct ClientChoice {
+    client_id: String,
+    name: String,
+    enabled: bool,
+}
+
+struct OptionToggle {
+    key: &'static str,
+    label: &'static str,
+    enabled: bool,
+}
+
+#[derive(Deserialize)]
+pub struct ConnectionPath {
+    id: String,
+}
+
+#[derive(Deserialize)]
+pub struct MatrixCellPath {
+    id: String,
+    client_id: String,
+}
+
+#[derive(Deserialize, Default)]
+pub struct ListQuery {
+    #[serde(default, deserialize_with = "empty_as_none")]
+    page: Option<u32>,
+    #[serde(default, deserialize_with = "empty_as_none")]
+    per_page: Option<u32>,
+    #[serde(default, deserialize_with = "empty_as_none")]
+    jump: Option<u32>,
 }
 
 pub async fn list(
-    State(state): State<AppState>,
+    RequireViewer(operator): RequireViewer,
+    tenant: Tenant,
+    Query(query): Query<ListQuery>,
     headers: HeaderMap,
 ) -> AppResult<Response> {
+    let page = requested_page(query.page, query.jump);
+    let per_page = requested_per_page(query.per_page, 50);
+
     let params = ListConnectionsParams {
-        page: Some(0),
-        per_page: Some(100),
-        include_totals: Some(false),
+        page: Some(page),
+        per_page: Some(per_page),
+        include_totals: Some(true),
         ..Default::default()
     };
 
-    let connections = match state.client.connections().list(Some(params)).await {
-        Ok(connections) => connections,
+    let params = &params;
+    let (connections, start, limit, total, error) = match tenant
+        .client
+        .get(|c| async move { c.connections().list_paginated(Some(params.clone())).await })
+        .await
+    {
+        Ok(result) => (result.items, result.start, result.limit, result.total, None),
         Err(e) => {
             tracing::error!(error = ?e, "failed to list connections");
-            Vec::new()
+            (Vec::new(), page * per_page, per_page, 0, Some(e.into()))
         }
     };
+    let pagination = Pagination::new(
+        &tenant.path("/connections"),
+        "#connections-list",
+        start,
+        limit,
+        total,
+    );
 
     if is_htmx_request(&headers) {
-        render(TableTemplate { connections })
+        render(TableTemplate {
+            tenant,
+            connections,
+            pagination,
+            error,
+        })
     } else {
-        render(ListTemplate { connections })
+        let form = CreateForm::default();
+        let policy = form.policy.clone();
+        render(ListTemplate {
+            operator,
+            tenant,
+            connections,
+            pagination,
+            error,
+            form,
+            policy,
+            errors: FormErrors::new(),
+        })
     }
 }
 
-pub async fn get_connection_names(client: &ManagementClient) -> Vec<String> {
-    match client.connections().list(None).await {
-        Ok(connections) => connections.into_iter().map(|c| c.name).collect(),
+/// Connection names for filters and forms, served from the cache.
+pub async fn get_connection_names(tenant: &Tenant) -> AppResult<Vec<String>> {
+    match tenant.cache.connections(&tenant.client).await {
+        Ok(connections) => Ok(connections.iter().map(|c| c.name.clone()).collect()),
         Err(e) => {
             tracing::error!(error = ?e, "failed to get connection names");
+            Err(e.into())
+        }
+    }
+}
+
+async fn fetch_connection(tenant: &Tenant, id: &str) -> Result<Connection, auth0_mgmt_api::Error> {
+    tenant
+        .client
+        .get(|c| async move { c.connections().get(ConnectionId::new(id)).await })
+        .await
+}
+
+/// Database connections, the only kind whose options and password policy
+/// are edited here.
+fn is_database(connection: &Connection) -> bool {
+    matches!(connection.strategy, ConnectionStrategy::Auth0)
+}
+
+fn options_value(connection: &Connection) -> Value {
+    match serde_json::to_value(&connection.options) {
+        Ok(Value::Object(options)) => Value::Object(options),
+        _ => Value::Object(Default::default()),
+    }
+}
+
+fn option_enabled(options: &Value, key: &str) -> bool {
+    options.get(key).and_then(Value::as_bool).unwrap_or(false)
+}
+
+/// Social and enterprise connections keep their provider secrets in
+/// `options`, and custom database connections keep theirs in
+/// `configuration`; neither belongs on screen.
+fn redact_secrets(value: &mut Value) {
+    match value {
+        Value::Object(map) => {
+            for (key, value) in map.iter_mut() {
+                if key.to_lowercase().contains("secret") || key == "configuration" {
+                    *value = Value::String("[redacted]".to_string());
+                } else {
+                    redact_secrets(value);
+                }
+            }
+        }
+        Value::Array(items) => items.iter_mut().for_each(redact_secrets),
+        _ => {}
+    }
+}
+
+/// Renders the detail page. `policy` carries a submitted password policy
+/// back after a validation error; otherwise it is read from the connection.
+async fn detail_page(
+    operator: Operator,
+    tenant: Tenant,
+    connection: Connection,
+    policy: Option<PasswordPolicyForm>,
+    mut errors: FormErrors,
+) -> AppResult<Response> {
+    let enabled: BTreeSet<String> = connection
+        .enabled_clients
+        .clone()
+        .unwrap_or_default()
+        .into_iter()
+        .collect();
+
+    let mut clients: Vec<ClientChoice> = match tenant.cache.clients(&tenant.client).await {
+        Ok(all) => all
+            .iter()
+            .map(|client| ClientChoice {
+                client_id: client.client_id.clone(),
+                name: client
+                    .name
+                    .clone()
+                    .unwrap_or_else(|| client.client_id.clone()),
+                enabled: enabled.contains(&client.client_id),
+            })
+            .collect(),
+        Err(e) => {
+            tracing::error!(error = ?e, "failed to list applications");
+            errors.add_base(&format!(
+                "Could not load application names: {}",
+                AppError::from(e).detail()
+            ));
             Vec::new()
         }
+    };
+    // Keep enabled applications we could not resolve, so saving the form
+    // does not silently disable them.
+    for client_id in &enabled {
+        if !clients.iter().any(|c| &c.client_id == client_id) {
+            clients.push(ClientChoice {
+                client_id: client_id.clone(),
+                name: client_id.clone(),
+                enabled: true,
+            });
+        }
+    }
+    clients.sort_by(|a, b| b.enabled.cmp(&a.enabled).then_with(|| a.name.cmp(&b.name)));
+
+    let strategy = format!("{:?}", connection.strategy);
+    let database = is_database(&connection);
+    let options = options_value(&connection);
+    let toggles = if database {
+        DATABASE_OPTIONS
+            .iter()
+            .map(|(key, label)| OptionToggle {
+                key,
+                label,
+                enabled: option_enabled(&options, key),
+            })
+            .collect()
+    } else {
+        Vec::new()
+    };
+
+    let policy = if database {
+        Some(policy.unwrap_or_else(|| PasswordPolicyForm::from_options(&options)))
+    } else {
+        None
+    };
+
+    let mut redacted = options;
+    redact_secrets(&mut redacted);
+    let options_json = serde_json::to_string_pretty(&redacted).unwrap_or_default();
+
+    let metadata = match serde_json::to_value(&connection.metadata) {
+        Ok(Value::Object(map)) => map
+            .into_iter()
+            .map(|(key, value)| match value {
+                Value::String(value) => (key, value),
+                other => (key, other.to_string()),
+            })
+            .collect(),
+        _ => Vec::new(),
+    };
+
+    render(DetailTemplate {
+        operator,
+        tenant,
+        connection,
+        strategy,
+        clients,
+        toggles,
+        options_json,
+        metadata,
+        policy,
+        errors,
+    })
+}
+
+pub async fn get(
+    RequireViewer(operator): RequireViewer,
+    tenant: Tenant,
+    Path(ConnectionPath { id }): Path<ConnectionPath>,
+) -> AppResult<Response> {
+    let connection = fetch_connection(&tenant, &id).await.map_err(|e| {
+        tracing::warn!(error = ?e, %id, "failed to get connection");
+        AppError::from(e)
+    })?;
+
+    detail_page(operator, tenant, connection, None, FormErrors::new()).await
+}
+
+#[derive(Deserialize, Validate)]
+pub struct UpdateForm {
+    #[validate(length(max = 128, message = "Display name must be at most 128 characters"))]
+    display_name: Option<String>,
+    #[serde(default)]
+    enabled_clients: Vec<String>,
+    /// Keys of the `DATABASE_OPTIONS` switches that are on.
+    #[serde(default)]
+    options: Vec<String>,
+}
+
+pub async fn update(
+    State(state): State<AppState>,
+    RequireAdmin(operator): RequireAdmin,
+    tenant: Tenant,
+    Path(ConnectionPath { id }): Path<ConnectionPath>,
+    Form(form): Form<UpdateForm>,
+) -> AppResult<Response> {
+    let connection = fetch_connection(&tenant, &id)
+        .await
+        .map_err(AppError::from)?;
+
+    if let Err(e) = form.validate() {
+        return detail_page(operator, tenant, connection, None, FormErrors::from(e)).await;
+    }
+
+    let mut changes = Vec::new();
+    let mut request = UpdateConnectionRequest::default();
+
+    let display_name = form.display_name.filter(|s| !s.is_empty());
+    if display_name.is_some() && display_name != connection.display_name {
+        changes.push(FieldChange::new(
+            "display_name",
+            connection.display_name.as_deref(),
+            display_name.as_deref(),
+        ));
+        request.display_name = display_name;
+    }
+
+    let before: BTreeSet<String> = connection
+        .enabled_clients
+        .clone()
+        .unwrap_or_default()
+        .into_iter()
+        .collect();
+    let after: BTreeSet<String> = form.enabled_clients.into_iter().collect();
+    if before != after {
+        let join = |ids: &BTreeSet<String>| ids.iter().cloned().collect::<Vec<_>>().join(", ");
+        changes.push(FieldChange::new(
+            "enabled_clients",
+            Some(&join(&before)),
+            Some(&join(&after)),
+        ));
+        request.enabled_clients = Some(after.into_iter().collect());
+    }
+
+    // Auth0 replaces `options` wholesale, so send the current object with
+    // only the switches changed.
+    if is_database(&connection) {
+        let mut options = options_value(&connection);
+        let mut options_changed = false;
+        for (key, _) in DATABASE_OPTIONS {
+            let was = option_enabled(&options, key);
+            let now = form.options.iter().any(|o| o == key);
+            if was != now {
+                changes.push(FieldChange::new(
+                    &format!("options.{}", key),
+                    Some(&was.to_string()),
+                    Some(&now.to_string()),
+                ));
+                options[*key] = Value::Bool(now);
+                options_changed = true;
+            }
+        }
+        if options_changed {
+            request.options = Some(options);
+        }
+    }
+
+    if changes.is_empty() {
+        return detail_page(operator, tenant, connection, None, FormErrors::new()).await;
+    }
+
+    let connection_id = ConnectionId::new(&id);
+    let result = tenant
+        .client
+        .send(|c| async move { c.connections().update(connection_id, request).await })
+        .await;
+    let error = result.as_ref().err().map(|e| e.to_string());
+    state
+        .audit
+        .record(
+            &operator,
+            &tenant,
+            Action::UpdateConnection,
+            &id,
+            changes,
+            error,
+        )
+        .await?;
+
+    match result {
+        Ok(updated) => {
+            tenant.cache.connections.invalidate();
+            detail_page(operator, tenant, updated, None, FormErrors::new()).await
+        }
+        Err(e) => {
+            tracing::error!(error = ?e, %id, "failed to update connection");
+            let mut errors = FormErrors::new();
+            errors.add_base(&format!(
+                "Failed to update connection: {}",
+                AppError::from(e).detail()
+            ));
+            detail_page(operator, tenant, connection, None, errors).await
+        }
+    }
+}
+
+/// The password settings of a database connection, as the form shows
+/// them. Checkboxes are `Some` when ticked; the dictionary is one word
+/// per line.
+#[derive(Clone, Deserialize, Default, Validate)]
+pub struct PasswordPolicyForm {
+    #[serde(default)]
+    #[validate(custom(function = "validate_password_policy"))]
+    password_policy: String,
+    #[serde(default, deserialize_with = "empty_as_none")]
+    #[validate(range(min = 1, max = 128, message = "Must be between 1 and 128"))]
+    min_length: Option<u32>,
+    password_history: Option<String>,
+    #[serde(default, deserialize_with = "empty_as_none")]
+    #[validate(range(min = 1, max = 24, message = "Auth0 keeps between 1 and 24 passwords"))]
+    password_history_size: Option<u32>,
+    password_dictionary: Option<String>,
+    #[serde(default)]
+    #[validate(custom(function = "validate_dictionary"))]
+    password_dictionary_words: String,
+    password_no_personal_info: Option<String>,
+}
+
+impl PasswordPolicyForm {
+    /// What a new connection starts with: Auth0's own defaults.
+    fn recommended() -> Self {
+        Self {
+            password_policy: "good".to_string(),
+            min_length: Some(8),
+            password_history_size: Some(5),
+            ..Default::default()
+        }
+    }
+
+    fn from_options(options: &Value) -> Self {
+        let flag = |pointer: &str| {
+            options
+                .pointer(pointer)
+                .and_then(Value::as_bool)
+                .unwrap_or(false)
+                .then(|| "on".to_string())
+        };
+        let number = |pointer: &str| {
+            options
+                .pointer(pointer)
+                .and_then(Value::as_u64)
+                .and_then(|n| u32::try_from(n).ok())
+        };
+        let words = options
+            .pointer("/password_dictionary/dictionary")
+            .and_then(Value::as_array)
+            .map(|words| {
+                words
+                    .iter()
+                    .filter_map(Value::as_str)
+                    .collect::<Vec<_>>()
+                    .join("\n")
+            })
+            .unwrap_or_default();
+
+        Self {
+            password_policy: options
+                .get("passwordPolicy")
+                .and_then(Value::as_str)
+                .unwrap_or("none")
+                .to_string(),
+            min_length: number("/password_complexity_options/min_length"),
+            password_history: flag("/password_history/enable"),
+            password_history_size: number("/password_history/size"),
+            password_dictionary: flag("/password_dictionary/enable"),
+            password_dictionary_words: words,
+            password_no_personal_info: flag("/password_no_personal_info/enable"),
+        }
+    }
+
+    pub fn policies(&self) -> &'static [(&'static str, &'static str)] {
+        PASSWORD_POLICIES
+    }
+
+    pub fn max_history(&self) -> u32 {
+        MAX_PASSWORD_HISTORY
+    }
+
+    fn dictionary_words(&self) -> Vec<String> {
+        self.password_dictionary_words
+            .lines()
+            .map(str::trim)
+            .filter(|word| !word.is_empty())
+            .map(str::to_string)
+            .collect()
+    }
+
+    /// Writes the settings that differ from `before` into `options` and
+    /// returns them as audit changes. Nothing is written if any setting's
+    /// parent in `options` is something other than an object.
+    fn apply(&self, before: &Self, options: &mut Value) -> Result<Vec<FieldChange>, String> {
+        let mut changes = Vec::new();
+        let mut writes = Vec::new();
+        let mut set =
+            |field: &str, was: String, now: String, path: &'static [&'static str], value: Value| {
+                if was == now {
+                    return;
+                }
+                changes.push(FieldChange::new(
+                    &format!("options.{}", field),
+                    Some(&was),
+                    Some(&now),
+                ));
+                writes.push((path, value));
+            };
+        let number = |n: Option<u32>| n.map(|n| n.to_string()).unwrap_or_default();
+
+        set(
+            "passwordPolicy",
+            before.password_policy.clone(),
+            self.password_policy.clone(),
+            &["passwordPolicy"],
+            json!(self.password_policy),
+        );
+        if let Some(min_length) = self.min_length {
+            set(
+                "password_complexity_options.min_length",
+                number(before.min_length),
+                min_length.to_string(),
+                &["password_complexity_options", "min_length"],
+                json!(min_length),
+            );
+        }
+        set(
+            "password_history.enable",
+            before.password_history.is_some().to_string(),
+            self.password_history.is_some().to_string(),
+            &["password_history", "enable"],
+            json!(self.password_history.is_some()),
+        );
+        if let Some(size) = self.password_history_size {
+            set(
+                "password_history.size",
+                number(before.password_history_size),
+                size.to_string(),
+                &["password_history", "size"],
+                json!(size),
+            );
+        }
+        set(
+            "password_dictionary.enable",
+            before.password_dictionary.is_some().to_string(),
+            self.password_dictionary.is_some().to_string(),
+            &["password_dictionary", "enable"],
+            json!(self.password_dictionary.is_some()),
+        );
+        let words = self.dictionary_words();
+        set(
+            "password_dictionary.dictionary",
+            before.dictionary_words().join(", "),
+            words.join(", "),
+            &["password_dictionary", "dictionary"],
+            json!(words),
+        );
+        set(
+            "password_no_personal_info.enable",
+            before.password_no_personal_info.is_some().to_string(),
+            self.password_no_personal_info.is_some().to_string(),
+            &["password_no_personal_info", "enable"],
+            json!(self.password_no_personal_info.is_some()),
+        );
+
+        let mut updated = options.clone();
+        for (path, value) in writes {
+            set_path(&mut updated, path, value)?;
+        }
+        *options = updated;
+        Ok(changes)
+    }
+}
+
+/// Sets `value` at `path` inside `options`, creating missing or null
+/// objects along the way.
+fn set_path(options: &mut Value, path: &[&str], value: Value) -> Result<(), String> {
+    let Some((last, parents)) = path.split_last() else {
+        return Ok(());
+    };
+    let mut target = options
+        .as_object_mut()
+        .ok_or_else(|| "Connection options are not an object".to_string())?;
+    for (depth, key) in parents.iter().enumerate() {
+        let entry = target.entry(*key).or_insert(Value::Null);
+        if entry.is_null() {
+            *entry = json!({});
+        }
+        target = entry.as_object_mut().ok_or_else(|| {
+            format!(
+                "options.{} is not an object in this connection",
+                path[..=depth].join(".")
+            )
+        })?;
+    }
+    target.insert(last.to_string(), value);
+    Ok(())
+}
+
+fn validate_password_policy(policy: &str) -> Result<(), ValidationError> {
+    if PASSWORD_POLICIES.iter().any(|(p, _)| *p == policy) {
+        Ok(())
+    } else {
+        Err(invalid(
+            "password_policy",
+            "Choose a password policy".to_string(),
+        ))
+    }
+}
+
+fn validate_dictionary(words: &str) -> Result<(), ValidationError> {
+    match words
+        .lines()
+        .map(str::trim)
+        .find(|word| word.chars().count() > MAX_DICTIONARY_WORD)
+    {
+        Some(word) => Err(invalid(
+            "dictionary",
+            format!("{} is longer than {} characters", word, MAX_DICTIONARY_WORD),
+        )),
+        None => Ok(()),
+    }
+}
+
+/// Auth0 connection names: letters, digits and hyphens, not starting or
+/// ending with a hyphen.
+fn validate_connection_name(name: &str) -> Result<(), ValidationError> {
+    let valid = !name.is_empty()
+        && name.len() <= 128
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+        && !name.starts_with('-')
+        && !name.ends_with('-');
+    if valid {
+        Ok(())
+    } else {
+        Err(invalid(
+            "connection_name",
+            "Use letters, digits and hyphens, not starting or ending with a hyphen".to_string(),
+        ))
+    }
+}
+
+#[derive(Clone, Deserialize)]
+pub struct CreateForm {
+    #[serde(default)]
+    name: String,
+    display_name: Option<String>,
+    disable_signup: Option<String>,
+    requires_username: Option<String>,
+    brute_force_protection: Option<String>,
+    #[serde(flatten)]
+    policy: PasswordPolicyForm,
+}
+
+impl Default for CreateForm {
+    fn default() -> Self {
+        Self {
+            name: String::new(),
+            display_name: None,
+            disable_signup: None,
+            requires_username: None,
+            brute_force_protection: Some("on".to_string()),
+            policy: PasswordPolicyForm::recommended(),
+        }
+    }
+}
+
+impl CreateForm {
+    /// The name is checked alongside the password policy so every problem
+    /// is reported at once.
+    fn validate(&self) -> Result<(), ValidationErrors> {
+        let mut errors = self
+            .policy
+            .validate()
+            .err()
+            .unwrap_or_else(ValidationErrors::new);
+        if let Err(e) = validate_connection_name(&self.name) {
+            errors.add("name", e);
+        }
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+}
+
+/// Creates an `auth0` (database) connection. Applications are enabled
+/// afterwards on the connection page, where the operator is sent.
+pub async fn create(
+    State(state): State<AppState>,
+    RequireAdmin(operator): RequireAdmin,
+    tenant: Tenant,
+    Form(form): Form<CreateForm>,
+) -> AppResult<Response> {
+    if let Err(e) = form.validate() {
+        let policy = form.policy.clone();
+        return render(CreateFormTemplate {
+            tenant,
+            form,
+            policy,
+            errors: FormErrors::from(e),
+        });
+    }
+
+    let name = form.name.trim().to_string();
+    let display_name = form.display_name.clone().filter(|s| !s.is_empty());
+    let mut options = json!({
+        "disable_signup": form.disable_signup.is_some(),
+        "requires_username": form.requires_username.is_some(),
+        "brute_force_protection": form.brute_force_protection.is_some(),
+    });
+    let mut changes = vec![
+        FieldChange::new("name", None, Some(&name)),
+        FieldChange::new("strategy", None, Some("auth0")),
+        FieldChange::new("display_name", None, display_name.as_deref()),
+    ];
+    for key in [
+        "disable_signup",
+        "requires_username",
+        "brute_force_protection",
+    ] {
+        changes.push(FieldChange::new(
+            &format!("options.{}", key),
+            None,
+            Some(&options[key].to_string()),
+        ));
+    }
+    match form
+        .policy
+        .apply(&PasswordPolicyForm::default(), &mut options)
+    {
+        Ok(policy_changes) => changes.extend(policy_changes),
+        Err(message) => {
+            let mut errors = FormErrors::new();
+            errors.add_base(&message);
+            let policy = form.policy.clone();
+            return render(CreateFormTemplate {
+                tenant,
+                form,
+                policy,
+                errors,
+            });
+        }
+    }
+
+    let request = CreateConnectionRequest {
+        name: name.clone(),
+        strategy: ConnectionStrategy::Auth0,
+        display_name,
+        options: Some(options),
+        ..Default::default()
+    };
+
+    let result = tenant
+        .client
+        .send(|c| async move { c.connections().create(request).await })
+        .await;
+    let target = match &result {
+        Ok(connection) => connection.id.clone(),
+        Err(_) => name,
+    };
+    let error = result.as_ref().err().map(|e| e.to_string());
+    state
+        .audit
+        .record(
+            &operator,
+            &tenant,
+            Action::CreateConnection,
+            &target,
+            changes,
+            error,
+        )
+        .await?;
+
+    match result {
+        Ok(connection) => {
+            tenant.cache.connections.invalidate();
+            let detail = tenant.path(&format!("/connections/{}", connection.id));
+            Ok([("hx-redirect", detail)].into_response())
+        }
+        Err(e) => {
+            tracing::error!(error = ?e, "failed to create connection");
+            let mut errors = FormErrors::new();
+            errors.add_base(&format!(
+                "Failed to create connection: {}",
+                AppError::from(e).detail()
+            ));
+            let policy = form.policy.clone();
+            render(CreateFormTemplate {
+                tenant,
+                form,
+                policy,
+                errors,
+            })
+        }
+    }
+}
+
+pub async fn update_password_policy(
+    State(state): State<AppState>,
+    RequireAdmin(operator): RequireAdmin,
+    tenant: Tenant,
+    Path(ConnectionPath { id }): Path<ConnectionPath>,
+    Form(policy): Form<PasswordPolicyForm>,
+) -> AppResult<Response> {
+    let connection = fetch_connection(&tenant, &id)
+        .await
+        .map_err(AppError::from)?;
+    if !is_database(&connection) {
+        return Err(AppError::NotFound);
+    }
+
+    if let Err(e) = policy.validate() {
+        return detail_page(
+            operator,
+            tenant,
+            connection,
+            Some(policy),
+            FormErrors::from(e),
+        )
+        .await;
+    }
+
+    // As with the switches, Auth0 replaces `options` wholesale.
+    let mut options = options_value(&connection);
+    let before = PasswordPolicyForm::from_options(&options);
+    let changes = match policy.apply(&before, &mut options) {
+        Ok(changes) => changes,
+        Err(message) => {
+            let mut errors = FormErrors::new();
+            errors.add_base(&message);
+            return detail_page(operator, tenant, connection, Some(policy), errors).await;
+        }
+    };
+    if changes.is_empty() {
+        return detail_page(operator, tenant, connection, None, FormErrors::new()).await;
+    }
+
+    let request = UpdateConnectionRequest {
+        options: Some(options),
+        ..Default::default()
+    };
+    let connection_id = ConnectionId::new(&id);
+    let result = tenant
+        .client
+        .send(|c| async move { c.connections().update(connection_id, request).await })
+        .await;
+    let error = result.as_ref().err().map(|e| e.to_string());
+    state
+        .audit
+        .record(
+            &operator,
+            &tenant,
+            Action::UpdateConnection,
+            &id,
+            changes,
+            error,
+        )
+        .await?;
+
+    match result {
+        Ok(updated) => {
+            tenant.cache.connections.invalidate();
+            detail_page(operator, tenant, updated, None, FormErrors::new()).await
+        }
+        Err(e) => {
+            tracing::error!(error = ?e, %id, "failed to update password policy");
+            let mut errors = FormErrors::new();
+            errors.add_base(&format!(
+                "Failed to update password policy: {}",
+                AppError::from(e).detail()
+            ));
+            detail_page(operator, tenant, connection, Some(policy), errors).await
+        }
+    }
+}
+
+/// Connections as rows and applications as columns, from the cached
+/// lists, with a checkbox for each `enabled_clients` entry.
+pub async fn matrix(RequireViewer(operator): RequireViewer, tenant: Tenant) -> AppResult<Response> {
+    let lists = tokio::try_join!(
+        tenant.cache.connections(&tenant.client),
+        tenant.cache.clients(&tenant.client),
+    );
+    let (connections, clients) = match lists {
+        Ok(lists) => lists,
+        Err(e) => {
+            tracing::error!(error = ?e, "failed to load connection matrix");
+            return render(MatrixTemplate {
+                operator,
+                tenant,
+                applications: Vec::new(),
+                rows: Vec::new(),
+                error: Some(e.into()),
+            });
+        }
+    };
+
+    let mut applications: Vec<(String, String)> = clients
+        .iter()
+        .map(|client| {
+            let name = client
+                .name
+                .clone()
+                .unwrap_or_else(|| client.client_id.clone());
+            (client.client_id.clone(), name)
+        })
+        .collect();
+    applications.sort_by(|a, b| a.1.to_lowercase().cmp(&b.1.to_lowercase()));
+
+    let mut rows: Vec<MatrixRow> = connections
+        .iter()
+        .map(|connection| {
+            let enabled = connection.enabled_clients.as_deref().unwrap_or_default();
+            MatrixRow {
+                id: connection.id.clone(),
+                name: connection.name.clone(),
+                strategy: format!("{:?}", connection.strategy),
+                cells: applications
+                    .iter()
+                    .map(|(client_id, _)| MatrixCell {
+                        connection_id: connection.id.clone(),
+                        client_id: client_id.clone(),
+                        enabled: enabled.contains(client_id),
+                        error: None,
+                    })
+                    .collect(),
+            }
+        })
+        .collect();
+    rows.sort_by(|a, b| a.name.cmp(&b.name));
+
+    render(MatrixTemplate {
+        operator,
+        tenant,
+        applications,
+        rows,
+        error: None,
+    })
+}
+
+#[derive(Deserialize)]
+pub struct MatrixToggleForm {
+    /// Present when the checkbox is ticked.
+    enabled: Option<String>,
+}
+
+/// The enabled applications after ticking or unticking `client_id`, or
+/// `None` if that changes nothing.
+fn toggled(before: &BTreeSet<String>, client_id: &str, want: bool) -> Option<BTreeSet<String>> {
+    let mut after = before.clone();
+    if want {
+        after.insert(client_id.to_string());
+    } else {
+        after.remove(client_id);
+    }
+    (after != *before).then_some(after)
+}
+
+/// Flips one cell of the matrix. The connection is re-read first so a
+/// stale cached list cannot undo someone else's change.
+pub async fn toggle_client(
+    State(state): State<AppState>,
+    RequireAdmin(operator): RequireAdmin,
+    tenant: Tenant,
+    Path(MatrixCellPath { id, client_id }): Path<MatrixCellPath>,
+    Form(form): Form<MatrixToggleForm>,
+) -> AppResult<Response> {
+    let want = form.enabled.is_some();
+    let mut cell = MatrixCell {
+        connection_id: id.clone(),
+        client_id: client_id.clone(),
+        enabled: !want,
+        error: None,
+    };
+
+    let connection = match fetch_connection(&tenant, &id).await {
+        Ok(connection) => connection,
+        Err(e) => {
+            tracing::error!(error = ?e, %id, "failed to get connection");
+            cell.error = Some(AppError::from(e).detail());
+            return render(MatrixCellTemplate {
+                operator,
+                tenant,
+                cell,
+            });
+        }
+    };
+
+    let before: BTreeSet<String> = connection
+        .enabled_clients
+        .clone()
+        .unwrap_or_default()
+        .into_iter()
+        .collect();
+    let Some(after) = toggled(&before, &client_id, want) else {
+        cell.enabled = want;
+        return render(MatrixCellTemplate {
+            operator,
+            tenant,
+            cell,
+        });
+    };
+
+    let join = |ids: &BTreeSet<String>| ids.iter().cloned().collect::<Vec<_>>().join(", ");
+    let changes = vec![FieldChange::new(
+        "enabled_clients",
+        Some(&join(&before)),
+        Some(&join(&after)),
+    )];
+    let request = UpdateConnectionRequest {
+        enabled_clients: Some(after.into_iter().collect()),
+        ..Default::default()
+    };
+    let connection_id = ConnectionId::new(&id);
+    let result = tenant
+        .client
+        .send(|c| async move { c.connections().update(connection_id, request).await })
+        .await;
+    let error = result.as_ref().err().map(|e| e.to_string());
+    state
+        .audit
+        .record(
+            &operator,
+            &tenant,
+            Action::UpdateConnection,
+            &id,
+            changes,
+            error,
+        )
+        .await?;
+
+    match result {
+        Ok(_) => {
+            tenant.cache.connections.invalidate();
+            cell.enabled = want;
+        }
+        Err(e) => {
+            tracing::error!(error = ?e, %id, %client_id, "failed to toggle application");
+            cell.error = Some(AppError::from(e).detail());
+        }
+    }
+    render(MatrixCellTemplate {
+        operator,
+        tenant,
+        cell,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::{Method, StatusCode};
+
+    use super::*;
+    use crate::auth::Role;
+    use crate::test_support;
+
+    fn policy() -> PasswordPolicyForm {
+        PasswordPolicyForm {
+            password_policy: "excellent".to_string(),
+            min_length: Some(12),
+            password_history: Some("on".to_string()),
+            password_history_size: Some(10),
+            password_dictionary: Some("on".to_string()),
+            password_dictionary_words: "hunter2\n\n  letmein \n".to_string(),
+            password_no_personal_info: Some("on".to_string()),
+        }
+    }
+
+    #[test]
+    fn apply_writes_changed_settings_into_empty_options() {
+        let mut options = json!({});
+        let changes = policy()
+            .apply(&PasswordPolicyForm::default(), &mut options)
+            .unwrap();
+
+        assert_eq!(
+            options,
+            json!({
+                "passwordPolicy": "excellent",
+                "password_complexity_options": { "min_length": 12 },
+                "password_history": { "enable": true, "size": 10 },
+                "password_dictionary": { "enable": true, "dictionary": ["hunter2", "letmein"] },
+                "password_no_personal_info": { "enable": true },
+            })
+        );
+        assert_eq!(changes.len(), 7);
+        assert_eq!(changes[0].field, "options.passwordPolicy");
+        assert_eq!(changes[0].before.as_deref(), Some(""));
+        assert_eq!(changes[0].after.as_deref(), Some("excellent"));
+    }
+
+    #[test]
+    fn apply_keeps_unrelated_options_and_fills_null_parents() {
+        let mut options = json!({
+            "brute_force_protection": true,
+            "password_history": null,
+            "password_complexity_options": { "min_length": 8, "other": "kept" },
+        });
+        let before = PasswordPolicyForm::from_options(&options);
+        let after = PasswordPolicyForm {
+            min_length: Some(10),
+            password_history: Some("on".to_string()),
+            ..before.clone()
+        };
+
+        let changes = after.apply(&before, &mut options).unwrap();
+
+        assert_eq!(changes.len(), 2);
+        assert_eq!(options["brute_force_protection"], json!(true));
+        assert_eq!(
+            options["password_complexity_options"],
+            json!({ "min_length": 10, "other": "kept" })
+        );
+        assert_eq!(options["password_history"], json!({ "enable": true }));
+    }
+
+    #[test]
+    fn apply_without_differences_changes_nothing() {
+        let mut options = json!({ "passwordPolicy": "good" });
+        let before = PasswordPolicyForm::from_options(&options);
+        let changes = before.clone().apply(&before, &mut options).unwrap();
+        assert!(changes.is_empty());
+        assert_eq!(options, json!({ "passwordPolicy": "good" }));
+    }
+
+    #[test]
+    fn apply_rejects_a_non_object_parent_without_writing() {
+        let original = json!({ "passwordPolicy": "none", "password_history": 5 });
+        let mut options = original.clone();
+
+        let result = policy().apply(&PasswordPolicyForm::from_options(&options), &mut options);
+
+        assert_eq!(
+            result.unwrap_err(),
+            "options.password_history is not an object in this connection"
+        );
+        assert_eq!(options, original);
+    }
+
+    #[test]
+    fn apply_rejects_non_object_options() {
+        let mut options = json!([]);
+        let result = policy().apply(&PasswordPolicyForm::default(), &mut options);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn provider_and_custom_database_secrets_are_redacted() {
+        let mut options = json!({
+            "client_id": "abc",
+            "client_secret": "s3cret",
+            "brute_force_protection": true,
+            "upstream_params": { "signingSecret": "x" },
+            "configuration": { "API_KEY": "k" },
+            "keys": [{ "app_secret": "y", "kid": "1" }],
+        });
+        redact_secrets(&mut options);
+
+        assert_eq!(options["client_id"], "abc");
+        assert_eq!(options["client_secret"], "[redacted]");
+        assert_eq!(options["brute_force_protection"], true);
+        assert_eq!(options["upstream_params"]["signingSecret"], "[redacted]");
+        assert_eq!(options["configuration"], "[redacted]");
+        assert_eq!(options["keys"][0]["app_secret"], "[redacted]");
+        assert_eq!(options["keys"][0]["kid"], "1");
+    }
+
+    #[test]
+    fn option_switches_are_on_only_for_true() {
+        let options = json!({ "disable_signup": true, "requires_username": "true" });
+        assert!(option_enabled(&options, "disable_signup"));
+        assert!(!option_enabled(&options, "requires_username"));
+        assert!(!option_enabled(&options, "brute_force_protection"));
+    }
+
+    #[tokio::test]
+    async fn only_admins_configure_connections() {
+        let response = test_support::send_as(
+            Some(Role::Support),
+            Method::PATCH,
+            "/t/prod/connections/con_1",
+            "display_name=Staff",
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+    }
+
+    #[test]
+    fn toggling_a_cell_changes_only_that_application() {
+        let set = |ids: &[&str]| ids.iter().map(|id| id.to_string()).collect::<BTreeSet<_>>();
+        let before = set(&["app_a", "app_b"]);
+
+        assert_eq!(
+            toggled(&before, "app_c", true),
+            Some(set(&["app_a", "app_b", "app_c"]))
+        );
+        assert_eq!(toggled(&before, "app_a", false), Some(set(&["app_b"])));
+        assert_eq!(toggled(&before, "app_a", true), None);
+        assert_eq!(toggled(&before, "app_c", false), None);
+    }
+
+    #[tokio::test]
+    async fn only_admins_toggle_matrix_cells() {
+        let uri = "/t/prod/connections/con_1/clients/app_1";
+        for role in [Role::Viewer, Role::Support] {
+            let response = test_support::send_as(Some(role), Method::PUT, uri, "enabled=on").await;
+            assert_eq!(response.status(), StatusCode::FORBIDDEN, "{:?}", role);
+        }
     }
 }
@@ -0,0 +1,110 @@
+use std::borrow::Cow;
+
+use auth0_mgmt_api::{
+    types::{
+        client_grants::{ClientGrant, ListClientGrantsParams},
+        resource_servers::{ListResourceServersParams, ResourceServer},
+        roles::{ListRolePermissionsParams, Permission},
+    },
+    RoleId,
+};
+use htmx_form_errors::FormErrors;
+use validator::{ValidationError, ValidationErrors};
+
+use crate::cache::{fetch_all, PAGE_SIZE};
+use crate::tenant::Tenant;
+
+pub fn invalid(code: &'static str, message: impl Into<Cow<'static, str>>) -> ValidationError {
+    ValidationError::new(code).with_message(message.into())
+}
+
+/// Delete problems are shown next to the confirmation box rather than at
+/// the top of the page.
+pub fn confirm_error(message: String) -> FormErrors {
+    let mut errors = ValidationErrors::new();
+    errors.add("confirm", invalid("confirm", message));
+    FormErrors::from(errors)
+}
+
+/// Which client grants to list: those for an API or those of a client.
+pub enum GrantsOf<'a> {
+    Api(&'a str),
+    Client(&'a str),
+}
+
+/// Every client grant for an API or a client, across all pages.
+pub async fn fetch_grants(
+    tenant: &Tenant,
+    of: GrantsOf<'_>,
+) -> Result<Vec<ClientGrant>, auth0_mgmt_api::Error> {
+    let (audience, client_id) = match of {
+        GrantsOf::Api(audience) => (Some(audience), None),
+        GrantsOf::Client(client_id) => (None, Some(client_id)),
+    };
+    fetch_all(|page| async move {
+        let params = ListClientGrantsParams {
+            audience: audience.map(str::to_string),
+            client_id: client_id.map(str::to_string),
+            page: Some(page),
+            per_page: Some(PAGE_SIZE),
+            include_totals: Some(true),
+            ..Default::default()
+        };
+        let params = &params;
+        let result = tenant
+            .client
+            .get(|c| async move { c.client_grants().list_paginated(Some(params.clone())).await })
+            .await?;
+        Ok((result.items, result.total))
+    })
+    .await
+}
+
+/// Every permission of a role, across all pages.
+pub async fn fetch_role_permissions(
+    tenant: &Tenant,
+    role_id: &str,
+) -> Result<Vec<Permission>, auth0_mgmt_api::Error> {
+    fetch_all(|page| async move {
+        let params = ListRolePermissionsParams {
+            page: Some(page),
+            per_page: Some(PAGE_SIZE),
+            include_totals: Some(true),
+            ..Default::default()
+        };
+        let params = &params;
+        let result = tenant
+            .client
+            .get(|c| async move {
+                c.roles()
+                    .permissions_paginated(RoleId::new(role_id), Some(params.clone()))
+                    .await
+            })
+            .await?;
+        Ok((result.items, result.total))
+    })
+    .await
+}
+
+/// Every API in the tenant, across all pages.
+pub async fn fetch_apis(tenant: &Tenant) -> Result<Vec<ResourceServer>, auth0_mgmt_api::Error> {
+    fetch_all(|page| async move {
+        let params = ListResourceServersParams {
+            page: Some(page),
+            per_page: Some(PAGE_SIZE),
+            include_totals: Some(true),
+            ..Default::default()
+        };
+        let params = &params;
+        let result = tenant
+            .client
+            .get(|c| async move {
+                c.resource_servers()
+                    .list_paginated(Some(params.clone()))
+                    .await
+            })
+            .await?;
+        Ok((result.items, result.total))
+    })
+    .await
+}
@@ -1,78 +1,323 @@
+use std::convert::Infallible;
+
 use askama::Template;
-use auth0_mgmt_api::types::logs::ListLogsParams;
+use auth0_mgmt_api::types::logs::{ListLogsParams, LogEvent};
 use axum::{
-    extract::{Query, State},
-    http::HeaderMap,
-    response::Response,
+    extract::Query,
+    http::{HeaderMap, StatusCode},
+    response::{
+        sse::{Event, KeepAlive, Sse},
+        IntoResponse, Response,
+    },
 };
 use serde::Deserialize;
+use tokio_stream::{wrappers::BroadcastStream, StreamExt};
 
-use crate::errors::AppResult;
-use crate::helpers::{is_htmx_request, total_pages};
-use crate::state::AppState;
+use crate::auth::{Operator, RequireViewer};
+use crate::errors::{AppError, AppResult};
+use crate::helpers::{empty_as_none, is_htmx_request};
+use crate::log_tail::LogFilter;
 use crate::templates::render;
+use crate::tenant::Tenant;
+
+const DEFAULT_TAKE: u32 = 50;
+/// Auth0 rejects searches with `per_page` above 100.
+const MAX_TAKE: u32 = 100;
 
 #[derive(Template)]
 #[template(path = "logs/list.html")]
 struct ListTemplate {
-    logs: Vec<auth0_mgmt_api::types::logs::LogEvent>,
-    page: u32,
-    total_pages: u32,
+    operator: Operator,
+    tenant: Tenant,
+    logs: Vec<LogEvent>,
+    older_url: Option<String>,
+    live_url: Option<String>,
+    live_refused: Option<String>,
     search_query: String,
+    live: bool,
+    error: Option<AppError>,
 }
 
 #[derive(Template)]
 #[template(path = "logs/table.html")]
 struct TableTemplate {
-    logs: Vec<auth0_mgmt_api::types::logs::LogEvent>,
-    page: u32,
-    total_pages: u32,
+    tenant: Tenant,
+    logs: Vec<LogEvent>,
+    older_url: Option<String>,
+    live_url: Option<String>,
+    live_refused: Option<String>,
+    error: Option<AppError>,
+}
+
+#[derive(Template)]
+#[template(path = "logs/rows.html")]
+struct RowsTemplate {
+    tenant: Tenant,
+    logs: Vec<LogEvent>,
+    older_url: Option<String>,
 }
 
 #[derive(Deserialize, Default)]
 pub struct ListQuery {
-    page: Option<u32>,
     q: Option<String>,
+    /// Timestamp of the oldest event already shown; set when loading older
+    /// events.
+    before: Option<String>,
+    /// Comma-separated `log_id`s of the shown events at exactly `before`,
+    /// which the inclusive date range returns again.
+    seen: Option<String>,
+    #[serde(default, deserialize_with = "empty_as_none")]
+    take: Option<u32>,
+    /// Set by the "Live tail" switch on the search form.
+    live: Option<String>,
+}
+
+/// Link for the infinite-scroll sentinel, continuing from the oldest event
+/// shown. Only offered when the last batch was full.
+///
+/// Auth0's `from` checkpoint pages forward in time and ignores `q`, and
+/// `page` stops at the first 1000 results, so older events are found by
+/// searching newest-first again below the oldest timestamp shown.
+fn older_url(path: &str, logs: &[LogEvent], take: u32, q: Option<&str>) -> Option<String> {
+    if logs.len() < take as usize {
+        return None;
+    }
+    let before = logs.last()?.date.as_deref()?;
+    let seen: Vec<&str> = logs
+        .iter()
+        .rev()
+        .take_while(|e| e.date.as_deref() == Some(before))
+        .filter_map(|e| e.log_id.as_deref())
+        .collect();
+    Some(older_link(path, before, &seen, take, q))
+}
+
+fn older_link(path: &str, before: &str, seen: &[&str], take: u32, q: Option<&str>) -> String {
+    let mut query = url::form_urlencoded::Serializer::new(String::new());
+    query.append_pair("before", before);
+    if !seen.is_empty() {
+        query.append_pair("seen", &seen.join(","));
+    }
+    query.append_pair("take", &take.to_string());
+    if let Some(q) = q.filter(|q| !q.is_empty()) {
+        query.append_pair("q", q);
+    }
+    format!("{}?{}", path, query.finish())
+}
+
+/// The operator's search narrowed to events at or before `before`.
+fn search_before(q: Option<&str>, before: &str) -> String {
+    let range = format!("date:[* TO \"{}\"]", before);
+    match q.filter(|q| !q.trim().is_empty()) {
+        Some(q) => format!("({}) AND {}", q, range),
+        None => range,
+    }
+}
+
+/// Only timestamps go into the date range, so `before` can't smuggle in
+/// other search syntax.
+fn is_timestamp(value: &str) -> bool {
+    !value.is_empty()
+        && value
+            .chars()
+            .all(|c| c.is_ascii_digit() || matches!(c, '-' | ':' | '.' | 'T' | 'Z' | '+'))
+}
+
+fn live_url(tenant: &Tenant, q: Option<&str>) -> String {
+    let stream = tenant.path("/logs/stream");
+    match q.filter(|q| !q.is_empty()) {
+        Some(q) => format!(
+            "{}?{}",
+            stream,
+            url::form_urlencoded::Serializer::new(String::new())
+                .append_pair("q", q)
+                .finish()
+        ),
+        None => stream,
+    }
 }
 
 pub async fn list(
-    State(state): State<AppState>,
+    RequireViewer(operator): RequireViewer,
+    tenant: Tenant,
     Query(query): Query<ListQuery>,
     headers: HeaderMap,
 ) -> AppResult<Response> {
-    let page = query.page.unwrap_or(0);
-    let per_page = 50;
+    let take = query.take.unwrap_or(DEFAULT_TAKE).clamp(1, MAX_TAKE);
 
+    let q = query.q.as_deref().filter(|q| !q.is_empty());
+    let before = query
+        .before
+        .as_deref()
+        .filter(|before| is_timestamp(before));
     let params = ListLogsParams {
-        page: Some(page),
-        per_page: Some(per_page),
-        include_totals: Some(true),
-        q: query.q.clone(),
+        page: Some(0),
+        per_page: Some(take),
+        include_totals: Some(false),
+        q: match before {
+            Some(before) => Some(search_before(q, before)),
+            None => q.map(str::to_string),
+        },
         sort: Some("date:-1".to_string()),
         ..Default::default()
     };
 
-    let logs = match state.client.logs().list(Some(params)).await {
-        Ok(logs) => logs,
-        Err(e) => {
-            tracing::error!(error = ?e, "failed to list logs");
-            Vec::new()
-        }
+    let params = &params;
+    let result = tenant
+        .client
+        .get(|c| async move { c.logs().list(Some(params.clone())).await })
+        .await;
+    if let Err(e) = &result {
+        tracing::error!(error = ?e, "failed to list logs");
+    }
+
+    // A failed "load older" request surfaces through the htmx error toast
+    // rather than being swapped into the table.
+    if before.is_some() {
+        let mut logs = result?;
+        let older_url = older_url(&tenant.path("/logs"), &logs, take, q);
+
+        let seen: Vec<&str> = query.seen.as_deref().unwrap_or("").split(',').collect();
+        logs.retain(|e| !e.log_id.as_deref().is_some_and(|id| seen.contains(&id)));
+        return render(RowsTemplate {
+            tenant,
+            logs,
+            older_url,
+        });
+    }
+
+    let (logs, error) = match result {
+        Ok(logs) => (logs, None),
+        Err(e) => (Vec::new(), Some(AppError::from(e))),
+    };
+    let older_url = older_url(&tenant.path("/logs"), &logs, take, q);
+    let live = query.live.is_some();
+    // Live tail matches events itself, so a search it can't represent
+    // would quietly show the wrong events; say why it is off instead.
+    let (live_url, live_refused) = match live.then(|| LogFilter::parse(q.unwrap_or(""))) {
+        Some(Ok(_)) => (Some(live_url(&tenant, q)), None),
+        Some(Err(reason)) => (None, Some(reason)),
+        None => (None, None),
     };
-    let pages = total_pages(logs.len(), per_page);
 
     if is_htmx_request(&headers) {
         render(TableTemplate {
+            tenant,
             logs,
-            page,
-            total_pages: pages,
+            older_url,
+            live_url,
+            live_refused,
+            error,
         })
     } else {
         render(ListTemplate {
+            operator,
+            tenant,
             logs,
-            page,
-            total_pages: pages,
+            older_url,
+            live_url,
+            live_refused,
             search_query: query.q.unwrap_or_default(),
+            live,
+            error,
         })
     }
 }
+
+#[derive(Deserialize)]
+pub struct StreamQuery {
+    q: Option<String>,
+}
+
+/// Pushes newly arrived events matching the search as rendered `<tr>` rows
+/// for the htmx SSE extension to prepend to the logs table.
+pub async fn stream(
+    _: RequireViewer,
+    tenant: Tenant,
+    Query(query): Query<StreamQuery>,
+) -> Response {
+    let filter = match LogFilter::parse(query.q.as_deref().unwrap_or("")) {
+        Ok(filter) => filter,
+        Err(reason) => return (StatusCode::BAD_REQUEST, reason).into_response(),
+    };
+    let receiver = tenant.log_tail.subscribe();
+
+    // A lagging subscriber skips the batches it missed rather than
+    // holding up everyone else on the same poller.
+    let events = BroadcastStream::new(receiver).filter_map(move |batch| {
+        let batch = batch.ok()?;
+        let logs: Vec<LogEvent> = batch
+            .iter()
+            .filter(|e| filter.matches(e))
+            .cloned()
+            .collect();
+        if logs.is_empty() {
+            return None;
+        }
+        let rows = RowsTemplate {
+            tenant: tenant.clone(),
+            logs,
+            older_url: None,
+        };
+        match rows.render() {
+            Ok(html) => Some(Ok::<_, Infallible>(
+                Event::default().event("log").data(html),
+            )),
+            Err(e) => {
+                tracing::error!(error = ?e, "failed to render live log rows");
+                None
+            }
+        }
+    });
+
+    Sse::new(events)
+        .keep_alive(KeepAlive::default())
+        .into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn older_link_searches_below_the_oldest_event() {
+        let url = older_link(
+            "/t/prod/logs",
+            "2024-05-01T12:00:00.123Z",
+            &["90020240501", "90020240502"],
+            50,
+            Some("type:f"),
+        );
+        assert_eq!(
+            url,
+            "/t/prod/logs?before=2024-05-01T12%3A00%3A00.123Z\
+             &seen=90020240501%2C90020240502&take=50&q=type%3Af"
+        );
+    }
+
+    #[test]
+    fn older_link_without_search_or_seen_events() {
+        let url = older_link("/t/prod/logs", "2024-05-01T12:00:00Z", &[], 100, Some(""));
+        assert_eq!(url, "/t/prod/logs?before=2024-05-01T12%3A00%3A00Z&take=100");
+    }
+
+    #[test]
+    fn search_before_adds_an_inclusive_date_range() {
+        assert_eq!(
+            search_before(None, "2024-05-01T12:00:00Z"),
+            "date:[* TO \"2024-05-01T12:00:00Z\"]"
+        );
+        assert_eq!(
+            search_before(Some("type:f OR type:fp"), "2024-05-01T12:00:00Z"),
+            "(type:f OR type:fp) AND date:[* TO \"2024-05-01T12:00:00Z\"]"
+        );
+    }
+
+    #[test]
+    fn before_must_be_a_timestamp() {
+        assert!(is_timestamp("2024-05-01T12:00:00.123Z"));
+        assert!(is_timestamp("2024-05-01T12:00:00+00:00"));
+        assert!(!is_timestamp(""));
+        assert!(!is_timestamp("2024\"] OR user_id:*"));
+    }
+}
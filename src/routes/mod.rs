@@ -1,5 +1,12 @@
+pub mod apis;
 pub mod applications;
+pub mod audit;
+pub mod auth;
 pub mod connections;
+mod helpers;
 pub mod logs;
+pub mod organizations;
+pub mod roles;
 pub mod root;
+pub mod status;
 pub mod users;
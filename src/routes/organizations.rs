@@ -0,0 +1,1285 @@
+use askama::Template;
+use auth0_mgmt_api::{
+    types::organizations::{
+        AddOrganizationConnectionRequest, CreateOrganizationInvitationRequest,
+        CreateOrganizationRequest, Invitee, Inviter, ListOrganizationInvitationsParams,
+        ListOrganizationMembersParams, ListOrganizationsParams, Organization, OrganizationBranding,
+        OrganizationColors, OrganizationConnection, OrganizationInvitation, OrganizationMember,
+        OrganizationMemberRolesRequest, OrganizationMembersRequest, UpdateOrganizationRequest,
+    },
+    ConnectionId, InvitationId, OrganizationId, UserId,
+};
+use axum::{
+    extract::{Path, Query, State},
+    http::HeaderMap,
+    response::{IntoResponse, Response},
+};
+use axum_extra::extract::Form;
+use htmx_form_errors::FormErrors;
+use serde::Deserialize;
+use validator::{Validate, ValidationError};
+
+use crate::audit::{Action, FieldChange};
+use crate::auth::{Operator, RequireAdmin, RequireSupport, RequireViewer};
+use crate::cache::{fetch_all, PAGE_SIZE};
+use crate::errors::{AppError, AppResult};
+use crate::helpers::{
+    empty_as_none, is_htmx_request, requested_page, requested_per_page, Pagination,
+};
+use crate::routes::helpers::invalid;
+use crate::state::AppState;
+use crate::templates::{render, ErrorBannerTemplate};
+use crate::tenant::Tenant;
+
+/// How many members a page of the members card shows by default.
+const MEMBERS_PER_PAGE: u32 = 50;
+/// What the Auth0 dashboard fills in for an unset branding colour.
+const DEFAULT_PRIMARY_COLOUR: &str = "#0059d6";
+const DEFAULT_BACKGROUND_COLOUR: &str = "#000000";
+
+#[derive(Template)]
+#[template(path = "organizations/list.html")]
+struct ListTemplate {
+    operator: Operator,
+    tenant: Tenant,
+    organizations: Vec<Organization>,
+    pagination: Pagination,
+    error: Option<AppError>,
+    form: OrganizationForm,
+    errors: FormErrors,
+}
+
+#[derive(Template)]
+#[template(path = "organizations/table.html")]
+struct TableTemplate {
+    tenant: Tenant,
+    organizations: Vec<Organization>,
+    pagination: Pagination,
+    error: Option<AppError>,
+}
+
+#[derive(Template)]
+#[template(path = "organizations/create_form.html")]
+struct CreateFormTemplate {
+    tenant: Tenant,
+    form: OrganizationForm,
+    errors: FormErrors,
+}
+
+#[derive(Template)]
+#[template(path = "organizations/detail.html")]
+struct DetailTemplate {
+    operator: Operator,
+    tenant: Tenant,
+    organization: Organization,
+    form: OrganizationForm,
+    connections: Vec<OrganizationConnection>,
+    /// Tenant connections not yet enabled for the organization.
+    other_connections: Vec<(String, String)>,
+    invitations: Vec<OrganizationInvitation>,
+    invitation: InvitationForm,
+    /// Applications and roles for the invitation form.
+    clients: Vec<(String, String)>,
+    roles: Vec<(String, String)>,
+    errors: FormErrors,
+}
+
+#[derive(Template)]
+#[template(path = "organizations/members.html")]
+struct MembersTemplate {
+    operator: Operator,
+    tenant: Tenant,
+    organization_id: String,
+    members: Vec<OrganizationMember>,
+    roles: Vec<(String, String)>,
+    pagination: Pagination,
+    error: Option<AppError>,
+}
+
+impl MembersTemplate {
+    /// Query string that keeps the current page, for the card's own
+    /// actions so they re-render the page the operator was on.
+    fn page_query(&self) -> String {
+        format!(
+            "page={}&per_page={}",
+            self.pagination.page, self.pagination.per_page
+        )
+    }
+}
+
+#[derive(Template)]
+#[template(path = "organizations/user_organizations.html")]
+struct UserOrganizationsTemplate {
+    tenant: Tenant,
+    organizations: Vec<Organization>,
+}
+
+#[derive(Deserialize)]
+pub struct OrganizationPath {
+    id: String,
+}
+
+#[derive(Deserialize)]
+pub struct ConnectionPath {
+    id: String,
+    connection_id: String,
+}
+
+#[derive(Deserialize)]
+pub struct InvitationPath {
+    id: String,
+    invitation_id: String,
+}
+
+#[derive(Deserialize)]
+pub struct MemberPath {
+    id: String,
+    user_id: String,
+}
+
+#[derive(Deserialize)]
+pub struct UserPath {
+    id: String,
+}
+
+#[derive(Deserialize, Default)]
+pub struct ListQuery {
+    #[serde(default, deserialize_with = "empty_as_none")]
+    page: Option<u32>,
+    #[serde(default, deserialize_with = "empty_as_none")]
+    per_page: Option<u32>,
+    #[serde(default, deserialize_with = "empty_as_none")]
+    jump: Option<u32>,
+}
+
+pub async fn list(
+    RequireViewer(operator): RequireViewer,
+    tenant: Tenant,
+    Query(query): Query<ListQuery>,
+    headers: HeaderMap,
+) -> AppResult<Response> {
+    let page = requested_page(query.page, query.jump);
+    let per_page = requested_per_page(query.per_page, 50);
+
+    let params = ListOrganizationsParams {
+        page: Some(page),
+        per_page: Some(per_page),
+        include_totals: Some(true),
+        ..Default::default()
+    };
+
+    let params = &params;
+    let (organizations, start, limit, total, error) = match tenant
+        .client
+        .get(|c| async move { c.organizations().list_paginated(Some(params.clone())).await })
+        .await
+    {
+        Ok(result) => (result.items, result.start, result.limit, result.total, None),
+        Err(e) => {
+            tracing::error!(error = ?e, "failed to list organizations");
+            (Vec::new(), page * per_page, per_page, 0, Some(e.into()))
+        }
+    };
+    let pagination = Pagination::new(
+        &tenant.path("/organizations"),
+        "#organizations-table",
+        start,
+        limit,
+        total,
+    );
+
+    if is_htmx_request(&headers) {
+        render(TableTemplate {
+            tenant,
+            organizations,
+            pagination,
+            error,
+        })
+    } else {
+        render(ListTemplate {
+            operator,
+            tenant,
+            organizations,
+            pagination,
+            error,
+            form: OrganizationForm::default(),
+            errors: FormErrors::new(),
+        })
+    }
+}
+
+/// Name, display name and branding. The name is only used on create;
+/// Auth0 allows renaming but links and invitations use it, so the detail
+/// page shows it read-only.
+#[derive(Clone, Deserialize, Default, Validate)]
+pub struct OrganizationForm {
+    #[serde(default)]
+    #[validate(custom(function = "validate_organization_name"))]
+    name: String,
+    #[serde(default)]
+    #[validate(length(max = 255, message = "Display name must be at most 255 characters"))]
+    display_name: String,
+    #[serde(default)]
+    #[validate(custom(function = "validate_logo_url"))]
+    logo_url: String,
+    #[serde(default)]
+    #[validate(custom(function = "validate_colour"))]
+    primary_colour: String,
+    #[serde(default)]
+    #[validate(custom(function = "validate_colour"))]
+    background_colour: String,
+}
+
+impl OrganizationForm {
+    fn from_organization(organization: &Organization) -> Self {
+        let branding = organization.branding.clone().unwrap_or_default();
+        let colors = branding.colors.unwrap_or_default();
+        Self {
+            name: organization.name.clone(),
+            display_name: organization.display_name.clone().unwrap_or_default(),
+            logo_url: branding.logo_url.unwrap_or_default(),
+            primary_colour: colors.primary.unwrap_or_default(),
+            background_colour: colors.page_background.unwrap_or_default(),
+        }
+    }
+
+    /// Auth0 wants both colours or neither; a missing one gets Auth0's
+    /// default.
+    fn branding(&self) -> OrganizationBranding {
+        let colour = |value: &str, default: &str| {
+            Some(if value.is_empty() { default } else { value }.to_string())
+        };
+        let colors =
+            (!self.primary_colour.is_empty() || !self.background_colour.is_empty()).then(|| {
+                OrganizationColors {
+                    primary: colour(&self.primary_colour, DEFAULT_PRIMARY_COLOUR),
+                    page_background: colour(&self.background_colour, DEFAULT_BACKGROUND_COLOUR),
+                }
+            });
+        OrganizationBranding {
+            logo_url: Some(self.logo_url.clone()).filter(|url| !url.is_empty()),
+            colors,
+        }
+    }
+}
+
+/// Auth0 organization names: lowercase letters, digits, `-` and `_`.
+fn validate_organization_name(name: &str) -> Result<(), ValidationError> {
+    let valid = !name.is_empty()
+        && name.len() <= 50
+        && name
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-' || c == '_');
+    if valid {
+        Ok(())
+    } else {
+        Err(invalid(
+            "organization_name",
+            "Use up to 50 lowercase letters, digits, hyphens and underscores",
+        ))
+    }
+}
+
+fn validate_logo_url(url: &str) -> Result<(), ValidationError> {
+    if url.is_empty() || url::Url::parse(url).is_ok_and(|url| url.scheme() == "https") {
+        Ok(())
+    } else {
+        Err(invalid("logo_url", "Must be an https:// URL"))
+    }
+}
+
+fn validate_colour(colour: &str) -> Result<(), ValidationError> {
+    let valid = colour.is_empty()
+        || (colour.len() == 7
+            && colour.starts_with('#')
+            && colour[1..].chars().all(|c| c.is_ascii_hexdigit()));
+    if valid {
+        Ok(())
+    } else {
+        Err(invalid("colour", "Must look like #rrggbb"))
+    }
+}
+
+pub async fn create(
+    State(state): State<AppState>,
+    RequireAdmin(operator): RequireAdmin,
+    tenant: Tenant,
+    Form(form): Form<OrganizationForm>,
+) -> AppResult<Response> {
+    if let Err(e) = form.validate() {
+        return render(CreateFormTemplate {
+            tenant,
+            form,
+            errors: FormErrors::from(e),
+        });
+    }
+
+    let changes = vec![
+        FieldChange::new("name", None, Some(&form.name)),
+        FieldChange::new("display_name", None, Some(&form.display_name)),
+        FieldChange::new("branding.logo_url", None, Some(&form.logo_url)),
+        FieldChange::new("branding.colors.primary", None, Some(&form.primary_colour)),
+        FieldChange::new(
+            "branding.colors.page_background",
+            None,
+            Some(&form.background_colour),
+        ),
+    ];
+    let request = CreateOrganizationRequest {
+        name: form.name.clone(),
+        display_name: Some(form.display_name.clone()).filter(|s| !s.is_empty()),
+        branding: Some(form.branding()),
+        ..Default::default()
+    };
+
+    let result = tenant
+        .client
+        .send(|c| async move { c.organizations().create(request).await })
+        .await;
+    let target = match &result {
+        Ok(organization) => organization.id.clone(),
+        Err(_) => form.name.clone(),
+    };
+    let error = result.as_ref().err().map(|e| e.to_string());
+    state
+        .audit
+        .record(
+            &operator,
+            &tenant,
+            Action::CreateOrganization,
+            &target,
+            changes,
+            error,
+        )
+        .await?;
+
+    match result {
+        Ok(organization) => {
+            let detail = tenant.path(&format!("/organizations/{}", organization.id));
+            Ok([("hx-redirect", detail)].into_response())
+        }
+        Err(e) => {
+            tracing::error!(error = ?e, "failed to create organization");
+            let mut errors = FormErrors::new();
+            errors.add_base(&format!(
+                "Failed to create organization: {}",
+                AppError::from(e).detail()
+            ));
+            render(CreateFormTemplate {
+                tenant,
+                form,
+                errors,
+            })
+        }
+    }
+}
+
+async fn fetch_organization(
+    tenant: &Tenant,
+    id: &str,
+) -> Result<Organization, auth0_mgmt_api::Error> {
+    tenant
+        .client
+        .get(|c| async move { c.organizations().get(OrganizationId::new(id)).await })
+        .await
+}
+
+/// Every pending invitation of an organization, across all pages.
+async fn fetch_invitations(
+    tenant: &Tenant,
+    id: &str,
+) -> Result<Vec<OrganizationInvitation>, auth0_mgmt_api::Error> {
+    fetch_all(|page| async move {
+        let params = ListOrganizationInvitationsParams {
+            page: Some(page),
+            per_page: Some(PAGE_SIZE),
+            include_totals: Some(true),
+            ..Default::default()
+        };
+        let params = &params;
+        let result = tenant
+            .client
+            .get(|c| async move {
+                c.organizations()
+                    .invitations_paginated(OrganizationId::new(id), Some(params.clone()))
+                    .await
+            })
+            .await?;
+        Ok((result.items, result.total))
+    })
+    .await
+}
+
+/// `(id, name)` pairs from a cached list, sorted by name, for selects.
+fn choices<T>(items: &[T], pair: impl Fn(&T) -> (String, String)) -> Vec<(String, String)> {
+    let mut choices: Vec<(String, String)> = items.iter().map(pair).collect();
+    choices.sort_by(|a, b| a.1.to_lowercase().cmp(&b.1.to_lowercase()));
+    choices
+}
+
+async fn role_choices(tenant: &Tenant) -> Result<Vec<(String, String)>, auth0_mgmt_api::Error> {
+    let roles = tenant.cache.roles(&tenant.client).await?;
+    Ok(choices(roles.as_slice(), |role| {
+        (
+            role.id.clone(),
+            role.name.clone().unwrap_or_else(|| role.id.clone()),
+        )
+    }))
+}
+
+/// Renders the organization page. Connections, invitations and the
+/// choices for the forms are loaded here; members load separately.
+async fn detail_page(
+    operator: Operator,
+    tenant: Tenant,
+    organization: Organization,
+    form: OrganizationForm,
+    invitation: InvitationForm,
+    mut errors: FormErrors,
+) -> AppResult<Response> {
+    let id = organization.id.as_str();
+    let loaded = tokio::try_join!(
+        tenant.client.get(|c| async move {
+            c.organizations()
+                .enabled_connections(OrganizationId::new(id))
+                .await
+        }),
+        fetch_invitations(&tenant, id),
+        tenant.cache.connections(&tenant.client),
+        tenant.cache.clients(&tenant.client),
+        role_choices(&tenant),
+    );
+    let (connections, invitations, all_connections, clients, roles) = match loaded {
+        Ok((connections, invitations, all_connections, clients, roles)) => (
+            connections,
+            invitations,
+            all_connections,
+            choices(clients.as_slice(), |client| {
+                (
+                    client.client_id.clone(),
+                    client
+                        .name
+                        .clone()
+                        .unwrap_or_else(|| client.client_id.clone()),
+                )
+            }),
+            roles,
+        ),
+        Err(e) => {
+            tracing::error!(error = ?e, %id, "failed to load organization details");
+            errors.add_base(&format!(
+                "Could not load connections and invitations: {}",
+                AppError::from(e).detail()
+            ));
+            Default::default()
+        }
+    };
+
+    let other_connections = choices(all_connections.as_slice(), |connection| {
+        (connection.id.clone(), connection.name.clone())
+    })
+    .into_iter()
+    .filter(|(id, _)| !connections.iter().any(|c| &c.connection_id == id))
+    .collect();
+
+    render(DetailTemplate {
+        operator,
+        tenant,
+        organization,
+        form,
+        connections,
+        other_connections,
+        invitations,
+        invitation,
+        clients,
+        roles,
+        errors,
+    })
+}
+
+pub async fn get(
+    RequireViewer(operator): RequireViewer,
+    tenant: Tenant,
+    Path(OrganizationPath { id }): Path<OrganizationPath>,
+) -> AppResult<Response> {
+    let organization = fetch_organization(&tenant, &id).await.map_err(|e| {
+        tracing::warn!(error = ?e, %id, "failed to get organization");
+        AppError::from(e)
+    })?;
+
+    let form = OrganizationForm::from_organization(&organization);
+    detail_page(
+        operator,
+        tenant,
+        organization,
+        form,
+        InvitationForm::default(),
+        FormErrors::new(),
+    )
+    .await
+}
+
+pub async fn update(
+    State(state): State<AppState>,
+    RequireAdmin(operator): RequireAdmin,
+    tenant: Tenant,
+    Path(OrganizationPath { id }): Path<OrganizationPath>,
+    Form(form): Form<OrganizationForm>,
+) -> AppResult<Response> {
+    let organization = fetch_organization(&tenant, &id)
+        .await
+        .map_err(AppError::from)?;
+    let current = OrganizationForm::from_organization(&organization);
+    // The name is not editable here; keep it so validation passes.
+    let form = OrganizationForm {
+        name: current.name.clone(),
+        ..form
+    };
+
+    let mut invalid_fields = form.validate().err().unwrap_or_default();
+    // Auth0 refuses an empty display name and leaves an omitted one as it
+    // is, so once set it can be changed but not removed.
+    if !current.display_name.is_empty() && form.display_name.is_empty() {
+        invalid_fields.add(
+            "display_name",
+            invalid(
+                "display_name",
+                "Auth0 cannot remove a display name; enter a new one",
+            ),
+        );
+    }
+    if !invalid_fields.is_empty() {
+        return detail_page(
+            operator,
+            tenant,
+            organization,
+            form,
+            InvitationForm::default(),
+            FormErrors::from(invalid_fields),
+        )
+        .await;
+    }
+
+    let mut changes = Vec::new();
+    let fields = [
+        ("display_name", &current.display_name, &form.display_name),
+        ("branding.logo_url", &current.logo_url, &form.logo_url),
+        (
+            "branding.colors.primary",
+            &current.primary_colour,
+            &form.primary_colour,
+        ),
+        (
+            "branding.colors.page_background",
+            &current.background_colour,
+            &form.background_colour,
+        ),
+    ];
+    for (field, before, after) in fields {
+        if before != after {
+            changes.push(FieldChange::new(field, Some(before), Some(after)));
+        }
+    }
+
+    if changes.is_empty() {
+        return detail_page(
+            operator,
+            tenant,
+            organization,
+            current,
+            InvitationForm::default(),
+            FormErrors::new(),
+        )
+        .await;
+    }
+
+    let request = UpdateOrganizationRequest {
+        // Cleared fields are left out, as on create. Branding is sent whole,
+        // so a cleared logo is dropped from it.
+        display_name: Some(form.display_name.clone()).filter(|s| !s.is_empty()),
+        branding: Some(form.branding()),
+        ..Default::default()
+    };
+    let organization_id = OrganizationId::new(&id);
+    let result = tenant
+        .client
+        .send(|c| async move { c.organizations().update(organization_id, request).await })
+        .await;
+    let error = result.as_ref().err().map(|e| e.to_string());
+    state
+        .audit
+        .record(
+            &operator,
+            &tenant,
+            Action::UpdateOrganization,
+            &id,
+            changes,
+            error,
+        )
+        .await?;
+
+    match result {
+        Ok(updated) => {
+            let form = OrganizationForm::from_organization(&updated);
+            detail_page(
+                operator,
+                tenant,
+                updated,
+                form,
+                InvitationForm::default(),
+                FormErrors::new(),
+            )
+            .await
+        }
+        Err(e) => {
+            tracing::error!(error = ?e, %id, "failed to update organization");
+            let mut errors = FormErrors::new();
+            errors.add_base(&format!(
+                "Failed to update organization: {}",
+                AppError::from(e).detail()
+            ));
+            detail_page(
+                operator,
+                tenant,
+                organization,
+                form,
+                InvitationForm::default(),
+                errors,
+            )
+            .await
+        }
+    }
+}
+
+/// Re-renders the organization page after a change made from one of its
+/// cards, with the error if Auth0 refused it.
+async fn after_change(
+    operator: Operator,
+    tenant: Tenant,
+    id: &str,
+    error: Option<(auth0_mgmt_api::Error, &str)>,
+) -> AppResult<Response> {
+    let organization = fetch_organization(&tenant, id)
+        .await
+        .map_err(AppError::from)?;
+    let mut errors = FormErrors::new();
+    if let Some((e, what)) = error {
+        tracing::error!(error = ?e, %id, "failed to {}", what);
+        errors.add_base(&format!(
+            "Failed to {}: {}",
+            what,
+            AppError::from(e).detail()
+        ));
+    }
+    let form = OrganizationForm::from_organization(&organization);
+    detail_page(
+        operator,
+        tenant,
+        organization,
+        form,
+        InvitationForm::default(),
+        errors,
+    )
+    .await
+}
+
+#[derive(Deserialize)]
+pub struct AddConnectionForm {
+    connection_id: String,
+    assign_membership_on_login: Option<String>,
+}
+
+pub async fn add_connection(
+    State(state): State<AppState>,
+    RequireAdmin(operator): RequireAdmin,
+    tenant: Tenant,
+    Path(OrganizationPath { id }): Path<OrganizationPath>,
+    Form(form): Form<AddConnectionForm>,
+) -> AppResult<Response> {
+    let auto_membership = form.assign_membership_on_login.is_some();
+    let changes = vec![
+        FieldChange::new("connection_id", None, Some(&form.connection_id)),
+        FieldChange::new(
+            "assign_membership_on_login",
+            None,
+            Some(&auto_membership.to_string()),
+        ),
+    ];
+    let request = AddOrganizationConnectionRequest {
+        connection_id: form.connection_id,
+        assign_membership_on_login: Some(auto_membership),
+    };
+    let organization_id = OrganizationId::new(&id);
+    let result = tenant
+        .client
+        .send(|c| async move {
+            c.organizations()
+                .add_enabled_connection(organization_id, request)
+                .await
+        })
+        .await;
+    let error = result.as_ref().err().map(|e| e.to_string());
+    state
+        .audit
+        .record(
+            &operator,
+            &tenant,
+            Action::AddOrganizationConnection,
+            &id,
+            changes,
+            error,
+        )
+        .await?;
+
+    let error = result.err().map(|e| (e, "enable connection"));
+    after_change(operator, tenant, &id, error).await
+}
+
+pub async fn remove_connection(
+    State(state): State<AppState>,
+    RequireAdmin(operator): RequireAdmin,
+    tenant: Tenant,
+    Path(ConnectionPath { id, connection_id }): Path<ConnectionPath>,
+) -> AppResult<Response> {
+    let changes = vec![FieldChange::new(
+        "connection_id",
+        Some(&connection_id),
+        None,
+    )];
+    let (organization_id, connection) =
+        (OrganizationId::new(&id), ConnectionId::new(&connection_id));
+    let result = tenant
+        .client
+        .send(|c| async move {
+            c.organizations()
+                .remove_enabled_connection(organization_id, connection)
+                .await
+        })
+        .await;
+    let error = result.as_ref().err().map(|e| e.to_string());
+    state
+        .audit
+        .record(
+            &operator,
+            &tenant,
+            Action::RemoveOrganizationConnection,
+            &id,
+            changes,
+            error,
+        )
+        .await?;
+
+    let error = result.err().map(|e| (e, "disable connection"));
+    after_change(operator, tenant, &id, error).await
+}
+
+#[derive(Clone, Deserialize, Default, Validate)]
+pub struct InvitationForm {
+    #[serde(default)]
+    #[validate(email(message = "Must be a valid email address"))]
+    invitee_email: String,
+    #[serde(default)]
+    #[validate(length(min = 1, max = 300, message = "Inviter name is required"))]
+    inviter_name: String,
+    #[serde(default)]
+    #[validate(length(min = 1, message = "Choose the application to log in to"))]
+    client_id: String,
+    #[serde(default)]
+    connection_id: String,
+    #[serde(default)]
+    roles: Vec<String>,
+    send_invitation_email: Option<String>,
+}
+
+pub async fn create_invitation(
+    State(state): State<AppState>,
+    RequireSupport(operator): RequireSupport,
+    tenant: Tenant,
+    Path(OrganizationPath { id }): Path<OrganizationPath>,
+    Form(invitation): Form<InvitationForm>,
+) -> AppResult<Response> {
+    if let Err(e) = invitation.validate() {
+        let organization = fetch_organization(&tenant, &id)
+            .await
+            .map_err(AppError::from)?;
+        let form = OrganizationForm::from_organization(&organization);
+        return detail_page(
+            operator,
+            tenant,
+            organization,
+            form,
+            invitation,
+            FormErrors::from(e),
+        )
+        .await;
+    }
+
+    let changes = vec![
+        FieldChange::new("invitee", None, Some(&invitation.invitee_email)),
+        FieldChange::new("client_id", None, Some(&invitation.client_id)),
+        FieldChange::new("connection_id", None, Some(&invitation.connection_id)),
+        FieldChange::new("roles", None, Some(&invitation.roles.join(", "))),
+    ];
+    let request = CreateOrganizationInvitationRequest {
+        inviter: Inviter {
+            name: invitation.inviter_name.clone(),
+        },
+        invitee: Invitee {
+            email: invitation.invitee_email.clone(),
+        },
+        client_id: invitation.client_id.clone(),
+        connection_id: Some(invitation.connection_id.clone()).filter(|c| !c.is_empty()),
+        roles: Some(invitation.roles.clone()).filter(|r| !r.is_empty()),
+        send_invitation_email: Some(invitation.send_invitation_email.is_some()),
+        ..Default::default()
+    };
+    let organization_id = OrganizationId::new(&id);
+    let result = tenant
+        .client
+        .send(|c| async move {
+            c.organizations()
+                .create_invitation(organization_id, request)
+                .await
+        })
+        .await;
+    let error = result.as_ref().err().map(|e| e.to_string());
+    state
+        .audit
+        .record(
+            &operator,
+            &tenant,
+            Action::CreateOrganizationInvitation,
+            &id,
+            changes,
+            error,
+        )
+        .await?;
+
+    match result {
+        Ok(_) => after_change(operator, tenant, &id, None).await,
+        Err(e) => {
+            tracing::error!(error = ?e, %id, "failed to create invitation");
+            let organization = fetch_organization(&tenant, &id)
+                .await
+                .map_err(AppError::from)?;
+            let mut errors = FormErrors::new();
+            errors.add_base(&format!(
+                "Failed to create invitation: {}",
+                AppError::from(e).detail()
+            ));
+            let form = OrganizationForm::from_organization(&organization);
+            detail_page(operator, tenant, organization, form, invitation, errors).await
+        }
+    }
+}
+
+pub async fn delete_invitation(
+    State(state): State<AppState>,
+    RequireSupport(operator): RequireSupport,
+    tenant: Tenant,
+    Path(InvitationPath { id, invitation_id }): Path<InvitationPath>,
+) -> AppResult<Response> {
+    let changes = vec![FieldChange::new(
+        "invitation_id",
+        Some(&invitation_id),
+        None,
+    )];
+    let (organization_id, invitation) =
+        (OrganizationId::new(&id), InvitationId::new(&invitation_id));
+    let result = tenant
+        .client
+        .send(|c| async move {
+            c.organizations()
+                .delete_invitation(organization_id, invitation)
+                .await
+        })
+        .await;
+    let error = result.as_ref().err().map(|e| e.to_string());
+    state
+        .audit
+        .record(
+            &operator,
+            &tenant,
+            Action::DeleteOrganizationInvitation,
+            &id,
+            changes,
+            error,
+        )
+        .await?;
+
+    let error = result.err().map(|e| (e, "revoke invitation"));
+    after_change(operator, tenant, &id, error).await
+}
+
+/// One page of the members card, with each member's roles in this
+/// organization.
+async fn members_card(
+    operator: Operator,
+    tenant: Tenant,
+    id: String,
+    query: &ListQuery,
+    error: Option<AppError>,
+) -> AppResult<Response> {
+    let page = requested_page(query.page, query.jump);
+    let per_page = requested_per_page(query.per_page, MEMBERS_PER_PAGE);
+    let params = ListOrganizationMembersParams {
+        page: Some(page),
+        per_page: Some(per_page),
+        include_totals: Some(true),
+        fields: Some("user_id,email,name,picture,roles".to_string()),
+        ..Default::default()
+    };
+    let (organization_id, params) = (id.as_str(), &params);
+    let loaded = tokio::try_join!(
+        tenant.client.get(|c| async move {
+            c.organizations()
+                .members_paginated(OrganizationId::new(organization_id), Some(params.clone()))
+                .await
+        }),
+        role_choices(&tenant),
+    );
+    let (members, roles) = match loaded {
+        Ok(loaded) => loaded,
+        Err(e) => {
+            tracing::error!(error = ?e, %id, "failed to list organization members");
+            return render(ErrorBannerTemplate { error: e.into() });
+        }
+    };
+
+    let pagination = Pagination::new(
+        &tenant.path(&format!("/organizations/{}/members", id)),
+        "#organization-members",
+        members.start,
+        members.limit,
+        members.total,
+    );
+
+    render(MembersTemplate {
+        operator,
+        tenant,
+        organization_id: id,
+        members: members.items,
+        roles,
+        pagination,
+        error,
+    })
+}
+
+pub async fn get_members(
+    RequireViewer(operator): RequireViewer,
+    tenant: Tenant,
+    Path(OrganizationPath { id }): Path<OrganizationPath>,
+    Query(query): Query<ListQuery>,
+) -> AppResult<Response> {
+    members_card(operator, tenant, id, &query, None).await
+}
+
+#[derive(Deserialize)]
+pub struct MemberForm {
+    user_id: String,
+}
+
+pub async fn add_member(
+    State(state): State<AppState>,
+    RequireSupport(operator): RequireSupport,
+    tenant: Tenant,
+    Path(OrganizationPath { id }): Path<OrganizationPath>,
+    Query(query): Query<ListQuery>,
+    Form(form): Form<MemberForm>,
+) -> AppResult<Response> {
+    let user_id = form.user_id.trim().to_string();
+    if user_id.is_empty() {
+        return members_card(operator, tenant, id, &query, None).await;
+    }
+
+    let changes = vec![FieldChange::new("members", None, Some(&user_id))];
+    let request = OrganizationMembersRequest {
+        members: vec![user_id],
+    };
+    let organization_id = OrganizationId::new(&id);
+    let result = tenant
+        .client
+        .send(|c| async move {
+            c.organizations()
+                .add_members(organization_id, request)
+                .await
+        })
+        .await;
+    let error = result.as_ref().err().map(|e| e.to_string());
+    state
+        .audit
+        .record(
+            &operator,
+            &tenant,
+            Action::AddOrganizationMembers,
+            &id,
+            changes,
+            error,
+        )
+        .await?;
+
+    let error = result.err().map(|e| {
+        tracing::error!(error = ?e, %id, "failed to add organization member");
+        AppError::from(e)
+    });
+    members_card(operator, tenant, id, &query, error).await
+}
+
+pub async fn remove_member(
+    State(state): State<AppState>,
+    RequireSupport(operator): RequireSupport,
+    tenant: Tenant,
+    Path(MemberPath { id, user_id }): Path<MemberPath>,
+    Query(query): Query<ListQuery>,
+) -> AppResult<Response> {
+    let changes = vec![FieldChange::new("members", Some(&user_id), None)];
+    let request = OrganizationMembersRequest {
+        members: vec![user_id],
+    };
+    let organization_id = OrganizationId::new(&id);
+    let result = tenant
+        .client
+        .send(|c| async move {
+            c.organizations()
+                .remove_members(organization_id, request)
+                .await
+        })
+        .await;
+    let error = result.as_ref().err().map(|e| e.to_string());
+    state
+        .audit
+        .record(
+            &operator,
+            &tenant,
+            Action::RemoveOrganizationMembers,
+            &id,
+            changes,
+            error,
+        )
+        .await?;
+
+    let error = result.err().map(|e| {
+        tracing::error!(error = ?e, %id, "failed to remove organization member");
+        AppError::from(e)
+    });
+    members_card(operator, tenant, id, &query, error).await
+}
+
+#[derive(Deserialize)]
+pub struct MemberRoleForm {
+    role_id: String,
+}
+
+/// Assigns (`assign == true`) or removes one of a member's roles in the
+/// organization. Audited against the organization, with the member as
+/// the field.
+async fn change_member_role(
+    state: AppState,
+    operator: Operator,
+    tenant: Tenant,
+    MemberPath { id, user_id }: MemberPath,
+    query: ListQuery,
+    role_id: String,
+    assign: bool,
+) -> AppResult<Response> {
+    if role_id.is_empty() {
+        return members_card(operator, tenant, id, &query, None).await;
+    }
+
+    let field = format!("members.{}.roles", user_id);
+    let (changes, action) = if assign {
+        (
+            vec![FieldChange::new(&field, None, Some(&role_id))],
+            Action::AssignOrganizationMemberRoles,
+        )
+    } else {
+        (
+            vec![FieldChange::new(&field, Some(&role_id), None)],
+            Action::RemoveOrganizationMemberRoles,
+        )
+    };
+    let request = OrganizationMemberRolesRequest {
+        roles: vec![role_id],
+    };
+    let (organization_id, member) = (OrganizationId::new(&id), UserId::new(&user_id));
+    let result = tenant
+        .client
+        .send(|c| async move {
+            let organizations = c.organizations();
+            if assign {
+                organizations
+                    .add_member_roles(organization_id, member, request)
+                    .await
+            } else {
+                organizations
+                    .remove_member_roles(organization_id, member, request)
+                    .await
+            }
+        })
+        .await;
+    let error = result.as_ref().err().map(|e| e.to_string());
+    state
+        .audit
+        .record(&operator, &tenant, action, &id, changes, error)
+        .await?;
+
+    let error = result.err().map(|e| {
+        tracing::error!(error = ?e, %id, %user_id, "failed to change member roles");
+        AppError::from(e)
+    });
+    members_card(operator, tenant, id, &query, error).await
+}
+
+pub async fn assign_member_role(
+    State(state): State<AppState>,
+    RequireAdmin(operator): RequireAdmin,
+    tenant: Tenant,
+    Path(path): Path<MemberPath>,
+    Query(query): Query<ListQuery>,
+    Form(form): Form<MemberRoleForm>,
+) -> AppResult<Response> {
+    change_member_role(state, operator, tenant, path, query, form.role_id, true).await
+}
+
+pub async fn remove_member_role(
+    State(state): State<AppState>,
+    RequireAdmin(operator): RequireAdmin,
+    tenant: Tenant,
+    Path(path): Path<MemberPath>,
+    Query(query): Query<ListQuery>,
+    Query(form): Query<MemberRoleForm>,
+) -> AppResult<Response> {
+    change_member_role(state, operator, tenant, path, query, form.role_id, false).await
+}
+
+/// The organizations card on the user page.
+pub async fn user_organizations(
+    _: RequireViewer,
+    tenant: Tenant,
+    Path(UserPath { id }): Path<UserPath>,
+) -> AppResult<Response> {
+    let user_id = id.as_str();
+    match tenant
+        .client
+        .get(|c| async move { c.users().organizations(UserId::new(user_id)).await })
+        .await
+    {
+        Ok(organizations) => render(UserOrganizationsTemplate {
+            tenant,
+            organizations,
+        }),
+        Err(e) => {
+            tracing::error!(error = ?e, %id, "failed to get user organizations");
+            render(ErrorBannerTemplate { error: e.into() })
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::{Method, StatusCode};
+
+    use super::*;
+    use crate::auth::Role;
+    use crate::test_support;
+
+    #[test]
+    fn one_colour_fills_in_auth0s_default_for_the_other() {
+        let form = OrganizationForm {
+            primary_colour: "#ff0000".to_string(),
+            ..OrganizationForm::default()
+        };
+        let colors = form.branding().colors.unwrap();
+        assert_eq!(colors.primary.as_deref(), Some("#ff0000"));
+        assert_eq!(
+            colors.page_background.as_deref(),
+            Some(DEFAULT_BACKGROUND_COLOUR)
+        );
+    }
+
+    #[test]
+    fn empty_branding_sends_neither_colours_nor_logo() {
+        let branding = OrganizationForm::default().branding();
+        assert!(branding.colors.is_none());
+        assert!(branding.logo_url.is_none());
+    }
+
+    #[test]
+    fn names_logos_and_colours_are_checked() {
+        assert!(validate_organization_name("acme_eu-2").is_ok());
+        assert!(validate_organization_name("").is_err());
+        assert!(validate_organization_name("Acme").is_err());
+        assert!(validate_organization_name(&"a".repeat(51)).is_err());
+
+        assert!(validate_logo_url("").is_ok());
+        assert!(validate_logo_url("https://example.com/logo.png").is_ok());
+        assert!(validate_logo_url("http://example.com/logo.png").is_err());
+
+        assert!(validate_colour("").is_ok());
+        assert!(validate_colour("#0059D6").is_ok());
+        assert!(validate_colour("0059d6").is_err());
+        assert!(validate_colour("#0059dg").is_err());
+    }
+
+    #[test]
+    fn choices_are_sorted_by_name_ignoring_case() {
+        let items = [("r1", "support"), ("r2", "Admin"), ("r3", "billing")];
+        let sorted = choices(&items, |(id, name)| (id.to_string(), name.to_string()));
+        let names: Vec<&str> = sorted.iter().map(|(_, name)| name.as_str()).collect();
+        assert_eq!(names, ["Admin", "billing", "support"]);
+    }
+
+    #[tokio::test]
+    async fn support_manages_people_but_only_admins_configure_organizations() {
+        let cases = [
+            (Role::Support, Method::POST, "/t/prod/organizations"),
+            (Role::Support, Method::PATCH, "/t/prod/organizations/org_1"),
+            (
+                Role::Support,
+                Method::POST,
+                "/t/prod/organizations/org_1/connections",
+            ),
+            (
+                Role::Support,
+                Method::DELETE,
+                "/t/prod/organizations/org_1/connections/con_1",
+            ),
+            (
+                Role::Support,
+                Method::POST,
+                "/t/prod/organizations/org_1/members/u1/roles",
+            ),
+            (
+                Role::Viewer,
+                Method::POST,
+                "/t/prod/organizations/org_1/members",
+            ),
+            (
+                Role::Viewer,
+                Method::DELETE,
+                "/t/prod/organizations/org_1/members/u1",
+            ),
+            (
+                Role::Viewer,
+                Method::POST,
+                "/t/prod/organizations/org_1/invitations",
+            ),
+            (
+                Role::Viewer,
+                Method::DELETE,
+                "/t/prod/organizations/org_1/invitations/inv_1",
+            ),
+        ];
+        for (role, method, uri) in cases {
+            let response = test_support::send_as(Some(role), method.clone(), uri, "").await;
+            assert_eq!(
+                response.status(),
+                StatusCode::FORBIDDEN,
+                "{:?} {} {}",
+                role,
+                method,
+                uri
+            );
+        }
+    }
+}
@@ -0,0 +1,597 @@
+use std::collections::BTreeSet;
+
+use askama::Template;
+use auth0_mgmt_api::{
+    types::roles::{
+        CreateRoleRequest, ListRolesParams, Permission, PermissionIdentifier, Role,
+        RolePermissionsRequest, UpdateRoleRequest,
+    },
+    RoleId,
+};
+use axum::{
+    extract::{Path, Query, State},
+    http::HeaderMap,
+    response::{IntoResponse, Redirect, Response},
+};
+use axum_extra::extract::Form;
+use htmx_form_errors::FormErrors;
+use serde::Deserialize;
+use validator::Validate;
+
+use crate::audit::{Action, FieldChange};
+use crate::auth::{Operator, RequireAdmin, RequireViewer};
+use crate::errors::{AppError, AppResult};
+use crate::helpers::{
+    empty_as_none, is_htmx_request, requested_page, requested_per_page, Pagination,
+};
+use crate::routes::helpers::{fetch_apis, fetch_role_permissions};
+use crate::state::AppState;
+use crate::templates::render;
+use crate::tenant::Tenant;
+
+#[derive(Template)]
+#[template(path = "roles/list.html")]
+struct ListTemplate {
+    operator: Operator,
+    tenant: Tenant,
+    roles: Vec<Role>,
+    pagination: Pagination,
+    error: Option<AppError>,
+    search_query: String,
+    form: RoleForm,
+    errors: FormErrors,
+}
+
+#[derive(Template)]
+#[template(path = "roles/table.html")]
+struct TableTemplate {
+    tenant: Tenant,
+    roles: Vec<Role>,
+    pagination: Pagination,
+    error: Option<AppError>,
+}
+
+#[derive(Template)]
+#[template(path = "roles/create_form.html")]
+struct CreateFormTemplate {
+    tenant: Tenant,
+    form: RoleForm,
+    errors: FormErrors,
+}
+
+#[derive(Template)]
+#[template(path = "roles/detail.html")]
+struct DetailTemplate {
+    operator: Operator,
+    tenant: Tenant,
+    role: Role,
+    form: RoleForm,
+    permissions: Vec<Permission>,
+    /// Scopes of every API that the role does not have yet, grouped by API.
+    available: Vec<ApiScopes>,
+    errors: FormErrors,
+}
+
+struct ApiScopes {
+    identifier: String,
+    name: String,
+    scopes: Vec<String>,
+}
+
+#[derive(Deserialize)]
+pub struct RolePath {
+    id: String,
+}
+
+#[derive(Deserialize, Default)]
+pub struct ListQuery {
+    #[serde(default, deserialize_with = "empty_as_none")]
+    page: Option<u32>,
+    #[serde(default, deserialize_with = "empty_as_none")]
+    per_page: Option<u32>,
+    #[serde(default, deserialize_with = "empty_as_none")]
+    jump: Option<u32>,
+    q: Option<String>,
+}
+
+pub async fn list(
+    RequireViewer(operator): RequireViewer,
+    tenant: Tenant,
+    Query(query): Query<ListQuery>,
+    headers: HeaderMap,
+) -> AppResult<Response> {
+    let page = requested_page(query.page, query.jump);
+    let per_page = requested_per_page(query.per_page, 50);
+
+    let params = ListRolesParams {
+        page: Some(page),
+        per_page: Some(per_page),
+        include_totals: Some(true),
+        name_filter: query.q.clone().filter(|q| !q.is_empty()),
+        ..Default::default()
+    };
+
+    let params = &params;
+    let (roles, start, limit, total, error) = match tenant
+        .client
+        .get(|c| async move { c.roles().list_paginated(Some(params.clone())).await })
+        .await
+    {
+        Ok(result) => (result.items, result.start, result.limit, result.total, None),
+        Err(e) => {
+            tracing::error!(error = ?e, "failed to list roles");
+            (Vec::new(), page * per_page, per_page, 0, Some(e.into()))
+        }
+    };
+    let pagination = Pagination::new(&tenant.path("/roles"), "#roles-table", start, limit, total)
+        .with_param("q", query.q.as_deref());
+
+    if is_htmx_request(&headers) {
+        render(TableTemplate {
+            tenant,
+            roles,
+            pagination,
+            error,
+        })
+    } else {
+        render(ListTemplate {
+            operator,
+            tenant,
+            roles,
+            pagination,
+            error,
+            search_query: query.q.unwrap_or_default(),
+            form: RoleForm::default(),
+            errors: FormErrors::new(),
+        })
+    }
+}
+
+#[derive(Clone, Deserialize, Default, Validate)]
+pub struct RoleForm {
+    #[validate(length(min = 1, max = 50, message = "Name must be 1 to 50 characters"))]
+    name: String,
+    #[serde(default)]
+    #[validate(length(max = 140, message = "Description must be at most 140 characters"))]
+    description: String,
+}
+
+impl RoleForm {
+    fn from_role(role: &Role) -> Self {
+        Self {
+            name: role.name.clone().unwrap_or_default(),
+            description: role.description.clone().unwrap_or_default(),
+        }
+    }
+}
+
+pub async fn create(
+    State(state): State<AppState>,
+    RequireAdmin(operator): RequireAdmin,
+    tenant: Tenant,
+    Form(form): Form<RoleForm>,
+) -> AppResult<Response> {
+    if let Err(e) = form.validate() {
+        return render(CreateFormTemplate {
+            tenant,
+            form,
+            errors: FormErrors::from(e),
+        });
+    }
+
+    let changes = vec![
+        FieldChange::new("name", None, Some(&form.name)),
+        FieldChange::new("description", None, Some(&form.description)),
+    ];
+    let request = CreateRoleRequest {
+        name: form.name.trim().to_string(),
+        description: Some(form.description.trim().to_string()).filter(|d| !d.is_empty()),
+    };
+
+    let result = tenant
+        .client
+        .send(|c| async move { c.roles().create(request).await })
+        .await;
+    let target = match &result {
+        Ok(role) => role.id.clone(),
+        Err(_) => form.name.clone(),
+    };
+    let error = result.as_ref().err().map(|e| e.to_string());
+    state
+        .audit
+        .record(
+            &operator,
+            &tenant,
+            Action::CreateRole,
+            &target,
+            changes,
+            error,
+        )
+        .await?;
+
+    match result {
+        Ok(role) => {
+            tenant.cache.roles.invalidate();
+            let detail = tenant.path(&format!("/roles/{}", role.id));
+            Ok([("hx-redirect", detail)].into_response())
+        }
+        Err(e) => {
+            tracing::error!(error = ?e, "failed to create role");
+            let mut errors = FormErrors::new();
+            errors.add_base(&format!(
+                "Failed to create role: {}",
+                AppError::from(e).detail()
+            ));
+            render(CreateFormTemplate {
+                tenant,
+                form,
+                errors,
+            })
+        }
+    }
+}
+
+async fn fetch_role(tenant: &Tenant, id: &str) -> Result<Role, auth0_mgmt_api::Error> {
+    tenant
+        .client
+        .get(|c| async move { c.roles().get(RoleId::new(id)).await })
+        .await
+}
+
+/// Renders the role page with its permissions and the scopes that can
+/// still be added. Lookup failures are shown on the page rather than
+/// failing it.
+async fn detail_page(
+    operator: Operator,
+    tenant: Tenant,
+    role: Role,
+    form: RoleForm,
+    mut errors: FormErrors,
+) -> AppResult<Response> {
+    let permissions = fetch_role_permissions(&tenant, &role.id)
+        .await
+        .unwrap_or_else(|e| {
+            tracing::error!(error = ?e, role = %role.id, "failed to list role permissions");
+            errors.add_base(&format!(
+                "Could not load permissions: {}",
+                AppError::from(e).detail()
+            ));
+            Vec::new()
+        });
+
+    let granted: BTreeSet<(&str, &str)> = permissions
+        .iter()
+        .map(|p| {
+            (
+                p.resource_server_identifier.as_str(),
+                p.permission_name.as_str(),
+            )
+        })
+        .collect();
+    let available = match fetch_apis(&tenant).await {
+        Ok(apis) => apis
+            .into_iter()
+            .filter_map(|api| {
+                let scopes: Vec<String> = api
+                    .scopes
+                    .unwrap_or_default()
+                    .into_iter()
+                    .map(|scope| scope.value)
+                    .filter(|scope| !granted.contains(&(api.identifier.as_str(), scope.as_str())))
+                    .collect();
+                (!scopes.is_empty()).then(|| ApiScopes {
+                    name: api.name.unwrap_or_else(|| api.identifier.clone()),
+                    identifier: api.identifier,
+                    scopes,
+                })
+            })
+            .collect(),
+        Err(e) => {
+            tracing::error!(error = ?e, "failed to list APIs");
+            errors.add_base(&format!(
+                "Could not load APIs: {}",
+                AppError::from(e).detail()
+            ));
+            Vec::new()
+        }
+    };
+
+    render(DetailTemplate {
+        operator,
+        tenant,
+        role,
+        form,
+        permissions,
+        available,
+        errors,
+    })
+}
+
+pub async fn get(
+    RequireViewer(operator): RequireViewer,
+    tenant: Tenant,
+    Path(RolePath { id }): Path<RolePath>,
+) -> AppResult<Response> {
+    let role = fetch_role(&tenant, &id).await.map_err(|e| {
+        tracing::warn!(error = ?e, %id, "failed to get role");
+        AppError::from(e)
+    })?;
+
+    let form = RoleForm::from_role(&role);
+    detail_page(operator, tenant, role, form, FormErrors::new()).await
+}
+
+pub async fn update(
+    State(state): State<AppState>,
+    RequireAdmin(operator): RequireAdmin,
+    tenant: Tenant,
+    Path(RolePath { id }): Path<RolePath>,
+    Form(form): Form<RoleForm>,
+) -> AppResult<Response> {
+    let role = fetch_role(&tenant, &id).await.map_err(AppError::from)?;
+
+    if let Err(e) = form.validate() {
+        return detail_page(operator, tenant, role, form, FormErrors::from(e)).await;
+    }
+
+    let current = RoleForm::from_role(&role);
+    let name = form.name.trim().to_string();
+    let description = form.description.trim().to_string();
+    let mut changes = Vec::new();
+    let mut request = UpdateRoleRequest::default();
+    if name != current.name {
+        changes.push(FieldChange::new("name", Some(&current.name), Some(&name)));
+        request.name = Some(name);
+    }
+    if description != current.description {
+        changes.push(FieldChange::new(
+            "description",
+            Some(&current.description),
+            Some(&description),
+        ));
+        request.description = Some(description);
+    }
+
+    if changes.is_empty() {
+        return detail_page(operator, tenant, role, current, FormErrors::new()).await;
+    }
+
+    let role_id = RoleId::new(&id);
+    let result = tenant
+        .client
+        .send(|c| async move { c.roles().update(role_id, request).await })
+        .await;
+    let error = result.as_ref().err().map(|e| e.to_string());
+    state
+        .audit
+        .record(&operator, &tenant, Action::UpdateRole, &id, changes, error)
+        .await?;
+
+    match result {
+        Ok(updated) => {
+            tenant.cache.roles.invalidate();
+            let form = RoleForm::from_role(&updated);
+            detail_page(operator, tenant, updated, form, FormErrors::new()).await
+        }
+        Err(e) => {
+            tracing::error!(error = ?e, %id, "failed to update role");
+            let mut errors = FormErrors::new();
+            errors.add_base(&format!(
+                "Failed to update role: {}",
+                AppError::from(e).detail()
+            ));
+            detail_page(operator, tenant, role, form, errors).await
+        }
+    }
+}
+
+pub async fn delete(
+    State(state): State<AppState>,
+    RequireAdmin(operator): RequireAdmin,
+    tenant: Tenant,
+    Path(RolePath { id }): Path<RolePath>,
+) -> AppResult<Response> {
+    let name = match fetch_role(&tenant, &id).await {
+        Ok(role) => role.name,
+        Err(_) => None,
+    };
+    let changes = vec![FieldChange::new("name", name.as_deref(), None)];
+
+    let role_id = RoleId::new(&id);
+    let result = tenant
+        .client
+        .send(|c| async move { c.roles().delete(role_id).await })
+        .await;
+    let error = result.as_ref().err().map(|e| e.to_string());
+    state
+        .audit
+        .record(&operator, &tenant, Action::DeleteRole, &id, changes, error)
+        .await?;
+
+    result.map_err(|e| {
+        tracing::error!(error = ?e, %id, "failed to delete role");
+        AppError::from(e)
+    })?;
+
+    tenant.cache.roles.invalidate();
+    Ok(Redirect::to(&tenant.path("/roles")).into_response())
+}
+
+/// Permissions picked in the multi-select, each `"{scope} {api identifier}"`.
+/// The scope comes first because an OAuth scope can never contain a space
+/// (RFC 6749, section 3.3), so the first space always ends it whatever the
+/// identifier holds.
+#[derive(Deserialize)]
+pub struct AddPermissionsForm {
+    #[serde(default)]
+    permissions: Vec<String>,
+}
+
+/// One permission to remove, sent as query parameters by `hx-delete`.
+#[derive(Deserialize)]
+pub struct RemovePermissionQuery {
+    resource_server_identifier: String,
+    permission_name: String,
+}
+
+/// Reads one picker value back, or `None` if it isn't a scope followed by
+/// an API identifier.
+fn parse_permission(value: &str) -> Option<PermissionIdentifier> {
+    let (scope, api) = value.split_once(' ')?;
+    if scope.is_empty() || api.trim().is_empty() {
+        return None;
+    }
+    Some(PermissionIdentifier {
+        resource_server_identifier: api.to_string(),
+        permission_name: scope.to_string(),
+    })
+}
+
+fn permission_label(permission: &PermissionIdentifier) -> String {
+    format!(
+        "{} {}",
+        permission.resource_server_identifier, permission.permission_name
+    )
+}
+
+pub async fn add_permissions(
+    State(state): State<AppState>,
+    RequireAdmin(operator): RequireAdmin,
+    tenant: Tenant,
+    Path(RolePath { id }): Path<RolePath>,
+    Form(form): Form<AddPermissionsForm>,
+) -> AppResult<Response> {
+    let Some(permissions) = form
+        .permissions
+        .iter()
+        .map(|value| parse_permission(value))
+        .collect::<Option<Vec<_>>>()
+    else {
+        tracing::warn!(%id, values = ?form.permissions, "malformed permission selection");
+        let role = fetch_role(&tenant, &id).await.map_err(AppError::from)?;
+        let mut errors = FormErrors::new();
+        errors.add_base("The permission selection was malformed; nothing was added.");
+        let form = RoleForm::from_role(&role);
+        return detail_page(operator, tenant, role, form, errors).await;
+    };
+    if permissions.is_empty() {
+        return permission_result(operator, tenant, &id, None, "add permissions").await;
+    }
+
+    let changes = permissions
+        .iter()
+        .map(|p| FieldChange::new("permissions", None, Some(&permission_label(p))))
+        .collect();
+    let request = RolePermissionsRequest { permissions };
+    let role_id = RoleId::new(&id);
+    let result = tenant
+        .client
+        .send(|c| async move { c.roles().add_permissions(role_id, request).await })
+        .await;
+    let error = result.as_ref().err().map(|e| e.to_string());
+    state
+        .audit
+        .record(
+            &operator,
+            &tenant,
+            Action::AddRolePermissions,
+            &id,
+            changes,
+            error,
+        )
+        .await?;
+    if result.is_ok() {
+        tenant.cache.role_permissions.invalidate();
+    }
+
+    permission_result(operator, tenant, &id, result.err(), "add permissions").await
+}
+
+pub async fn remove_permission(
+    State(state): State<AppState>,
+    RequireAdmin(operator): RequireAdmin,
+    tenant: Tenant,
+    Path(RolePath { id }): Path<RolePath>,
+    Query(query): Query<RemovePermissionQuery>,
+) -> AppResult<Response> {
+    let permission = PermissionIdentifier {
+        resource_server_identifier: query.resource_server_identifier,
+        permission_name: query.permission_name,
+    };
+    let changes = vec![FieldChange::new(
+        "permissions",
+        Some(&permission_label(&permission)),
+        None,
+    )];
+    let request = RolePermissionsRequest {
+        permissions: vec![permission],
+    };
+    let role_id = RoleId::new(&id);
+    let result = tenant
+        .client
+        .send(|c| async move { c.roles().remove_permissions(role_id, request).await })
+        .await;
+    let error = result.as_ref().err().map(|e| e.to_string());
+    state
+        .audit
+        .record(
+            &operator,
+            &tenant,
+            Action::RemoveRolePermissions,
+            &id,
+            changes,
+            error,
+        )
+        .await?;
+    if result.is_ok() {
+        tenant.cache.role_permissions.invalidate();
+    }
+
+    permission_result(operator, tenant, &id, result.err(), "remove permission").await
+}
+
+/// Re-renders the role page after a permission change, with the error if
+/// Auth0 refused it.
+async fn permission_result(
+    operator: Operator,
+    tenant: Tenant,
+    id: &str,
+    error: Option<auth0_mgmt_api::Error>,
+    what: &str,
+) -> AppResult<Response> {
+    let role = fetch_role(&tenant, id).await.map_err(AppError::from)?;
+    let mut errors = FormErrors::new();
+    if let Some(e) = error {
+        tracing::error!(error = ?e, %id, "failed to {}", what);
+        errors.add_base(&format!(
+            "Failed to {}: {}",
+            what,
+            AppError::from(e).detail()
+        ));
+    }
+    let form = RoleForm::from_role(&role);
+    detail_page(operator, tenant, role, form, errors).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_permission_splits_at_the_scope() {
+        let permission = parse_permission("read:users https://api.example.com/v2 beta").unwrap();
+        assert_eq!(permission.permission_name, "read:users");
+        assert_eq!(
+            permission.resource_server_identifier,
+            "https://api.example.com/v2 beta"
+        );
+    }
+
+    #[test]
+    fn parse_permission_rejects_malformed_values() {
+        assert!(parse_permission("read:users").is_none());
+        assert!(parse_permission(" https://api.example.com").is_none());
+        assert!(parse_permission("read:users ").is_none());
+        assert!(parse_permission("").is_none());
+    }
+}
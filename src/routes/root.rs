@@ -1,13 +1,27 @@
 use askama::Template;
-use axum::response::Response;
+use axum::{
+    extract::State,
+    response::{IntoResponse, Redirect, Response},
+};
 
+use crate::auth::{Operator, RequireViewer};
 use crate::errors::AppResult;
+use crate::state::AppState;
 use crate::templates::render;
+use crate::tenant::Tenant;
 
 #[derive(Template)]
 #[template(path = "index.html")]
-struct IndexTemplate;
+struct IndexTemplate {
+    operator: Operator,
+    tenant: Tenant,
+}
+
+pub async fn index(RequireViewer(operator): RequireViewer, tenant: Tenant) -> AppResult<Response> {
+    render(IndexTemplate { operator, tenant })
+}
 
-pub async fn index() -> AppResult<Response> {
-    render(IndexTemplate)
+/// `/` sends operators to the first configured tenant.
+pub async fn default_tenant(State(state): State<AppState>, _: RequireViewer) -> Response {
+    Redirect::to(&state.tenants.default_tenant().base_path).into_response()
 }
@@ -0,0 +1,84 @@
+use askama::Template;
+use axum::{
+    extract::State,
+    http::HeaderMap,
+    response::{IntoResponse, Redirect, Response},
+};
+
+use crate::audit::WriteFailures;
+use crate::auth::{Operator, RequireSupport, RequireViewer};
+use crate::cache::CacheStatus;
+use crate::client::Budget;
+use crate::errors::AppResult;
+use crate::helpers::is_htmx_request;
+use crate::state::AppState;
+use crate::templates::render;
+use crate::tenant::Tenant;
+
+#[derive(Template)]
+#[template(path = "status/index.html")]
+struct IndexTemplate {
+    operator: Operator,
+    tenant: Tenant,
+    budget: Budget,
+    probe_error: Option<String>,
+    caches: Vec<CacheStatus>,
+    audit: WriteFailures,
+}
+
+#[derive(Template)]
+#[template(path = "status/budget.html")]
+struct BudgetTemplate {
+    operator: Operator,
+    tenant: Tenant,
+    budget: Budget,
+    probe_error: Option<String>,
+    caches: Vec<CacheStatus>,
+    audit: WriteFailures,
+}
+
+pub async fn index(
+    State(state): State<AppState>,
+    RequireViewer(operator): RequireViewer,
+    tenant: Tenant,
+    headers: HeaderMap,
+) -> AppResult<Response> {
+    let probe_error = tenant.client.probe_rate_limit().await.err();
+    let budget = tenant.client.budget();
+    let caches = tenant.cache.status();
+    let audit = state.audit.write_failures();
+
+    if is_htmx_request(&headers) {
+        render(BudgetTemplate {
+            operator,
+            tenant,
+            budget,
+            probe_error,
+            caches,
+            audit,
+        })
+    } else {
+        render(IndexTemplate {
+            operator,
+            tenant,
+            budget,
+            probe_error,
+            caches,
+            audit,
+        })
+    }
+}
+
+/// Drops every cached list so the next page load reads fresh data from
+/// Auth0. Support and up only, since everyone's next page then waits on
+/// upstream. htmx callers get the current page reloaded.
+pub async fn refresh_cache(_: RequireSupport, tenant: Tenant, headers: HeaderMap) -> Response {
+    tenant.cache.invalidate_all();
+    tracing::info!(tenant = %tenant.name, "cached lists refreshed");
+
+    if is_htmx_request(&headers) {
+        [("hx-refresh", "true")].into_response()
+    } else {
+        Redirect::to(&tenant.path("/status")).into_response()
+    }
+}
@@ -1,30 +1,55 @@
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+
 use askama::Template;
 use auth0_mgmt_api::{
-    types::users::{CreateUserRequest, ListUsersParams, UpdateUserRequest},
-    UserId,
+    types::{
+        device_credentials::{DeviceCredential, ListDeviceCredentialsParams},
+        jobs::VerificationEmailJobRequest,
+        refresh_tokens::RefreshToken,
+        roles::{Permission, Role as Auth0Role},
+        sessions::Session,
+        tickets::{EmailVerificationTicketRequest, PasswordChangeTicketRequest},
+        users::{
+            AuthenticationMethod, CreateUserRequest, ListUserPermissionsParams,
+            ListUserRefreshTokensParams, ListUserRolesParams, ListUserSessionsParams,
+            ListUsersParams, UpdateUserRequest, UserRolesRequest,
+        },
+    },
+    AuthenticationMethodId, DeviceCredentialId, RefreshTokenId, SessionId, UserId,
 };
 use axum::{
     extract::{Path, Query, State},
-    http::HeaderMap,
-    response::{Html, IntoResponse, Redirect, Response},
+    http::{header, HeaderMap},
+    response::{IntoResponse, Redirect, Response},
     Form,
 };
 use htmx_form_errors::FormErrors;
 use serde::Deserialize;
-use validator::Validate;
+use serde_json::{Map, Value};
+use sha2::{Digest, Sha256};
+use validator::{Validate, ValidationError, ValidationErrors};
 
+use crate::audit::{Action, FieldChange};
+use crate::auth::{Operator, RequireAdmin, RequireSupport, RequireViewer, Role};
+use crate::cache::{fetch_all, fetch_all_from, PAGE_SIZE};
 use crate::errors::{AppError, AppResult};
-use crate::helpers::{htmx_target_is, is_htmx_request, total_pages};
+use crate::helpers::{
+    empty_as_none, htmx_target_is, is_htmx_request, requested_page, requested_per_page, Pagination,
+};
 use crate::routes::connections::get_connection_names;
 use crate::state::AppState;
-use crate::templates::render;
+use crate::templates::{render, ErrorBannerTemplate};
+use crate::tenant::Tenant;
 
 #[derive(Template)]
 #[template(path = "users/list.html")]
 struct ListTemplate {
+    operator: Operator,
+    tenant: Tenant,
     users: Vec<auth0_mgmt_api::types::users::User>,
-    page: u32,
-    total_pages: u32,
+    pagination: Pagination,
+    error: Option<AppError>,
     search_query: String,
     connection: String,
     connections: Vec<String>,
@@ -35,14 +60,18 @@ struct ListTemplate {
 #[derive(Template)]
 #[template(path = "users/table.html")]
 struct TableTemplate {
+    operator: Operator,
+    tenant: Tenant,
     users: Vec<auth0_mgmt_api::types::users::User>,
-    page: u32,
-    total_pages: u32,
+    pagination: Pagination,
+    error: Option<AppError>,
 }
 
 #[derive(Template)]
 #[template(path = "users/detail.html")]
 struct DetailTemplate {
+    operator: Operator,
+    tenant: Tenant,
     user: auth0_mgmt_api::types::users::User,
     errors: FormErrors,
 }
@@ -50,6 +79,7 @@ struct DetailTemplate {
 #[derive(Template)]
 #[template(path = "users/create_form.html")]
 struct CreateFormTemplate {
+    tenant: Tenant,
     form: CreateForm,
     connections: Vec<String>,
     errors: FormErrors,
@@ -61,20 +91,105 @@ struct LogsTemplate {
     logs: Vec<auth0_mgmt_api::types::logs::LogEvent>,
 }
 
+#[derive(Template)]
+#[template(path = "users/roles.html")]
+struct RolesTemplate {
+    operator: Operator,
+    tenant: Tenant,
+    user_id: String,
+    roles: Vec<Auth0Role>,
+    /// Roles the user does not have yet, for the picker.
+    available: Vec<Auth0Role>,
+    permissions: Vec<Permission>,
+    error: Option<AppError>,
+}
+
+#[derive(Template)]
+#[template(path = "users/metadata.html")]
+struct MetadataTemplate {
+    operator: Operator,
+    tenant: Tenant,
+    user_id: String,
+    form: MetadataForm,
+    /// Set when previewing: what saving the form would change.
+    diff: Option<Vec<FieldChange>>,
+    notice: Option<String>,
+    errors: FormErrors,
+}
+
+#[derive(Template)]
+#[template(path = "users/mfa.html")]
+struct MfaTemplate {
+    operator: Operator,
+    tenant: Tenant,
+    user_id: String,
+    methods: Vec<AuthenticationMethod>,
+    /// A freshly generated recovery code, shown once.
+    recovery_code: Option<String>,
+    error: Option<AppError>,
+}
+
+#[derive(Template)]
+#[template(path = "users/sessions.html")]
+struct SessionsTemplate {
+    operator: Operator,
+    tenant: Tenant,
+    user_id: String,
+    sessions: Vec<Session>,
+    refresh_tokens: Vec<RefreshToken>,
+    device_credentials: Vec<DeviceCredential>,
+    error: Option<AppError>,
+}
+
+#[derive(Template)]
+#[template(path = "users/tickets.html")]
+struct TicketsTemplate {
+    operator: Operator,
+    tenant: Tenant,
+    user_id: String,
+    email_verified: bool,
+    form: TicketForm,
+    /// A ticket just created, as (what it is for, URL). Shown once.
+    ticket: Option<(String, String)>,
+    notice: Option<String>,
+    errors: FormErrors,
+}
+
+/// `{id}` under `/t/{tenant}/users`; the tenant segment is picked up by
+/// the `Tenant` extractor.
+#[derive(Deserialize)]
+pub struct UserPath {
+    id: String,
+}
+
 #[derive(Deserialize, Default)]
 pub struct ListQuery {
+    #[serde(default, deserialize_with = "empty_as_none")]
     page: Option<u32>,
+    #[serde(default, deserialize_with = "empty_as_none")]
+    per_page: Option<u32>,
+    #[serde(default, deserialize_with = "empty_as_none")]
+    jump: Option<u32>,
     q: Option<String>,
     connection: Option<String>,
 }
 
-pub async fn list(
-    State(state): State<AppState>,
-    Query(query): Query<ListQuery>,
-    headers: HeaderMap,
-) -> AppResult<Response> {
-    let page = query.page.unwrap_or(0);
-    let per_page = 20;
+/// Auth0 returns at most the first 1000 users of a search, however it is
+/// paged.
+const SEARCH_WINDOW: u32 = 1000;
+
+/// Fetches one page of users. A failed request yields an empty page and
+/// the error to show above it.
+async fn users_page(
+    tenant: &Tenant,
+    query: &ListQuery,
+) -> (
+    Vec<auth0_mgmt_api::types::users::User>,
+    Pagination,
+    Option<AppError>,
+) {
+    let per_page = requested_per_page(query.per_page, 20);
+    let page = requested_page(query.page, query.jump).min(SEARCH_WINDOW / per_page - 1);
 
     let params = ListUsersParams {
         page: Some(page),
@@ -87,27 +202,54 @@ pub async fn list(
         ..Default::default()
     };
 
-    let users = match state.client.users().list(Some(params)).await {
-        Ok(users) => users,
+    let params = &params;
+    let (users, start, limit, total, error) = match tenant
+        .client
+        .get(|c| async move { c.users().list_paginated(Some(params.clone())).await })
+        .await
+    {
+        Ok(result) => (result.items, result.start, result.limit, result.total, None),
         Err(e) => {
             tracing::error!(error = ?e, "failed to list users");
-            Vec::new()
+            (Vec::new(), page * per_page, per_page, 0, Some(e.into()))
         }
     };
-    let pages = total_pages(users.len(), per_page);
-    let connections = get_connection_names(&state.client).await;
+
+    let pagination = Pagination::new(&tenant.path("/users"), "#users-table", start, limit, total)
+        .within(SEARCH_WINDOW)
+        .with_param("q", query.q.as_deref())
+        .with_param("connection", query.connection.as_deref());
+
+    (users, pagination, error)
+}
+
+pub async fn list(
+    RequireViewer(operator): RequireViewer,
+    tenant: Tenant,
+    Query(query): Query<ListQuery>,
+    headers: HeaderMap,
+) -> AppResult<Response> {
+    let (users, pagination, error) = users_page(&tenant, &query).await;
 
     if is_htmx_request(&headers) {
         render(TableTemplate {
+            operator,
+            tenant,
             users,
-            page,
-            total_pages: pages,
+            pagination,
+            error,
         })
     } else {
+        let (connections, error) = match get_connection_names(&tenant).await {
+            Ok(connections) => (connections, error),
+            Err(e) => (Vec::new(), error.or(Some(e))),
+        };
         render(ListTemplate {
+            operator,
+            tenant,
             users,
-            page,
-            total_pages: pages,
+            pagination,
+            error,
             search_query: query.q.unwrap_or_default(),
             connection: query.connection.unwrap_or_default(),
             connections,
@@ -117,6 +259,15 @@ pub async fn list(
     }
 }
 
+/// Connection names for the create form's select. If they cannot be
+/// loaded the form says so rather than offering an empty list.
+async fn connection_choices(tenant: &Tenant, errors: &mut FormErrors) -> Vec<String> {
+    get_connection_names(tenant).await.unwrap_or_else(|e| {
+        errors.add_base(&format!("Could not load connections: {}", e.detail()));
+        Vec::new()
+    })
+}
+
 #[derive(Clone, Deserialize, Default, Validate)]
 pub struct CreateForm {
     #[validate(email(message = "Must be a valid email address"))]
@@ -133,16 +284,19 @@ pub struct CreateForm {
 
 pub async fn create(
     State(state): State<AppState>,
+    RequireSupport(operator): RequireSupport,
+    tenant: Tenant,
     Form(form): Form<CreateForm>,
 ) -> AppResult<Response> {
-    let errors = match form.validate() {
+    let mut errors = match form.validate() {
         Ok(_) => FormErrors::new(),
         Err(e) => FormErrors::from(e),
     };
 
     if !errors.is_empty() {
-        let connections = get_connection_names(&state.client).await;
+        let connections = connection_choices(&tenant, &mut errors).await;
         return render(CreateFormTemplate {
+            tenant,
             form,
             connections,
             errors,
@@ -150,6 +304,14 @@ pub async fn create(
     }
 
     let form_snapshot = form.clone();
+    let changes = vec![
+        FieldChange::new("email", None, Some(&form.email)),
+        FieldChange::new("connection", None, Some(&form.connection)),
+        FieldChange::new("username", None, form.username.as_deref()),
+        FieldChange::new("given_name", None, form.given_name.as_deref()),
+        FieldChange::new("family_name", None, form.family_name.as_deref()),
+        FieldChange::redacted("password"),
+    ];
 
     let name = match (&form.given_name, &form.family_name) {
         (Some(given), Some(family)) => Some(format!("{} {}", given, family)),
@@ -170,28 +332,54 @@ pub async fn create(
         ..Default::default()
     };
 
-    match state.client.users().create(request).await {
-        Ok(_) => {
-            let users = match state.client.users().list(None).await {
-                Ok(users) => users,
-                Err(e) => {
-                    tracing::error!(error = ?e, "failed to list users after create");
-                    Vec::new()
-                }
-            };
+    match tenant
+        .client
+        .send(|c| async move { c.users().create(request).await })
+        .await
+    {
+        Ok(user) => {
+            state
+                .audit
+                .record(
+                    &operator,
+                    &tenant,
+                    Action::CreateUser,
+                    &user.user_id,
+                    changes,
+                    None,
+                )
+                .await?;
 
+            let (users, pagination, error) = users_page(&tenant, &ListQuery::default()).await;
             render(TableTemplate {
+                operator,
+                tenant,
                 users,
-                page: 0,
-                total_pages: 1,
+                pagination,
+                error,
             })
         }
         Err(e) => {
             tracing::error!(error = ?e, "failed to create user");
+            state
+                .audit
+                .record(
+                    &operator,
+                    &tenant,
+                    Action::CreateUser,
+                    &form_snapshot.email,
+                    changes,
+                    Some(e.to_string()),
+                )
+                .await?;
             let mut errors = FormErrors::new();
-            errors.add_base(&format!("Failed to create user: {}", e));
-            let connections = get_connection_names(&state.client).await;
+            errors.add_base(&format!(
+                "Failed to create user: {}",
+                AppError::from(e).detail()
+            ));
+            let connections = connection_choices(&tenant, &mut errors).await;
             render(CreateFormTemplate {
+                tenant,
                 form: form_snapshot,
                 connections,
                 errors,
@@ -201,20 +389,18 @@ pub async fn create(
 }
 
 pub async fn get(
-    State(state): State<AppState>,
-    Path(id): Path<String>,
+    RequireViewer(operator): RequireViewer,
+    tenant: Tenant,
+    Path(UserPath { id }): Path<UserPath>,
 ) -> AppResult<Response> {
-    let user = state
-        .client
-        .users()
-        .get(UserId::new(&id))
-        .await
-        .map_err(|e| {
-            tracing::warn!(error = ?e, %id, "user not found");
-            AppError::NotFound
-        })?;
+    let user = fetch_user(&tenant, &id).await.map_err(|e| {
+        tracing::warn!(error = ?e, %id, "failed to get user");
+        AppError::from(e)
+    })?;
 
     render(DetailTemplate {
+        operator,
+        tenant,
         user,
         errors: FormErrors::new(),
     })
@@ -237,22 +423,29 @@ pub struct UpdateForm {
 
 pub async fn update(
     State(state): State<AppState>,
-    Path(id): Path<String>,
+    RequireSupport(operator): RequireSupport,
+    tenant: Tenant,
+    Path(UserPath { id }): Path<UserPath>,
     Form(form): Form<UpdateForm>,
 ) -> AppResult<Response> {
+    if form.password.as_deref().is_some_and(|p| !p.is_empty()) {
+        operator.require(Role::Admin)?;
+    }
+
     let errors = match form.validate() {
         Ok(_) => FormErrors::new(),
         Err(e) => FormErrors::from(e),
     };
 
+    let user = fetch_user(&tenant, &id).await.map_err(AppError::from)?;
+
     if !errors.is_empty() {
-        let user = state
-            .client
-            .users()
-            .get(UserId::new(&id))
-            .await
-            .map_err(|_| AppError::NotFound)?;
-        return render(DetailTemplate { user, errors });
+        return render(DetailTemplate {
+            operator,
+            tenant,
+            user,
+            errors,
+        });
     }
 
     let name = match (&form.given_name, &form.family_name) {
@@ -276,63 +469,157 @@ pub async fn update(
         name,
         ..Default::default()
     };
+    let changes = update_changes(&user, &request);
 
-    match state.client.users().update(UserId::new(&id), request).await {
+    let user_id = UserId::new(&id);
+    match tenant
+        .client
+        .send(|c| async move { c.users().update(user_id, request).await })
+        .await
+    {
         Ok(_) => {
-            let user = state
-                .client
-                .users()
-                .get(UserId::new(&id))
-                .await
-                .map_err(|_| AppError::NotFound)?;
+            state
+                .audit
+                .record(&operator, &tenant, Action::UpdateUser, &id, changes, None)
+                .await?;
+
+            let user = fetch_user(&tenant, &id).await.map_err(AppError::from)?;
             render(DetailTemplate {
+                operator,
+                tenant,
                 user,
                 errors: FormErrors::new(),
             })
         }
         Err(e) => {
             tracing::error!(error = ?e, %id, "failed to update user");
+            state
+                .audit
+                .record(
+                    &operator,
+                    &tenant,
+                    Action::UpdateUser,
+                    &id,
+                    changes,
+                    Some(e.to_string()),
+                )
+                .await?;
             let mut errors = FormErrors::new();
-            errors.add_base(&format!("Failed to update user: {}", e));
-            let user = state
-                .client
-                .users()
-                .get(UserId::new(&id))
-                .await
-                .map_err(|_| AppError::NotFound)?;
-            render(DetailTemplate { user, errors })
+            errors.add_base(&format!(
+                "Failed to update user: {}",
+                AppError::from(e).detail()
+            ));
+            render(DetailTemplate {
+                operator,
+                tenant,
+                user,
+                errors,
+            })
         }
     }
 }
 
+async fn fetch_user(
+    tenant: &Tenant,
+    id: &str,
+) -> Result<auth0_mgmt_api::types::users::User, auth0_mgmt_api::Error> {
+    tenant
+        .client
+        .get(|c| async move { c.users().get(UserId::new(id)).await })
+        .await
+}
+
+/// Diffs the fields an `UpdateUserRequest` would change against the current user.
+fn update_changes(
+    user: &auth0_mgmt_api::types::users::User,
+    request: &UpdateUserRequest,
+) -> Vec<FieldChange> {
+    let fields = [
+        ("email", user.email.as_deref(), request.email.as_deref()),
+        (
+            "username",
+            user.username.as_deref(),
+            request.username.as_deref(),
+        ),
+        (
+            "given_name",
+            user.given_name.as_deref(),
+            request.given_name.as_deref(),
+        ),
+        (
+            "family_name",
+            user.family_name.as_deref(),
+            request.family_name.as_deref(),
+        ),
+        (
+            "nickname",
+            user.nickname.as_deref(),
+            request.nickname.as_deref(),
+        ),
+        (
+            "phone_number",
+            user.phone_number.as_deref(),
+            request.phone_number.as_deref(),
+        ),
+        (
+            "picture",
+            user.picture.as_deref(),
+            request.picture.as_deref(),
+        ),
+        ("name", user.name.as_deref(), request.name.as_deref()),
+    ];
+
+    let mut changes: Vec<FieldChange> = fields
+        .into_iter()
+        .filter(|(_, before, after)| after.is_some() && before != after)
+        .map(|(field, before, after)| FieldChange::new(field, before, after))
+        .collect();
+
+    if request.password.is_some() {
+        changes.push(FieldChange::redacted("password"));
+    }
+    changes
+}
+
 pub async fn delete(
     State(state): State<AppState>,
-    Path(id): Path<String>,
+    RequireAdmin(operator): RequireAdmin,
+    tenant: Tenant,
+    Path(UserPath { id }): Path<UserPath>,
 ) -> AppResult<Response> {
-    state
+    let email = match fetch_user(&tenant, &id).await {
+        Ok(user) => user.email,
+        Err(_) => None,
+    };
+    let changes = vec![FieldChange::new("email", email.as_deref(), None)];
+
+    let user_id = UserId::new(&id);
+    let result = tenant
         .client
-        .users()
-        .delete(UserId::new(&id))
-        .await
-        .map_err(|e| {
-            tracing::error!(error = ?e, %id, "failed to delete user");
-            AppError::Auth0(e.to_string())
-        })?;
+        .send(|c| async move { c.users().delete(user_id).await })
+        .await;
+    let error = result.as_ref().err().map(|e| e.to_string());
+    state
+        .audit
+        .record(&operator, &tenant, Action::DeleteUser, &id, changes, error)
+        .await?;
 
-    Ok(Redirect::to("/users").into_response())
+    result.map_err(|e| {
+        tracing::error!(error = ?e, %id, "failed to delete user");
+        AppError::from(e)
+    })?;
+
+    Ok(Redirect::to(&tenant.path("/users")).into_response())
 }
 
 pub async fn toggle_block(
     State(state): State<AppState>,
-    Path(id): Path<String>,
+    RequireAdmin(operator): RequireAdmin,
+    tenant: Tenant,
+    Path(UserPath { id }): Path<UserPath>,
     headers: HeaderMap,
 ) -> AppResult<Response> {
-    let user = state
-        .client
-        .users()
-        .get(UserId::new(&id))
-        .await
-        .map_err(|_| AppError::NotFound)?;
+    let user = fetch_user(&tenant, &id).await.map_err(AppError::from)?;
 
     let currently_blocked = user.blocked.unwrap_or(false);
     let request = UpdateUserRequest {
@@ -340,37 +627,51 @@ pub async fn toggle_block(
         ..Default::default()
     };
 
-    state
+    let action = if currently_blocked {
+        Action::UnblockUser
+    } else {
+        Action::BlockUser
+    };
+    let changes = vec![FieldChange::new(
+        "blocked",
+        Some(&currently_blocked.to_string()),
+        Some(&(!currently_blocked).to_string()),
+    )];
+
+    let user_id = UserId::new(&id);
+    let result = tenant
         .client
-        .users()
-        .update(UserId::new(&id), request)
-        .await
-        .map_err(|e| {
-            tracing::error!(error = ?e, %id, "failed to toggle block status");
-            AppError::Auth0(e.to_string())
-        })?;
+        .send(|c| async move { c.users().update(user_id, request).await })
+        .await;
+    let error = result.as_ref().err().map(|e| e.to_string());
+    state
+        .audit
+        .record(&operator, &tenant, action, &id, changes, error)
+        .await?;
+
+    result.map_err(|e| {
+        tracing::error!(error = ?e, %id, "failed to toggle block status");
+        AppError::from(e)
+    })?;
 
     if htmx_target_is(&headers, "users-table") {
-        let users = match state.client.users().list(None).await {
-            Ok(users) => users,
-            Err(e) => {
-                tracing::error!(error = ?e, "failed to list users");
-                Vec::new()
-            }
-        };
+        let (users, pagination, error) = users_page(&tenant, &ListQuery::default()).await;
         render(TableTemplate {
+            operator,
+            tenant,
             users,
-            page: 0,
-            total_pages: 1,
+            pagination,
+            error,
         })
     } else {
-        Ok(Redirect::to(&format!("/users/{}", id)).into_response())
+        Ok(Redirect::to(&tenant.path(&format!("/users/{}", id))).into_response())
     }
 }
 
 pub async fn get_logs(
-    State(state): State<AppState>,
-    Path(id): Path<String>,
+    _: RequireViewer,
+    tenant: Tenant,
+    Path(UserPath { id }): Path<UserPath>,
 ) -> AppResult<Response> {
     let params = auth0_mgmt_api::types::users::GetUserLogsParams {
         page: Some(0),
@@ -379,13 +680,1748 @@ pub async fn get_logs(
         include_totals: Some(false),
     };
 
-    match state
+    let (user_id, params) = (id.as_str(), &params);
+    match tenant
         .client
-        .users()
-        .get_logs(UserId::new(&id), Some(params))
+        .get(|c| async move {
+            c.users()
+                .get_logs(UserId::new(user_id), Some(params.clone()))
+                .await
+        })
         .await
     {
         Ok(logs) => render(LogsTemplate { logs }),
-        Err(_) => Ok(Html("<p class='text-muted'>Unable to load logs</p>").into_response()),
+        Err(e) => {
+            tracing::error!(error = ?e, %id, "failed to get user logs");
+            render(ErrorBannerTemplate { error: e.into() })
+        }
+    }
+}
+
+/// Every role assigned to the user, across all pages.
+async fn fetch_user_roles(
+    tenant: &Tenant,
+    id: &str,
+) -> Result<Vec<Auth0Role>, auth0_mgmt_api::Error> {
+    fetch_all(|page| async move {
+        let params = ListUserRolesParams {
+            page: Some(page),
+            per_page: Some(PAGE_SIZE),
+            include_totals: Some(true),
+            ..Default::default()
+        };
+        let params = &params;
+        let result = tenant
+            .client
+            .get(|c| async move {
+                c.users()
+                    .roles_paginated(UserId::new(id), Some(params.clone()))
+                    .await
+            })
+            .await?;
+        Ok((result.items, result.total))
+    })
+    .await
+}
+
+/// Every permission the user has, directly or through roles, across all
+/// pages.
+async fn fetch_user_permissions(
+    tenant: &Tenant,
+    id: &str,
+) -> Result<Vec<Permission>, auth0_mgmt_api::Error> {
+    fetch_all(|page| async move {
+        let params = ListUserPermissionsParams {
+            page: Some(page),
+            per_page: Some(PAGE_SIZE),
+            include_totals: Some(true),
+            ..Default::default()
+        };
+        let params = &params;
+        let result = tenant
+            .client
+            .get(|c| async move {
+                c.users()
+                    .permissions_paginated(UserId::new(id), Some(params.clone()))
+                    .await
+            })
+            .await?;
+        Ok((result.items, result.total))
+    })
+    .await
+}
+
+/// Roles from the tenant's list that the user does not have yet.
+fn unassigned(all: &[Auth0Role], assigned: &[Auth0Role]) -> Vec<Auth0Role> {
+    all.iter()
+        .filter(|role| !assigned.iter().any(|r| r.id == role.id))
+        .cloned()
+        .collect()
+}
+
+/// The roles card on the user page: assigned roles, the picker and the
+/// permissions those roles grant. `error` is a failed change to show
+/// above it.
+async fn roles_card(
+    operator: Operator,
+    tenant: Tenant,
+    id: String,
+    error: Option<AppError>,
+) -> AppResult<Response> {
+    let lists = tokio::try_join!(
+        fetch_user_roles(&tenant, &id),
+        fetch_user_permissions(&tenant, &id),
+        tenant.cache.roles(&tenant.client),
+    );
+    let (roles, permissions, all_roles) = match lists {
+        Ok(lists) => lists,
+        Err(e) => {
+            tracing::error!(error = ?e, %id, "failed to get user roles");
+            return render(ErrorBannerTemplate { error: e.into() });
+        }
+    };
+
+    let available = unassigned(&all_roles, &roles);
+
+    render(RolesTemplate {
+        operator,
+        tenant,
+        user_id: id,
+        roles,
+        available,
+        permissions,
+        error,
+    })
+}
+
+pub async fn get_roles(
+    RequireViewer(operator): RequireViewer,
+    tenant: Tenant,
+    Path(UserPath { id }): Path<UserPath>,
+) -> AppResult<Response> {
+    roles_card(operator, tenant, id, None).await
+}
+
+#[derive(Deserialize)]
+pub struct MethodPath {
+    id: String,
+    method_id: String,
+}
+
+/// A session, refresh token or device credential of the user.
+#[derive(Deserialize)]
+pub struct CredentialPath {
+    id: String,
+    credential_id: String,
+}
+
+#[derive(Deserialize)]
+pub struct UserRoleForm {
+    role_id: String,
+}
+
+/// Role names for the audit log, from the cached list.
+async fn role_name(tenant: &Tenant, role_id: &str) -> String {
+    match tenant.cache.roles(&tenant.client).await {
+        Ok(roles) => roles
+            .iter()
+            .find(|role| role.id == role_id)
+            .and_then(|role| role.name.clone())
+            .unwrap_or_else(|| role_id.to_string()),
+        Err(_) => role_id.to_string(),
+    }
+}
+
+pub async fn assign_role(
+    State(state): State<AppState>,
+    RequireAdmin(operator): RequireAdmin,
+    tenant: Tenant,
+    Path(UserPath { id }): Path<UserPath>,
+    Form(form): Form<UserRoleForm>,
+) -> AppResult<Response> {
+    if form.role_id.is_empty() {
+        return roles_card(operator, tenant, id, None).await;
+    }
+
+    let name = role_name(&tenant, &form.role_id).await;
+    let changes = vec![FieldChange::new("roles", None, Some(&name))];
+    let request = UserRolesRequest {
+        roles: vec![form.role_id],
+    };
+    let user_id = UserId::new(&id);
+    let result = tenant
+        .client
+        .send(|c| async move { c.users().assign_roles(user_id, request).await })
+        .await;
+    let error = result.as_ref().err().map(|e| e.to_string());
+    state
+        .audit
+        .record(
+            &operator,
+            &tenant,
+            Action::AssignUserRoles,
+            &id,
+            changes,
+            error,
+        )
+        .await?;
+
+    let error = result.err().map(|e| {
+        tracing::error!(error = ?e, %id, "failed to assign role");
+        AppError::from(e)
+    });
+    roles_card(operator, tenant, id, error).await
+}
+
+pub async fn remove_role(
+    State(state): State<AppState>,
+    RequireAdmin(operator): RequireAdmin,
+    tenant: Tenant,
+    Path(UserPath { id }): Path<UserPath>,
+    Query(form): Query<UserRoleForm>,
+) -> AppResult<Response> {
+    let name = role_name(&tenant, &form.role_id).await;
+    let changes = vec![FieldChange::new("roles", Some(&name), None)];
+    let request = UserRolesRequest {
+        roles: vec![form.role_id],
+    };
+    let user_id = UserId::new(&id);
+    let result = tenant
+        .client
+        .send(|c| async move { c.users().remove_roles(user_id, request).await })
+        .await;
+    let error = result.as_ref().err().map(|e| e.to_string());
+    state
+        .audit
+        .record(
+            &operator,
+            &tenant,
+            Action::RemoveUserRoles,
+            &id,
+            changes,
+            error,
+        )
+        .await?;
+
+    let error = result.err().map(|e| {
+        tracing::error!(error = ?e, %id, "failed to remove role");
+        AppError::from(e)
+    });
+    roles_card(operator, tenant, id, error).await
+}
+
+/// How the submitted metadata is combined with what the user has.
+#[derive(Clone, Copy, Default, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum MetadataMode {
+    /// Auth0's own semantics: top-level keys are set, and a key set to
+    /// `null` is removed. Keys not mentioned are left alone.
+    #[default]
+    Merge,
+    /// The submitted object becomes the whole value.
+    Replace,
+}
+
+#[derive(Clone, Default, Deserialize)]
+pub struct MetadataForm {
+    #[serde(default)]
+    app_metadata: String,
+    #[serde(default)]
+    user_metadata: String,
+    #[serde(default)]
+    mode: MetadataMode,
+    /// Fingerprint of the metadata the preview was computed against.
+    #[serde(default)]
+    fingerprint: String,
+}
+
+impl MetadataForm {
+    fn from_user(user: &auth0_mgmt_api::types::users::User) -> Self {
+        let pretty = |value: &Option<Value>| {
+            serde_json::to_string_pretty(&Value::Object(metadata_object(value))).unwrap_or_default()
+        };
+        Self {
+            app_metadata: pretty(&user.app_metadata),
+            user_metadata: pretty(&user.user_metadata),
+            mode: MetadataMode::Merge,
+            fingerprint: metadata_fingerprint(&user.app_metadata, &user.user_metadata),
+        }
+    }
+
+    pub fn is_replace(&self) -> bool {
+        self.mode == MetadataMode::Replace
+    }
+}
+
+fn metadata_object(value: &Option<Value>) -> Map<String, Value> {
+    value
+        .as_ref()
+        .and_then(Value::as_object)
+        .cloned()
+        .unwrap_or_default()
+}
+
+/// Detects metadata changed by someone else between preview and save.
+fn metadata_fingerprint(app_metadata: &Option<Value>, user_metadata: &Option<Value>) -> String {
+    let both = serde_json::json!([app_metadata, user_metadata]);
+    format!("{:x}", Sha256::digest(both.to_string()))
+}
+
+/// Metadata must be a JSON object; an empty box means `{}`.
+fn parse_metadata(
+    field: &'static str,
+    text: &str,
+    errors: &mut ValidationErrors,
+) -> Option<Map<String, Value>> {
+    if text.trim().is_empty() {
+        return Some(Map::new());
+    }
+    let message = match serde_json::from_str::<Value>(text) {
+        Ok(Value::Object(map)) => return Some(map),
+        Ok(_) => "Must be a JSON object".to_string(),
+        Err(e) => format!("Invalid JSON: {}", e),
+    };
+    errors.add(
+        field,
+        ValidationError::new("json").with_message(Cow::Owned(message)),
+    );
+    None
+}
+
+/// The metadata the user ends up with after saving `input`.
+fn combine_metadata(
+    current: &Map<String, Value>,
+    input: Map<String, Value>,
+    mode: MetadataMode,
+) -> Map<String, Value> {
+    let mut result = match mode {
+        MetadataMode::Merge => current.clone(),
+        MetadataMode::Replace => Map::new(),
+    };
+    for (key, value) in input {
+        if value.is_null() {
+            result.remove(&key);
+        } else {
+            result.insert(key, value);
+        }
+    }
+    result
+}
+
+/// The smallest update that turns `current` into `result`: Auth0 merges
+/// metadata at the top level, so changed keys are sent whole and removed
+/// keys as `null`. `None` if nothing changes.
+fn metadata_patch(current: &Map<String, Value>, result: &Map<String, Value>) -> Option<Value> {
+    let mut patch = Map::new();
+    for (key, value) in result {
+        if current.get(key) != Some(value) {
+            patch.insert(key.clone(), value.clone());
+        }
+    }
+    for key in current.keys() {
+        if !result.contains_key(key) {
+            patch.insert(key.clone(), Value::Null);
+        }
+    }
+    (!patch.is_empty()).then_some(Value::Object(patch))
+}
+
+fn flatten_metadata(path: String, value: &Value, out: &mut BTreeMap<String, String>) {
+    match value {
+        Value::Object(map) if !map.is_empty() => {
+            for (key, value) in map {
+                flatten_metadata(format!("{}.{}", path, key), value, out);
+            }
+        }
+        _ => {
+            out.insert(path, value.to_string());
+        }
+    }
+}
+
+/// Changes by dotted path, e.g. `app_metadata.flags.beta`, for the preview
+/// and the audit log.
+fn metadata_diff(
+    field: &str,
+    before: &Map<String, Value>,
+    after: &Map<String, Value>,
+) -> Vec<FieldChange> {
+    let (mut old, mut new) = (BTreeMap::new(), BTreeMap::new());
+    for (key, value) in before {
+        flatten_metadata(format!("{}.{}", field, key), value, &mut old);
+    }
+    for (key, value) in after {
+        flatten_metadata(format!("{}.{}", field, key), value, &mut new);
+    }
+
+    let mut paths: Vec<&String> = old.keys().chain(new.keys()).collect();
+    paths.sort();
+    paths.dedup();
+    paths
+        .into_iter()
+        .filter(|path| old.get(*path) != new.get(*path))
+        .map(|path| {
+            FieldChange::new(
+                path,
+                old.get(path).map(String::as_str),
+                new.get(path).map(String::as_str),
+            )
+        })
+        .collect()
+}
+
+/// What a metadata form would do to `user`: the update to send and the
+/// changes it makes, or the form errors.
+struct MetadataPlan {
+    app_metadata: Option<Value>,
+    user_metadata: Option<Value>,
+    changes: Vec<FieldChange>,
+}
+
+fn plan_metadata(
+    operator: &Operator,
+    user: &auth0_mgmt_api::types::users::User,
+    form: &MetadataForm,
+) -> Result<MetadataPlan, FormErrors> {
+    let mut errors = ValidationErrors::new();
+    let app_input = parse_metadata("app_metadata", &form.app_metadata, &mut errors);
+    let user_input = parse_metadata("user_metadata", &form.user_metadata, &mut errors);
+    let (Some(app_input), Some(user_input)) = (app_input, user_input) else {
+        return Err(FormErrors::from(errors));
+    };
+
+    let app_current = metadata_object(&user.app_metadata);
+    let user_current = metadata_object(&user.user_metadata);
+    let app_result = combine_metadata(&app_current, app_input, form.mode);
+    let user_result = combine_metadata(&user_current, user_input, form.mode);
+
+    let app_metadata = metadata_patch(&app_current, &app_result);
+    // app_metadata often drives authorization, so only admins may change it.
+    if app_metadata.is_some() && operator.require(Role::Admin).is_err() {
+        let mut errors = ValidationErrors::new();
+        errors.add(
+            "app_metadata",
+            ValidationError::new("forbidden")
+                .with_message(Cow::Borrowed("Only admins can change app_metadata")),
+        );
+        return Err(FormErrors::from(errors));
+    }
+
+    let mut changes = metadata_diff("app_metadata", &app_current, &app_result);
+    changes.extend(metadata_diff("user_metadata", &user_current, &user_result));
+    Ok(MetadataPlan {
+        app_metadata,
+        user_metadata: metadata_patch(&user_current, &user_result),
+        changes,
+    })
+}
+
+fn metadata_card(
+    operator: Operator,
+    tenant: Tenant,
+    user_id: String,
+    form: MetadataForm,
+    diff: Option<Vec<FieldChange>>,
+    notice: Option<String>,
+    errors: FormErrors,
+) -> AppResult<Response> {
+    render(MetadataTemplate {
+        operator,
+        tenant,
+        user_id,
+        form,
+        diff,
+        notice,
+        errors,
+    })
+}
+
+pub async fn get_metadata(
+    RequireViewer(operator): RequireViewer,
+    tenant: Tenant,
+    Path(UserPath { id }): Path<UserPath>,
+) -> AppResult<Response> {
+    let user = match fetch_user(&tenant, &id).await {
+        Ok(user) => user,
+        Err(e) => {
+            tracing::error!(error = ?e, %id, "failed to get user metadata");
+            return render(ErrorBannerTemplate { error: e.into() });
+        }
+    };
+    let form = MetadataForm::from_user(&user);
+    metadata_card(operator, tenant, id, form, None, None, FormErrors::new())
+}
+
+/// Validates the edited metadata and shows what saving it would change,
+/// without changing anything.
+pub async fn preview_metadata(
+    RequireSupport(operator): RequireSupport,
+    tenant: Tenant,
+    Path(UserPath { id }): Path<UserPath>,
+    Form(mut form): Form<MetadataForm>,
+) -> AppResult<Response> {
+    let user = match fetch_user(&tenant, &id).await {
+        Ok(user) => user,
+        Err(e) => return render(ErrorBannerTemplate { error: e.into() }),
+    };
+    form.fingerprint = metadata_fingerprint(&user.app_metadata, &user.user_metadata);
+    match plan_metadata(&operator, &user, &form) {
+        Ok(plan) => metadata_card(
+            operator,
+            tenant,
+            id,
+            form,
+            Some(plan.changes),
+            None,
+            FormErrors::new(),
+        ),
+        Err(errors) => metadata_card(operator, tenant, id, form, None, None, errors),
+    }
+}
+
+/// Saves a previewed change. If the metadata changed since the preview,
+/// the new diff is shown again instead of saving.
+pub async fn update_metadata(
+    State(state): State<AppState>,
+    RequireSupport(operator): RequireSupport,
+    tenant: Tenant,
+    Path(UserPath { id }): Path<UserPath>,
+    Form(mut form): Form<MetadataForm>,
+) -> AppResult<Response> {
+    let user = match fetch_user(&tenant, &id).await {
+        Ok(user) => user,
+        Err(e) => return render(ErrorBannerTemplate { error: e.into() }),
+    };
+    let plan = match plan_metadata(&operator, &user, &form) {
+        Ok(plan) => plan,
+        Err(errors) => return metadata_card(operator, tenant, id, form, None, None, errors),
+    };
+
+    let fingerprint = metadata_fingerprint(&user.app_metadata, &user.user_metadata);
+    if form.fingerprint != fingerprint {
+        form.fingerprint = fingerprint;
+        let notice = "The metadata changed since the preview; review the changes again.";
+        return metadata_card(
+            operator,
+            tenant,
+            id,
+            form,
+            Some(plan.changes),
+            Some(notice.to_string()),
+            FormErrors::new(),
+        );
+    }
+    if plan.changes.is_empty() {
+        let form = MetadataForm::from_user(&user);
+        let notice = Some("Nothing to change.".to_string());
+        return metadata_card(operator, tenant, id, form, None, notice, FormErrors::new());
+    }
+
+    let request = UpdateUserRequest {
+        app_metadata: plan.app_metadata,
+        user_metadata: plan.user_metadata,
+        ..Default::default()
+    };
+    let user_id = UserId::new(&id);
+    let result = tenant
+        .client
+        .send(|c| async move { c.users().update(user_id, request).await })
+        .await;
+    let error = result.as_ref().err().map(|e| e.to_string());
+    state
+        .audit
+        .record(
+            &operator,
+            &tenant,
+            Action::UpdateUserMetadata,
+            &id,
+            plan.changes,
+            error,
+        )
+        .await?;
+
+    match result {
+        Ok(updated) => {
+            let form = MetadataForm::from_user(&updated);
+            let notice = Some("Metadata saved.".to_string());
+            metadata_card(operator, tenant, id, form, None, notice, FormErrors::new())
+        }
+        Err(e) => {
+            tracing::error!(error = ?e, %id, "failed to update user metadata");
+            let mut errors = FormErrors::new();
+            errors.add_base(&format!(
+                "Failed to save metadata: {}",
+                AppError::from(e).detail()
+            ));
+            metadata_card(operator, tenant, id, form, None, None, errors)
+        }
+    }
+}
+
+async fn mfa_card(
+    operator: Operator,
+    tenant: Tenant,
+    id: String,
+    recovery_code: Option<String>,
+    error: Option<AppError>,
+) -> AppResult<Response> {
+    let user_id = id.as_str();
+    let methods = match tenant
+        .client
+        .get(|c| async move { c.users().authentication_methods(UserId::new(user_id)).await })
+        .await
+    {
+        Ok(methods) => methods,
+        Err(e) => {
+            tracing::error!(error = ?e, %id, "failed to list authentication methods");
+            return render(ErrorBannerTemplate { error: e.into() });
+        }
+    };
+
+    render(MfaTemplate {
+        operator,
+        tenant,
+        user_id: id,
+        methods,
+        recovery_code,
+        error,
+    })
+}
+
+pub async fn get_mfa(
+    RequireViewer(operator): RequireViewer,
+    tenant: Tenant,
+    Path(UserPath { id }): Path<UserPath>,
+) -> AppResult<Response> {
+    mfa_card(operator, tenant, id, None, None).await
+}
+
+/// Removes one enrolled factor, e.g. the authenticator on a lost phone.
+pub async fn delete_mfa_method(
+    State(state): State<AppState>,
+    RequireSupport(operator): RequireSupport,
+    tenant: Tenant,
+    Path(MethodPath { id, method_id }): Path<MethodPath>,
+) -> AppResult<Response> {
+    let changes = vec![FieldChange::new(
+        "authentication_methods",
+        Some(&method_id),
+        None,
+    )];
+    let user_id = UserId::new(&id);
+    let method = AuthenticationMethodId::new(&method_id);
+    let result = tenant
+        .client
+        .send(|c| async move {
+            c.users()
+                .delete_authentication_method(user_id, method)
+                .await
+        })
+        .await;
+    let error = result.as_ref().err().map(|e| e.to_string());
+    state
+        .audit
+        .record(
+            &operator,
+            &tenant,
+            Action::DeleteUserMfaMethod,
+            &id,
+            changes,
+            error,
+        )
+        .await?;
+
+    let error = result.err().map(|e| {
+        tracing::error!(error = ?e, %id, %method_id, "failed to delete authentication method");
+        AppError::from(e)
+    });
+    mfa_card(operator, tenant, id, None, error).await
+}
+
+/// Removes every enrolled factor, so the user enrolls again at next login.
+pub async fn reset_mfa(
+    State(state): State<AppState>,
+    RequireSupport(operator): RequireSupport,
+    tenant: Tenant,
+    Path(UserPath { id }): Path<UserPath>,
+) -> AppResult<Response> {
+    let user_id = UserId::new(&id);
+    let result = tenant
+        .client
+        .send(|c| async move { c.users().delete_authentication_methods(user_id).await })
+        .await;
+    let error = result.as_ref().err().map(|e| e.to_string());
+    state
+        .audit
+        .record(
+            &operator,
+            &tenant,
+            Action::ResetUserMfa,
+            &id,
+            vec![FieldChange::new("authentication_methods", None, Some("[]"))],
+            error,
+        )
+        .await?;
+
+    let error = result.err().map(|e| {
+        tracing::error!(error = ?e, %id, "failed to reset authentication methods");
+        AppError::from(e)
+    });
+    mfa_card(operator, tenant, id, None, error).await
+}
+
+/// Replaces the user's recovery code. Like a rotated client secret, the
+/// new code appears only in this response and is never logged or audited.
+pub async fn regenerate_recovery_code(
+    State(state): State<AppState>,
+    RequireSupport(operator): RequireSupport,
+    tenant: Tenant,
+    Path(UserPath { id }): Path<UserPath>,
+) -> AppResult<Response> {
+    let user_id = UserId::new(&id);
+    let result = tenant
+        .client
+        .send(|c| async move { c.users().regenerate_recovery_code(user_id).await })
+        .await;
+    let error = result.as_ref().err().map(|e| e.to_string());
+    state
+        .audit
+        .record(
+            &operator,
+            &tenant,
+            Action::RegenerateUserRecoveryCode,
+            &id,
+            vec![FieldChange::redacted("recovery_code")],
+            error,
+        )
+        .await?;
+
+    let (recovery_code, error) = match result {
+        Ok(code) => (Some(code.recovery_code), None),
+        Err(e) => {
+            tracing::error!(error = %e, %id, "failed to regenerate recovery code");
+            (None, Some(AppError::from(e)))
+        }
+    };
+    let mut response = mfa_card(operator, tenant, id, recovery_code, error).await?;
+    response.headers_mut().insert(
+        header::CACHE_CONTROL,
+        header::HeaderValue::from_static("no-store"),
+    );
+    Ok(response)
+}
+
+/// Everything that keeps a user signed in without re-entering credentials.
+struct Credentials {
+    sessions: Vec<Session>,
+    refresh_tokens: Vec<RefreshToken>,
+    device_credentials: Vec<DeviceCredential>,
+}
+
+/// All of them, across every page, so revoking never misses any.
+async fn fetch_credentials(
+    tenant: &Tenant,
+    id: &str,
+) -> Result<Credentials, auth0_mgmt_api::Error> {
+    let (sessions, refresh_tokens, device_credentials) = tokio::try_join!(
+        fetch_all_from(|from| async move {
+            let params = ListUserSessionsParams {
+                from,
+                take: Some(PAGE_SIZE),
+                ..Default::default()
+            };
+            let params = &params;
+            let page = tenant
+                .client
+                .get(|c| async move {
+                    c.users()
+                        .sessions_paginated(UserId::new(id), Some(params.clone()))
+                        .await
+                })
+                .await?;
+            Ok((page.sessions, page.next))
+        }),
+        fetch_all_from(|from| async move {
+            let params = ListUserRefreshTokensParams {
+                from,
+                take: Some(PAGE_SIZE),
+                ..Default::default()
+            };
+            let params = &params;
+            let page = tenant
+                .client
+                .get(|c| async move {
+                    c.users()
+                        .refresh_tokens_paginated(UserId::new(id), Some(params.clone()))
+                        .await
+                })
+                .await?;
+            Ok((page.tokens, page.next))
+        }),
+        fetch_device_credentials(tenant, id),
+    )?;
+    Ok(Credentials {
+        sessions,
+        refresh_tokens,
+        device_credentials,
+    })
+}
+
+/// Device credentials have no revoke-all endpoint, so revoking them needs
+/// every page of the list.
+async fn fetch_device_credentials(
+    tenant: &Tenant,
+    id: &str,
+) -> Result<Vec<DeviceCredential>, auth0_mgmt_api::Error> {
+    fetch_all(|page| async move {
+        let params = ListDeviceCredentialsParams {
+            user_id: Some(id.to_string()),
+            page: Some(page),
+            per_page: Some(PAGE_SIZE),
+            include_totals: Some(true),
+            ..Default::default()
+        };
+        let params = &params;
+        let result = tenant
+            .client
+            .get(|c| async move {
+                c.device_credentials()
+                    .list_paginated(Some(params.clone()))
+                    .await
+            })
+            .await?;
+        Ok((result.items, result.total))
+    })
+    .await
+}
+
+async fn sessions_card(
+    operator: Operator,
+    tenant: Tenant,
+    id: String,
+    error: Option<AppError>,
+) -> AppResult<Response> {
+    let credentials = match fetch_credentials(&tenant, &id).await {
+        Ok(credentials) => credentials,
+        Err(e) => {
+            tracing::error!(error = ?e, %id, "failed to list sessions and tokens");
+            return render(ErrorBannerTemplate { error: e.into() });
+        }
+    };
+
+    render(SessionsTemplate {
+        operator,
+        tenant,
+        user_id: id,
+        sessions: credentials.sessions,
+        refresh_tokens: credentials.refresh_tokens,
+        device_credentials: credentials.device_credentials,
+        error,
+    })
+}
+
+pub async fn get_sessions(
+    RequireViewer(operator): RequireViewer,
+    tenant: Tenant,
+    Path(UserPath { id }): Path<UserPath>,
+) -> AppResult<Response> {
+    sessions_card(operator, tenant, id, None).await
+}
+
+/// Which kind of credential a revoke-one request names.
+#[derive(Clone, Copy)]
+enum CredentialKind {
+    Session,
+    RefreshToken,
+    DeviceCredential,
+}
+
+impl CredentialKind {
+    fn field(self) -> &'static str {
+        match self {
+            CredentialKind::Session => "sessions",
+            CredentialKind::RefreshToken => "refresh_tokens",
+            CredentialKind::DeviceCredential => "device_credentials",
+        }
+    }
+
+    /// Credential IDs are global in Auth0, so check the one named in the
+    /// URL really belongs to this user before revoking it.
+    fn belongs_to(self, credentials: &Credentials, credential_id: &str) -> bool {
+        match self {
+            CredentialKind::Session => credentials.sessions.iter().any(|s| s.id == credential_id),
+            CredentialKind::RefreshToken => credentials
+                .refresh_tokens
+                .iter()
+                .any(|t| t.id == credential_id),
+            CredentialKind::DeviceCredential => credentials
+                .device_credentials
+                .iter()
+                .any(|d| d.id == credential_id),
+        }
+    }
+}
+
+async fn revoke_one(
+    state: AppState,
+    operator: Operator,
+    tenant: Tenant,
+    id: String,
+    credential_id: String,
+    kind: CredentialKind,
+) -> AppResult<Response> {
+    match fetch_credentials(&tenant, &id).await {
+        Ok(credentials) if kind.belongs_to(&credentials, &credential_id) => {}
+        Ok(_) => return sessions_card(operator, tenant, id, Some(AppError::NotFound)).await,
+        Err(e) => return sessions_card(operator, tenant, id, Some(e.into())).await,
+    }
+
+    let changes = vec![FieldChange::new(kind.field(), Some(&credential_id), None)];
+    let result = match kind {
+        CredentialKind::Session => {
+            let session_id = SessionId::new(&credential_id);
+            tenant
+                .client
+                .send(|c| async move { c.sessions().delete(session_id).await })
+                .await
+        }
+        CredentialKind::RefreshToken => {
+            let token_id = RefreshTokenId::new(&credential_id);
+            tenant
+                .client
+                .send(|c| async move { c.refresh_tokens().delete(token_id).await })
+                .await
+        }
+        CredentialKind::DeviceCredential => {
+            let credential = DeviceCredentialId::new(&credential_id);
+            tenant
+                .client
+                .send(|c| async move { c.device_credentials().delete(credential).await })
+                .await
+        }
+    };
+    let error = result.as_ref().err().map(|e| e.to_string());
+    state
+        .audit
+        .record(
+            &operator,
+            &tenant,
+            Action::RevokeUserCredential,
+            &id,
+            changes,
+            error,
+        )
+        .await?;
+
+    let error = result.err().map(|e| {
+        tracing::error!(error = ?e, %id, %credential_id, "failed to revoke {}", kind.field());
+        AppError::from(e)
+    });
+    sessions_card(operator, tenant, id, error).await
+}
+
+pub async fn revoke_session(
+    State(state): State<AppState>,
+    RequireSupport(operator): RequireSupport,
+    tenant: Tenant,
+    Path(CredentialPath { id, credential_id }): Path<CredentialPath>,
+) -> AppResult<Response> {
+    revoke_one(
+        state,
+        operator,
+        tenant,
+        id,
+        credential_id,
+        CredentialKind::Session,
+    )
+    .await
+}
+
+pub async fn revoke_refresh_token(
+    State(state): State<AppState>,
+    RequireSupport(operator): RequireSupport,
+    tenant: Tenant,
+    Path(CredentialPath { id, credential_id }): Path<CredentialPath>,
+) -> AppResult<Response> {
+    revoke_one(
+        state,
+        operator,
+        tenant,
+        id,
+        credential_id,
+        CredentialKind::RefreshToken,
+    )
+    .await
+}
+
+pub async fn revoke_device_credential(
+    State(state): State<AppState>,
+    RequireSupport(operator): RequireSupport,
+    tenant: Tenant,
+    Path(CredentialPath { id, credential_id }): Path<CredentialPath>,
+) -> AppResult<Response> {
+    revoke_one(
+        state,
+        operator,
+        tenant,
+        id,
+        credential_id,
+        CredentialKind::DeviceCredential,
+    )
+    .await
+}
+
+/// Revokes every session, refresh token and device credential, carrying
+/// on past failures so as much as possible is revoked. Audited as one
+/// entry; returns what could not be revoked, or the audit write failure.
+async fn revoke_everything(
+    state: &AppState,
+    operator: &Operator,
+    tenant: &Tenant,
+    id: &str,
+) -> AppResult<Vec<String>> {
+    let mut failures = Vec::new();
+
+    let user_id = UserId::new(id);
+    if let Err(e) = tenant
+        .client
+        .send(|c| async move { c.users().delete_sessions(user_id).await })
+        .await
+    {
+        tracing::error!(error = ?e, %id, "failed to revoke sessions");
+        failures.push(format!("sessions: {}", AppError::from(e).detail()));
+    }
+
+    let user_id = UserId::new(id);
+    if let Err(e) = tenant
+        .client
+        .send(|c| async move { c.users().delete_refresh_tokens(user_id).await })
+        .await
+    {
+        tracing::error!(error = ?e, %id, "failed to revoke refresh tokens");
+        failures.push(format!("refresh tokens: {}", AppError::from(e).detail()));
+    }
+
+    match fetch_device_credentials(tenant, id).await {
+        Ok(credentials) => {
+            for credential in credentials {
+                let credential_id = DeviceCredentialId::new(&credential.id);
+                if let Err(e) = tenant
+                    .client
+                    .send(|c| async move { c.device_credentials().delete(credential_id).await })
+                    .await
+                {
+                    tracing::error!(
+                        error = ?e,
+                        %id,
+                        credential = %credential.id,
+                        "failed to revoke device credential"
+                    );
+                    failures.push(format!(
+                        "device credential {}: {}",
+                        credential.id,
+                        AppError::from(e).detail()
+                    ));
+                }
+            }
+        }
+        Err(e) => {
+            tracing::error!(error = ?e, %id, "failed to list device credentials");
+            failures.push(format!(
+                "device credentials: {}",
+                AppError::from(e).detail()
+            ));
+        }
+    }
+
+    let changes = ["sessions", "refresh_tokens", "device_credentials"]
+        .into_iter()
+        .map(|field| FieldChange::new(field, Some("all"), None))
+        .collect();
+    let error = (!failures.is_empty()).then(|| failures.join("; "));
+    state
+        .audit
+        .record(
+            operator,
+            tenant,
+            Action::RevokeUserCredentials,
+            id,
+            changes,
+            error,
+        )
+        .await?;
+
+    Ok(failures)
+}
+
+pub async fn revoke_all(
+    State(state): State<AppState>,
+    RequireSupport(operator): RequireSupport,
+    tenant: Tenant,
+    Path(UserPath { id }): Path<UserPath>,
+) -> AppResult<Response> {
+    let failures = revoke_everything(&state, &operator, &tenant, &id).await?;
+    let error = (!failures.is_empty()).then(|| {
+        AppError::Auth0(format!(
+            "Some credentials were not revoked: {}",
+            failures.join("; ")
+        ))
+    });
+    sessions_card(operator, tenant, id, error).await
+}
+
+/// Compromise response: blocks the user, then revokes everything that
+/// would keep an attacker signed in. Blocking comes first so nothing new
+/// can be issued while the rest is revoked.
+pub async fn block_and_revoke(
+    State(state): State<AppState>,
+    RequireAdmin(operator): RequireAdmin,
+    tenant: Tenant,
+    Path(UserPath { id }): Path<UserPath>,
+) -> AppResult<Response> {
+    let user = fetch_user(&tenant, &id).await.map_err(AppError::from)?;
+    let mut problems = Vec::new();
+
+    if !user.blocked.unwrap_or(false) {
+        let request = UpdateUserRequest {
+            blocked: Some(true),
+            ..Default::default()
+        };
+        let changes = vec![FieldChange::new("blocked", Some("false"), Some("true"))];
+        let user_id = UserId::new(&id);
+        let result = tenant
+            .client
+            .send(|c| async move { c.users().update(user_id, request).await })
+            .await;
+        let error = result.as_ref().err().map(|e| e.to_string());
+        // Carry on revoking even if the block could not be audited.
+        if let Err(e) = state
+            .audit
+            .record(&operator, &tenant, Action::BlockUser, &id, changes, error)
+            .await
+        {
+            problems.push(format!("{}.", e.detail()));
+        }
+        if let Err(e) = result {
+            tracing::error!(error = ?e, %id, "failed to block user");
+            problems.push(format!(
+                "Failed to block user: {}.",
+                AppError::from(e).detail()
+            ));
+        }
+    }
+
+    let failures = match revoke_everything(&state, &operator, &tenant, &id).await {
+        Ok(failures) => failures,
+        Err(e) => {
+            problems.push(format!("{}.", e.detail()));
+            Vec::new()
+        }
+    };
+    if !failures.is_empty() {
+        problems.push(format!(
+            "Some credentials were not revoked: {}.",
+            failures.join("; ")
+        ));
+    }
+
+    if problems.is_empty() {
+        return Ok(Redirect::to(&tenant.path(&format!("/users/{}", id))).into_response());
+    }
+    let mut errors = FormErrors::new();
+    errors.add_base(&problems.join(" "));
+    let user = fetch_user(&tenant, &id).await.unwrap_or(user);
+    render(DetailTemplate {
+        operator,
+        tenant,
+        user,
+        errors,
+    })
+}
+
+/// Auth0's default ticket lifetime, five days.
+const DEFAULT_TICKET_TTL: u32 = 432000;
+
+#[derive(Clone, Deserialize, Validate)]
+pub struct TicketForm {
+    #[serde(default, deserialize_with = "empty_as_none")]
+    #[validate(range(
+        min = 60,
+        max = 2592000,
+        message = "Must be between one minute and 30 days"
+    ))]
+    ttl_sec: Option<u32>,
+    /// Where the user lands after using the ticket.
+    #[serde(default, deserialize_with = "empty_as_none")]
+    #[validate(url(message = "Must be a valid URL"))]
+    result_url: Option<String>,
+    mark_email_as_verified: Option<String>,
+}
+
+impl Default for TicketForm {
+    fn default() -> Self {
+        Self {
+            ttl_sec: Some(DEFAULT_TICKET_TTL),
+            result_url: None,
+            mark_email_as_verified: None,
+        }
+    }
+}
+
+async fn tickets_card(
+    operator: Operator,
+    tenant: Tenant,
+    id: String,
+    form: TicketForm,
+    ticket: Option<(String, String)>,
+    notice: Option<String>,
+    errors: FormErrors,
+) -> AppResult<Response> {
+    let email_verified = match fetch_user(&tenant, &id).await {
+        Ok(user) => user.email_verified.unwrap_or(false),
+        Err(e) => {
+            tracing::error!(error = ?e, %id, "failed to get user");
+            return render(ErrorBannerTemplate { error: e.into() });
+        }
+    };
+
+    let mut response = render(TicketsTemplate {
+        operator,
+        tenant,
+        user_id: id,
+        email_verified,
+        form,
+        ticket,
+        notice,
+        errors,
+    })?;
+    // A ticket URL lets whoever holds it reset the password.
+    response.headers_mut().insert(
+        header::CACHE_CONTROL,
+        header::HeaderValue::from_static("no-store"),
+    );
+    Ok(response)
+}
+
+pub async fn get_tickets(
+    RequireViewer(operator): RequireViewer,
+    tenant: Tenant,
+    Path(UserPath { id }): Path<UserPath>,
+) -> AppResult<Response> {
+    tickets_card(
+        operator,
+        tenant,
+        id,
+        TicketForm::default(),
+        None,
+        None,
+        FormErrors::new(),
+    )
+    .await
+}
+
+/// Which ticket a form submission asks for.
+#[derive(Clone, Copy)]
+enum TicketKind {
+    PasswordChange,
+    EmailVerification,
+}
+
+/// Creates a password-change or email-verification ticket and shows its
+/// URL for the operator to pass on. The URL is a credential: it is not
+/// logged or audited.
+async fn create_ticket(
+    state: AppState,
+    operator: Operator,
+    tenant: Tenant,
+    id: String,
+    form: TicketForm,
+    kind: TicketKind,
+) -> AppResult<Response> {
+    if let Err(e) = form.validate() {
+        let errors = FormErrors::from(e);
+        return tickets_card(operator, tenant, id, form, None, None, errors).await;
+    }
+
+    let ttl = form.ttl_sec.map(|ttl| ttl.to_string());
+    let mut changes = vec![
+        FieldChange::new("ttl_sec", None, ttl.as_deref()),
+        FieldChange::new("result_url", None, form.result_url.as_deref()),
+    ];
+    let (action, label, result) = match kind {
+        TicketKind::PasswordChange => {
+            let mark_verified = form.mark_email_as_verified.is_some();
+            changes.push(FieldChange::new(
+                "mark_email_as_verified",
+                None,
+                Some(&mark_verified.to_string()),
+            ));
+            let request = PasswordChangeTicketRequest {
+                user_id: Some(id.clone()),
+                result_url: form.result_url.clone(),
+                ttl_sec: form.ttl_sec,
+                mark_email_as_verified: Some(mark_verified),
+                ..Default::default()
+            };
+            let result = tenant
+                .client
+                .send(|c| async move { c.tickets().password_change(request).await })
+                .await;
+            (Action::CreatePasswordChangeTicket, "Password reset", result)
+        }
+        TicketKind::EmailVerification => {
+            let request = EmailVerificationTicketRequest {
+                user_id: id.clone(),
+                result_url: form.result_url.clone(),
+                ttl_sec: form.ttl_sec,
+                ..Default::default()
+            };
+            let result = tenant
+                .client
+                .send(|c| async move { c.tickets().email_verification(request).await })
+                .await;
+            (
+                Action::CreateEmailVerificationTicket,
+                "Email verification",
+                result,
+            )
+        }
+    };
+    changes.push(FieldChange::redacted("ticket"));
+    let error = result.as_ref().err().map(|e| e.to_string());
+    state
+        .audit
+        .record(&operator, &tenant, action, &id, changes, error)
+        .await?;
+
+    match result {
+        Ok(response) => {
+            let ticket = Some((label.to_string(), response.ticket));
+            let form = TicketForm::default();
+            tickets_card(operator, tenant, id, form, ticket, None, FormErrors::new()).await
+        }
+        Err(e) => {
+            tracing::error!(error = %e, %id, "failed to create {} ticket", label);
+            let mut errors = FormErrors::new();
+            errors.add_base(&format!(
+                "Failed to create ticket: {}",
+                AppError::from(e).detail()
+            ));
+            tickets_card(operator, tenant, id, form, None, None, errors).await
+        }
+    }
+}
+
+pub async fn create_password_ticket(
+    State(state): State<AppState>,
+    RequireSupport(operator): RequireSupport,
+    tenant: Tenant,
+    Path(UserPath { id }): Path<UserPath>,
+    Form(form): Form<TicketForm>,
+) -> AppResult<Response> {
+    create_ticket(
+        state,
+        operator,
+        tenant,
+        id,
+        form,
+        TicketKind::PasswordChange,
+    )
+    .await
+}
+
+pub async fn create_verification_ticket(
+    State(state): State<AppState>,
+    RequireSupport(operator): RequireSupport,
+    tenant: Tenant,
+    Path(UserPath { id }): Path<UserPath>,
+    Form(form): Form<TicketForm>,
+) -> AppResult<Response> {
+    create_ticket(
+        state,
+        operator,
+        tenant,
+        id,
+        form,
+        TicketKind::EmailVerification,
+    )
+    .await
+}
+
+/// Queues Auth0's own verification email to the user's address.
+pub async fn send_verification_email(
+    State(state): State<AppState>,
+    RequireSupport(operator): RequireSupport,
+    tenant: Tenant,
+    Path(UserPath { id }): Path<UserPath>,
+) -> AppResult<Response> {
+    let request = VerificationEmailJobRequest {
+        user_id: id.clone(),
+        ..Default::default()
+    };
+    let result = tenant
+        .client
+        .send(|c| async move { c.jobs().send_verification_email(request).await })
+        .await;
+    let error = result.as_ref().err().map(|e| e.to_string());
+    state
+        .audit
+        .record(
+            &operator,
+            &tenant,
+            Action::SendVerificationEmail,
+            &id,
+            Vec::new(),
+            error,
+        )
+        .await?;
+
+    let form = TicketForm::default();
+    match result {
+        Ok(job) => {
+            let notice = Some(format!("Verification email queued (job {}).", job.id));
+            tickets_card(operator, tenant, id, form, None, notice, FormErrors::new()).await
+        }
+        Err(e) => {
+            tracing::error!(error = ?e, %id, "failed to send verification email");
+            let mut errors = FormErrors::new();
+            errors.add_base(&format!(
+                "Failed to send verification email: {}",
+                AppError::from(e).detail()
+            ));
+            tickets_card(operator, tenant, id, form, None, None, errors).await
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::{Method, StatusCode};
+    use serde_json::json;
+
+    use super::*;
+    use crate::test_support;
+
+    fn object(value: Value) -> Map<String, Value> {
+        match value {
+            Value::Object(map) => map,
+            _ => panic!("not an object"),
+        }
+    }
+
+    #[test]
+    fn parse_metadata_wants_an_object() {
+        let mut errors = ValidationErrors::new();
+        assert_eq!(
+            parse_metadata("user_metadata", "  ", &mut errors),
+            Some(Map::new())
+        );
+        assert_eq!(
+            parse_metadata("user_metadata", r#"{"a": 1}"#, &mut errors),
+            Some(object(json!({ "a": 1 })))
+        );
+        assert!(errors.is_empty());
+
+        assert_eq!(parse_metadata("app_metadata", "[1]", &mut errors), None);
+        assert_eq!(parse_metadata("user_metadata", "{", &mut errors), None);
+        let fields = errors.field_errors();
+        assert_eq!(
+            fields["app_metadata"][0].message.as_deref(),
+            Some("Must be a JSON object")
+        );
+        assert!(fields["user_metadata"][0]
+            .message
+            .as_deref()
+            .unwrap()
+            .starts_with("Invalid JSON"));
+    }
+
+    #[test]
+    fn merge_keeps_other_keys_and_null_removes() {
+        let current = object(json!({ "plan": "free", "beta": true, "theme": "dark" }));
+        let input = object(json!({ "plan": "pro", "beta": null }));
+
+        let merged = combine_metadata(&current, input.clone(), MetadataMode::Merge);
+        assert_eq!(merged, object(json!({ "plan": "pro", "theme": "dark" })));
+
+        let replaced = combine_metadata(&current, input, MetadataMode::Replace);
+        assert_eq!(replaced, object(json!({ "plan": "pro" })));
+    }
+
+    #[test]
+    fn patch_sends_changed_keys_whole_and_removed_keys_as_null() {
+        let current = object(json!({ "flags": { "a": 1, "b": 2 }, "old": 1, "same": "x" }));
+        let result = object(json!({ "flags": { "a": 1, "b": 3 }, "same": "x", "new": [] }));
+
+        assert_eq!(
+            metadata_patch(&current, &result),
+            Some(json!({ "flags": { "a": 1, "b": 3 }, "old": null, "new": [] }))
+        );
+        assert_eq!(metadata_patch(&current, &current), None);
+    }
+
+    #[test]
+    fn diff_lists_changes_by_dotted_path() {
+        let before = object(json!({ "flags": { "a": 1, "b": 2 }, "old": "x", "empty": {} }));
+        let after = object(json!({ "flags": { "a": 1, "b": 3 }, "new": true, "empty": {} }));
+
+        let changes: Vec<_> = metadata_diff("app_metadata", &before, &after)
+            .into_iter()
+            .map(|c| (c.field, c.before, c.after))
+            .collect();
+        assert_eq!(
+            changes,
+            vec![
+                (
+                    "app_metadata.flags.b".to_string(),
+                    Some("2".to_string()),
+                    Some("3".to_string())
+                ),
+                (
+                    "app_metadata.new".to_string(),
+                    None,
+                    Some("true".to_string())
+                ),
+                (
+                    "app_metadata.old".to_string(),
+                    Some("\"x\"".to_string()),
+                    None
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn fingerprint_changes_with_either_metadata() {
+        let app = Some(json!({ "plan": "pro" }));
+        let user = Some(json!({ "theme": "dark" }));
+        let fingerprint = metadata_fingerprint(&app, &user);
+
+        assert_eq!(
+            fingerprint,
+            metadata_fingerprint(
+                &Some(json!({ "plan": "pro" })),
+                &Some(json!({ "theme": "dark" }))
+            )
+        );
+        assert_ne!(
+            fingerprint,
+            metadata_fingerprint(&Some(json!({ "plan": "free" })), &user)
+        );
+        assert_ne!(fingerprint, metadata_fingerprint(&app, &None));
+        // The same key moving between the two is still a change.
+        assert_ne!(
+            metadata_fingerprint(&Some(json!({ "a": 1 })), &None),
+            metadata_fingerprint(&None, &Some(json!({ "a": 1 })))
+        );
+    }
+
+    fn roles(ids: &[&str]) -> Vec<Auth0Role> {
+        ids.iter()
+            .map(|id| serde_json::from_value(json!({ "id": id, "name": id })).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn picker_offers_only_roles_the_user_lacks() {
+        let all = roles(&["rol_admin", "rol_billing", "rol_support"]);
+        let assigned = roles(&["rol_billing", "rol_gone"]);
+
+        let available: Vec<String> = unassigned(&all, &assigned)
+            .into_iter()
+            .map(|role| role.id)
+            .collect();
+        assert_eq!(available, vec!["rol_admin", "rol_support"]);
+        assert!(unassigned(&all, &all).is_empty());
+    }
+
+    #[tokio::test]
+    async fn only_admins_assign_or_remove_user_roles() {
+        let cases = [
+            (Method::POST, "/t/prod/users/u1/roles", "role_id=rol_admin"),
+            (
+                Method::DELETE,
+                "/t/prod/users/u1/roles?role_id=rol_admin",
+                "",
+            ),
+        ];
+        for (method, uri, form) in cases {
+            let response =
+                test_support::send_as(Some(Role::Support), method.clone(), uri, form).await;
+            assert_eq!(
+                response.status(),
+                StatusCode::FORBIDDEN,
+                "{} {}",
+                method,
+                uri
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn viewers_cannot_change_mfa() {
+        let cases = [
+            (Method::DELETE, "/t/prod/users/u1/mfa"),
+            (Method::DELETE, "/t/prod/users/u1/mfa/am_1"),
+            (Method::POST, "/t/prod/users/u1/mfa/recovery-code"),
+        ];
+        for (method, uri) in cases {
+            let response = test_support::send_as(Some(Role::Viewer), method.clone(), uri, "").await;
+            assert_eq!(
+                response.status(),
+                StatusCode::FORBIDDEN,
+                "{} {}",
+                method,
+                uri
+            );
+        }
+    }
+
+    /// Auth0 is unreachable from tests, so the attempt fails; it must
+    /// still be audited without the code and the page never cached.
+    #[tokio::test]
+    async fn recovery_code_attempts_are_audited_without_the_code() {
+        let state = test_support::state().await;
+        let tenant = state.tenants.default_tenant().clone();
+
+        let response = regenerate_recovery_code(
+            State(state.clone()),
+            RequireSupport(test_support::operator(Role::Support)),
+            tenant,
+            Path(UserPath {
+                id: "u1".to_string(),
+            }),
+        )
+        .await
+        .unwrap();
+        assert_eq!(response.headers()[header::CACHE_CONTROL], "no-store");
+
+        let entries = state.audit.entries().await.unwrap().entries;
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].action, Action::RegenerateUserRecoveryCode);
+        assert_eq!(entries[0].changes[0].after.as_deref(), Some("[redacted]"));
+        assert!(entries[0].error.is_some());
+    }
+
+    #[tokio::test]
+    async fn viewers_cannot_revoke_and_only_admins_block_and_revoke() {
+        let cases = [
+            (Role::Viewer, Method::DELETE, "/t/prod/users/u1/sessions"),
+            (
+                Role::Viewer,
+                Method::DELETE,
+                "/t/prod/users/u1/sessions/s_1",
+            ),
+            (
+                Role::Viewer,
+                Method::DELETE,
+                "/t/prod/users/u1/refresh-tokens/rt_1",
+            ),
+            (
+                Role::Viewer,
+                Method::DELETE,
+                "/t/prod/users/u1/device-credentials/dc_1",
+            ),
+            (
+                Role::Support,
+                Method::POST,
+                "/t/prod/users/u1/block-and-revoke",
+            ),
+        ];
+        for (role, method, uri) in cases {
+            let response = test_support::send_as(Some(role), method.clone(), uri, "").await;
+            assert_eq!(
+                response.status(),
+                StatusCode::FORBIDDEN,
+                "{:?} {} {}",
+                role,
+                method,
+                uri
+            );
+        }
+    }
+
+    /// Every step fails against an unreachable Auth0; each failure is
+    /// reported and the attempt is still audited once.
+    #[tokio::test]
+    async fn revoking_everything_carries_on_past_failures() {
+        let state = test_support::state().await;
+        let tenant = state.tenants.default_tenant().clone();
+        let operator = test_support::operator(Role::Support);
+
+        let failures = revoke_everything(&state, &operator, &tenant, "u1")
+            .await
+            .unwrap();
+        assert_eq!(failures.len(), 3, "{:?}", failures);
+        assert!(failures[0].starts_with("sessions: "));
+        assert!(failures[1].starts_with("refresh tokens: "));
+        assert!(failures[2].starts_with("device credentials: "));
+
+        let entries = state.audit.entries().await.unwrap().entries;
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].action, Action::RevokeUserCredentials);
+        assert_eq!(
+            entries[0].error.as_deref(),
+            Some(failures.join("; ").as_str())
+        );
+    }
+
+    #[test]
+    fn ticket_lifetime_and_result_url_are_checked() {
+        assert!(TicketForm::default().validate().is_ok());
+
+        let short = TicketForm {
+            ttl_sec: Some(30),
+            ..TicketForm::default()
+        };
+        assert!(short
+            .validate()
+            .unwrap_err()
+            .field_errors()
+            .contains_key("ttl_sec"));
+
+        let bad_url = TicketForm {
+            result_url: Some("not a url".to_string()),
+            ..TicketForm::default()
+        };
+        assert!(bad_url
+            .validate()
+            .unwrap_err()
+            .field_errors()
+            .contains_key("result_url"));
+    }
+
+    #[tokio::test]
+    async fn invalid_tickets_are_not_requested_or_audited() {
+        let state = test_support::state().await;
+        let tenant = state.tenants.default_tenant().clone();
+        let form = TicketForm {
+            ttl_sec: Some(30),
+            ..TicketForm::default()
+        };
+
+        create_ticket(
+            state.clone(),
+            test_support::operator(Role::Support),
+            tenant,
+            "u1".to_string(),
+            form,
+            TicketKind::PasswordChange,
+        )
+        .await
+        .unwrap();
+        assert!(state.audit.entries().await.unwrap().entries.is_empty());
+    }
+
+    #[tokio::test]
+    async fn viewers_cannot_issue_tickets_or_send_verification_emails() {
+        for uri in [
+            "/t/prod/users/u1/tickets/password-change",
+            "/t/prod/users/u1/tickets/email-verification",
+            "/t/prod/users/u1/verification-email",
+        ] {
+            let response = test_support::send_as(Some(Role::Viewer), Method::POST, uri, "").await;
+            assert_eq!(response.status(), StatusCode::FORBIDDEN, "{}", uri);
+        }
     }
 }
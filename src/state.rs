@@ -1,25 +1,44 @@
 use std::sync::Arc;
 
-use auth0_mgmt_api::ManagementClient;
 use axum::{
-    routing::{get, post},
+    extract::FromRef,
+    middleware,
+    routing::{delete, get, patch, post, put},
     Router,
 };
+use axum_extra::extract::cookie::Key;
 use tower_http::trace::TraceLayer;
 
-use crate::routes::{applications, connections, logs, root, users};
+use crate::audit::AuditLog;
+use crate::auth::{require_operator, Auth};
+use crate::routes::{
+    apis, applications, audit, auth, connections, logs, organizations, roles, root, status, users,
+};
+use crate::tenant::Tenants;
 
 #[derive(Clone)]
 pub struct AppState {
-    pub client: Arc<ManagementClient>,
+    pub tenants: Arc<Tenants>,
+    pub auth: Arc<Auth>,
+    pub audit: Arc<AuditLog>,
+}
+
+impl FromRef<AppState> for Key {
+    fn from_ref(state: &AppState) -> Self {
+        state.auth.key.clone()
+    }
 }
 
-pub fn build_app(client: ManagementClient) -> Router {
+pub fn build_app(tenants: Tenants, auth: Auth, audit: AuditLog) -> Router {
     let state = AppState {
-        client: Arc::new(client),
+        tenants: Arc::new(tenants),
+        auth: Arc::new(auth),
+        audit: Arc::new(audit),
     };
 
-    Router::new()
+    // Everything that talks to Auth0 lives under `/t/{tenant}` so the
+    // tenant is always explicit in the URL.
+    let tenant = Router::new()
         .route("/", get(root::index))
         .route("/users", get(users::list).post(users::create))
         .route(
@@ -27,10 +46,223 @@ pub fn build_app(client: ManagementClient) -> Router {
             get(users::get).patch(users::update).delete(users::delete),
         )
         .route("/users/{id}/logs", get(users::get_logs))
+        .route(
+            "/users/{id}/organizations",
+            get(organizations::user_organizations),
+        )
+        .route(
+            "/users/{id}/metadata",
+            get(users::get_metadata).put(users::update_metadata),
+        )
+        .route("/users/{id}/metadata/preview", post(users::preview_metadata))
+        .route(
+            "/users/{id}/roles",
+            get(users::get_roles)
+                .post(users::assign_role)
+                .delete(users::remove_role),
+        )
+        .route(
+            "/users/{id}/mfa",
+            get(users::get_mfa).delete(users::reset_mfa),
+        )
+        .route(
+            "/users/{id}/mfa/{method_id}",
+            delete(users::delete_mfa_method),
+        )
+        .route(
+            "/users/{id}/mfa/recovery-code",
+            post(users::regenerate_recovery_code),
+        )
+        .route(
+            "/users/{id}/sessions",
+            get(users::get_sessions).delete(users::revoke_all),
+        )
+        .route(
+            "/users/{id}/sessions/{credential_id}",
+            delete(users::revoke_session),
+        )
+        .route(
+            "/users/{id}/refresh-tokens/{credential_id}",
+            delete(users::revoke_refresh_token),
+        )
+        .route(
+            "/users/{id}/device-credentials/{credential_id}",
+            delete(users::revoke_device_credential),
+        )
+        .route("/users/{id}/tickets", get(users::get_tickets))
+        .route(
+            "/users/{id}/tickets/password-change",
+            post(users::create_password_ticket),
+        )
+        .route(
+            "/users/{id}/tickets/email-verification",
+            post(users::create_verification_ticket),
+        )
+        .route(
+            "/users/{id}/verification-email",
+            post(users::send_verification_email),
+        )
         .route("/users/{id}/toggle-block", post(users::toggle_block))
-        .route("/connections", get(connections::list))
-        .route("/applications", get(applications::list))
+        .route("/users/{id}/block-and-revoke", post(users::block_and_revoke))
+        .route(
+            "/connections",
+            get(connections::list).post(connections::create),
+        )
+        .route("/connections/matrix", get(connections::matrix))
+        .route(
+            "/connections/{id}",
+            get(connections::get).patch(connections::update),
+        )
+        .route(
+            "/connections/{id}/password-policy",
+            patch(connections::update_password_policy),
+        )
+        .route(
+            "/connections/{id}/clients/{client_id}",
+            put(connections::toggle_client),
+        )
+        .route(
+            "/applications",
+            get(applications::list).post(applications::create),
+        )
+        .route(
+            "/applications/{client_id}",
+            get(applications::get)
+                .patch(applications::update)
+                .delete(applications::delete),
+        )
+        .route(
+            "/applications/{client_id}/rotate-secret",
+            post(applications::rotate_secret),
+        )
+        .route(
+            "/applications/{client_id}/grants",
+            get(applications::get_grants).post(applications::create_grant),
+        )
+        .route(
+            "/applications/{client_id}/grants/{grant_id}",
+            patch(applications::update_grant).delete(applications::delete_grant),
+        )
+        .route("/apis", get(apis::list).post(apis::create))
+        .route(
+            "/apis/{id}",
+            get(apis::get).patch(apis::update).delete(apis::delete),
+        )
+        .route(
+            "/apis/{id}/scopes",
+            post(apis::add_scope).delete(apis::remove_scope),
+        )
+        .route("/apis/{id}/usage", get(apis::usage))
+        .route("/roles", get(roles::list).post(roles::create))
+        .route(
+            "/roles/{id}",
+            get(roles::get).patch(roles::update).delete(roles::delete),
+        )
+        .route(
+            "/roles/{id}/permissions",
+            post(roles::add_permissions).delete(roles::remove_permission),
+        )
+        .route(
+            "/organizations",
+            get(organizations::list).post(organizations::create),
+        )
+        .route(
+            "/organizations/{id}",
+            get(organizations::get).patch(organizations::update),
+        )
+        .route(
+            "/organizations/{id}/connections",
+            post(organizations::add_connection),
+        )
+        .route(
+            "/organizations/{id}/connections/{connection_id}",
+            delete(organizations::remove_connection),
+        )
+        .route(
+            "/organizations/{id}/members",
+            get(organizations::get_members).post(organizations::add_member),
+        )
+        .route(
+            "/organizations/{id}/members/{user_id}",
+            delete(organizations::remove_member),
+        )
+        .route(
+            "/organizations/{id}/members/{user_id}/roles",
+            post(organizations::assign_member_role).delete(organizations::remove_member_role),
+        )
+        .route(
+            "/organizations/{id}/invitations",
+            post(organizations::create_invitation),
+        )
+        .route(
+            "/organizations/{id}/invitations/{invitation_id}",
+            delete(organizations::delete_invitation),
+        )
         .route("/logs", get(logs::list))
+        .route("/logs/stream", get(logs::stream))
+        .route("/audit", get(audit::list))
+        .route("/audit/export", get(audit::export))
+        .route("/status", get(status::index))
+        .route("/cache/refresh", post(status::refresh_cache));
+
+    let protected = Router::new()
+        .route("/", get(root::default_tenant))
+        .nest("/t/{tenant}", tenant)
+        .route_layer(middleware::from_fn_with_state(
+            state.clone(),
+            require_operator,
+        ));
+
+    Router::new()
+        .merge(protected)
+        .route("/auth/login", get(auth::login))
+        .route("/auth/callback", get(auth::callback))
+        .route("/auth/logout", post(auth::logout))
         .layer(TraceLayer::new_for_http())
         .with_state(state)
 }
+
+#[cfg(test)]
+mod tests {
+    use axum::http::{Method, StatusCode};
+
+    use crate::auth::Role;
+    use crate::test_support;
+
+    async fn status_as(role: Option<Role>, method: Method, uri: &str, form: &str) -> StatusCode {
+        test_support::send_as(role, method, uri, form).await.status()
+    }
+
+    #[tokio::test]
+    async fn support_cannot_delete_block_or_set_passwords() {
+        let cases = [
+            (Method::DELETE, "/t/prod/users/u1", ""),
+            (Method::POST, "/t/prod/users/u1/toggle-block", ""),
+            (Method::PATCH, "/t/prod/users/u1", "password=hunter2hunter2"),
+            (Method::POST, "/t/prod/roles", "name=auditor"),
+        ];
+        for (method, uri, form) in cases {
+            let status = status_as(Some(Role::Support), method.clone(), uri, form).await;
+            assert_eq!(status, StatusCode::FORBIDDEN, "{} {}", method, uri);
+        }
+    }
+
+    #[tokio::test]
+    async fn viewer_cannot_create_edit_users_or_refresh_caches() {
+        let cases = [
+            (Method::POST, "/t/prod/users", "email=ada%40example.com"),
+            (Method::PATCH, "/t/prod/users/u1", "given_name=Ada"),
+            (Method::POST, "/t/prod/cache/refresh", ""),
+        ];
+        for (method, uri, form) in cases {
+            let status = status_as(Some(Role::Viewer), method.clone(), uri, form).await;
+            assert_eq!(status, StatusCode::FORBIDDEN, "{} {}", method, uri);
+        }
+    }
+
+    #[tokio::test]
+    async fn anonymous_requests_are_sent_to_login() {
+        let status = status_as(None, Method::GET, "/t/prod/users", "").await;
+        assert_eq!(status, StatusCode::SEE_OTHER);
+    }
+}
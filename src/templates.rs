@@ -1,7 +1,7 @@
 use askama::Template;
 use axum::response::{Html, IntoResponse, Response};
 
-use crate::errors::AppResult;
+use crate::errors::{AppError, AppResult};
 
 pub fn render<T: Template>(template: T) -> AppResult<Response> {
     Ok(Html(template.render()?).into_response())
@@ -29,3 +29,11 @@ pub struct ToastTemplate {
     pub title: String,
     pub message: String,
 }
+
+/// The error banner on its own, for fragments that load into a page
+/// already rendered (e.g. the recent logs card on the user page).
+#[derive(Template)]
+#[template(path = "error_banner.html")]
+pub struct ErrorBannerTemplate {
+    pub error: AppError,
+}
@@ -0,0 +1,204 @@
+use std::sync::Arc;
+
+use auth0_mgmt_api::ManagementClient;
+use axum::extract::{FromRequestParts, Path};
+use axum::http::request::Parts;
+use serde::Deserialize;
+
+use crate::cache::Cache;
+use crate::client::Auth0Client;
+use crate::config::Config;
+use crate::errors::AppError;
+use crate::log_tail::LogTail;
+use crate::state::AppState;
+
+const DEFAULT_COLOUR: &str = "#6c757d";
+
+/// Everything scoped to one Auth0 tenant. Handlers get it from the
+/// `{tenant}` segment of `/t/{tenant}/...`; cloning is cheap.
+#[derive(Clone)]
+pub struct Tenant {
+    pub name: String,
+    pub label: String,
+    pub colour: String,
+    pub domain: String,
+    /// Our own Management API client, which must not be deleted from here.
+    pub client_id: String,
+    /// `/t/{name}`, the prefix for every link inside this tenant.
+    pub base_path: String,
+    pub client: Arc<Auth0Client>,
+    pub cache: Arc<Cache>,
+    pub log_tail: Arc<LogTail>,
+    /// All configured tenants, for the switcher in the nav bar.
+    pub switcher: Arc<Vec<TenantLink>>,
+}
+
+pub struct TenantLink {
+    pub name: String,
+    pub label: String,
+    pub colour: String,
+}
+
+impl Tenant {
+    /// Absolute path to a page inside this tenant, e.g. `path("/users")`.
+    pub fn path(&self, path: &str) -> String {
+        format!("{}{}", self.base_path, path)
+    }
+}
+
+/// The configured tenants, in the order they appear in the config. The
+/// first one is where `/` sends operators.
+pub struct Tenants {
+    tenants: Vec<Tenant>,
+}
+
+impl Tenants {
+    pub fn build(config: &Config) -> Result<Self, auth0_mgmt_api::Error> {
+        let switcher: Arc<Vec<TenantLink>> = Arc::new(
+            config
+                .tenants
+                .iter()
+                .map(|t| TenantLink {
+                    name: t.name.clone(),
+                    label: t.label.clone().unwrap_or_else(|| t.name.clone()),
+                    colour: t
+                        .colour
+                        .clone()
+                        .unwrap_or_else(|| DEFAULT_COLOUR.to_string()),
+                })
+                .collect(),
+        );
+
+        let mut tenants = Vec::with_capacity(config.tenants.len());
+        for (tenant, link) in config.tenants.iter().zip(switcher.iter()) {
+            let client = ManagementClient::builder()
+                .domain(&tenant.domain)
+                .client_id(&tenant.client_id)
+                .client_secret(&tenant.client_secret)
+                .build()?;
+            let client = Arc::new(Auth0Client::new(
+                client,
+                tenant,
+                config.auth0_max_concurrency,
+                config.auth0_max_retries,
+            ));
+
+            tenants.push(Tenant {
+                name: tenant.name.clone(),
+                label: link.label.clone(),
+                colour: link.colour.clone(),
+                domain: tenant.domain.clone(),
+                client_id: tenant.client_id.clone(),
+                base_path: format!("/t/{}", tenant.name),
+                log_tail: Arc::new(LogTail::new(client.clone())),
+                cache: Arc::new(Cache::new(config.cache_ttl)),
+                client,
+                switcher: switcher.clone(),
+            });
+        }
+
+        Ok(Self { tenants })
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Tenant> {
+        self.tenants.iter().find(|t| t.name == name)
+    }
+
+    pub fn default_tenant(&self) -> &Tenant {
+        &self.tenants[0]
+    }
+}
+
+#[derive(Deserialize)]
+struct TenantPath {
+    tenant: String,
+}
+
+impl FromRequestParts<AppState> for Tenant {
+    type Rejection = AppError;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &AppState,
+    ) -> Result<Self, Self::Rejection> {
+        // Other path parameters (`{id}` etc.) are ignored here.
+        let Path(TenantPath { tenant }) = Path::from_request_parts(parts, state)
+            .await
+            .map_err(|_| AppError::NotFound)?;
+        state
+            .tenants
+            .get(&tenant)
+            .cloned()
+            .ok_or(AppError::NotFound)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{body::Body, http::Request, http::StatusCode, routing::get, Router};
+    use tower::ServiceExt;
+
+    use super::*;
+    use crate::config::TenantConfig;
+    use crate::test_support;
+
+    #[test]
+    fn build_keeps_config_order_and_fills_defaults() {
+        let mut config = test_support::config();
+        config.tenants[1] = TenantConfig {
+            label: Some("Staging".to_string()),
+            colour: Some("#ff0000".to_string()),
+            ..test_support::tenant_config("staging")
+        };
+        let tenants = Tenants::build(&config).unwrap();
+
+        let prod = tenants.default_tenant();
+        assert_eq!(prod.name, "prod");
+        assert_eq!(prod.label, "prod");
+        assert_eq!(prod.colour, DEFAULT_COLOUR);
+        assert_eq!(prod.path("/users"), "/t/prod/users");
+
+        let staging = tenants.get("staging").unwrap();
+        assert_eq!(staging.label, "Staging");
+        assert_eq!(staging.colour, "#ff0000");
+        assert_eq!(staging.domain, "staging.auth0.invalid");
+        assert!(tenants.get("dev").is_none());
+
+        let links: Vec<_> = staging.switcher.iter().map(|t| t.label.as_str()).collect();
+        assert_eq!(links, ["prod", "Staging"]);
+    }
+
+    async fn tenant_for(uri: &str) -> Result<String, StatusCode> {
+        let app = Router::new()
+            .route(
+                "/t/{tenant}/users/{id}",
+                get(|tenant: Tenant| async move { tenant.name }),
+            )
+            .with_state(test_support::state().await);
+        let response = app
+            .oneshot(Request::get(uri).body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        if response.status() != StatusCode::OK {
+            return Err(response.status());
+        }
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        Ok(String::from_utf8(body.to_vec()).unwrap())
+    }
+
+    #[tokio::test]
+    async fn extractor_picks_the_tenant_from_the_path() {
+        assert_eq!(tenant_for("/t/prod/users/u1").await.unwrap(), "prod");
+        assert_eq!(tenant_for("/t/staging/users/u1").await.unwrap(), "staging");
+    }
+
+    #[tokio::test]
+    async fn unknown_tenant_is_not_found() {
+        assert_eq!(
+            tenant_for("/t/dev/users/u1").await,
+            Err(StatusCode::NOT_FOUND)
+        );
+    }
+}
@@ -0,0 +1,167 @@
+//! Builders for handler tests: configuration, operators and app state that
+//! never reach Auth0 or the OIDC issuer.
+
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use axum::{
+    body::Body,
+    http::{header, Method, Request},
+    response::{IntoResponse, Response},
+    Router,
+};
+use axum_extra::extract::cookie::{Cookie, Key, SignedCookieJar};
+use openidconnect::{
+    core::{
+        CoreClient, CoreJwsSigningAlgorithm, CoreProviderMetadata, CoreResponseType,
+        CoreSubjectIdentifierType,
+    },
+    reqwest, AuthUrl, ClientId, ClientSecret, EmptyAdditionalProviderMetadata, IssuerUrl,
+    JsonWebKeySetUrl, RedirectUrl, ResponseTypes,
+};
+use tower::ServiceExt;
+
+use crate::audit::AuditLog;
+use crate::auth::{Auth, Operator, OperatorRoles, Role, SESSION_COOKIE};
+use crate::config::{Config, TenantConfig};
+use crate::state::{build_app, AppState};
+use crate::tenant::Tenants;
+
+/// The Management API client every test tenant is configured with.
+pub const OWN_CLIENT_ID: &str = "console-client";
+
+pub fn tenant_config(name: &str) -> TenantConfig {
+    TenantConfig {
+        name: name.to_string(),
+        label: None,
+        domain: format!("{}.auth0.invalid", name),
+        client_id: OWN_CLIENT_ID.to_string(),
+        client_secret: "secret".to_string(),
+        colour: None,
+        source: "AUTH0_CLIENT_ID and AUTH0_CLIENT_SECRET".to_string(),
+    }
+}
+
+pub fn config() -> Config {
+    Config {
+        tenants: vec![tenant_config("prod"), tenant_config("staging")],
+        auth0_max_concurrency: 1,
+        auth0_max_retries: 0,
+        bind_addr: "127.0.0.1:0".parse().unwrap(),
+        oidc_issuer: "https://issuer.invalid".to_string(),
+        oidc_client_id: "console".to_string(),
+        oidc_client_secret: "console-secret".to_string(),
+        oidc_redirect_url: "http://localhost:3000/auth/callback".to_string(),
+        session_secret: "s".repeat(64),
+        session_ttl: Duration::from_secs(60),
+        oidc_role_claim: None,
+        operators_file: None,
+        audit_log_path: temp_path("audit"),
+        cache_ttl: Duration::from_secs(60),
+    }
+}
+
+/// A unique path under the system temp dir.
+pub fn temp_path(name: &str) -> PathBuf {
+    static NEXT: AtomicU64 = AtomicU64::new(0);
+    std::env::temp_dir().join(format!(
+        "auth0-frontend-{}-{}-{}",
+        std::process::id(),
+        NEXT.fetch_add(1, Ordering::Relaxed),
+        name
+    ))
+}
+
+/// Auth without discovery; fine for anything but signing in.
+pub fn auth(config: &Config) -> Auth {
+    let issuer = &config.oidc_issuer;
+    let metadata = CoreProviderMetadata::new(
+        IssuerUrl::new(issuer.clone()).unwrap(),
+        AuthUrl::new(format!("{}/authorize", issuer)).unwrap(),
+        JsonWebKeySetUrl::new(format!("{}/jwks", issuer)).unwrap(),
+        vec![ResponseTypes::new(vec![CoreResponseType::Code])],
+        vec![CoreSubjectIdentifierType::Public],
+        vec![CoreJwsSigningAlgorithm::RsaSsaPkcs1V15Sha256],
+        EmptyAdditionalProviderMetadata {},
+    );
+    let oidc = CoreClient::from_provider_metadata(
+        metadata,
+        ClientId::new(config.oidc_client_id.clone()),
+        Some(ClientSecret::new(config.oidc_client_secret.clone())),
+    )
+    .set_redirect_uri(RedirectUrl::new(config.oidc_redirect_url.clone()).unwrap());
+
+    Auth {
+        oidc,
+        http: reqwest::Client::new(),
+        key: Key::from(config.session_secret.as_bytes()),
+        session_ttl: config.session_ttl,
+        secure_cookies: false,
+        role_claim: None,
+        roles: OperatorRoles::default(),
+    }
+}
+
+pub async fn state() -> AppState {
+    let config = config();
+    AppState {
+        tenants: Arc::new(Tenants::build(&config).unwrap()),
+        auth: Arc::new(auth(&config)),
+        audit: Arc::new(AuditLog::open(config.audit_log_path.clone()).await.unwrap()),
+    }
+}
+
+/// The whole router, wired as in `main` but from [`config`].
+pub async fn app() -> Router {
+    let config = config();
+    build_app(
+        Tenants::build(&config).unwrap(),
+        auth(&config),
+        AuditLog::open(config.audit_log_path.clone()).await.unwrap(),
+    )
+}
+
+/// A `Cookie` header that signs `operator` in to [`app`].
+pub fn session_cookie(operator: &Operator) -> String {
+    let key = Key::from(config().session_secret.as_bytes());
+    let session = serde_json::to_string(operator).unwrap();
+    let response = SignedCookieJar::new(key)
+        .add(Cookie::new(SESSION_COOKIE, session))
+        .into_response();
+    let set_cookie = response.headers()[header::SET_COOKIE].to_str().unwrap();
+    set_cookie.split(';').next().unwrap().to_string()
+}
+
+/// Sends a form request through [`app`], signed in with `role` or, for
+/// `None`, anonymously.
+pub async fn send_as(role: Option<Role>, method: Method, uri: &str, form: &str) -> Response {
+    let mut request = Request::builder()
+        .method(method)
+        .uri(uri)
+        .header(header::CONTENT_TYPE, "application/x-www-form-urlencoded");
+    if let Some(role) = role {
+        request = request.header(header::COOKIE, session_cookie(&operator(role)));
+    }
+    let request = request.body(Body::from(form.to_string())).unwrap();
+    app().await.oneshot(request).await.unwrap()
+}
+
+/// The body of a rendered page or fragment.
+pub async fn body_text(response: Response) -> String {
+    let bytes = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap();
+    String::from_utf8(bytes.to_vec()).unwrap()
+}
+
+pub fn operator(role: Role) -> Operator {
+    Operator {
+        sub: "auth0|operator".to_string(),
+        name: Some("Operator".to_string()),
+        email: Some("operator@example.com".to_string()),
+        role,
+        expires_at: u64::MAX,
+    }
+}